@@ -3,5 +3,12 @@ pub const GENERATION_INPUTS_DEFAULT_OUTPUT_DIR: &str = "generation_inputs";
 /// We use the `BlockchainTests` subdirectory of the `Cancun` folder
 /// as it contains all hardfork variants up to this one.
 pub const MAIN_TEST_DIR: &str = "Cancun/BlockchainTests";
+/// The version of the `ParsedTestManifest` format written by the parser.
+/// Increment this whenever `ParsedTestManifest` (or any type it contains)
+/// changes, so that the runner can detect tests parsed with an older format.
+pub const PARSED_TEST_SCHEMA_VERSION: u32 = 1;
+/// The file in `MAIN_TEST_DIR` of the parsed test directory listing the
+/// SHA-256 of every parsed test file, in the format of `sha256sum`.
+pub const CHECKSUMS_FILE_NAME: &str = "checksums.sha256";
 pub const MATIC_CHAIN_ID: u64 = 137;
 pub const ETHEREUM_CHAIN_ID: u64 = 1;
@@ -1,6 +1,7 @@
 use std::{
     collections::HashMap,
     ops::RangeInclusive,
+    path::PathBuf,
     str::{FromStr, Split},
 };
 
@@ -15,9 +16,36 @@ use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ParsedTestManifest {
+    /// The `PARSED_TEST_SCHEMA_VERSION` of the parser that wrote this
+    /// manifest. Manifests written before versioning was introduced default
+    /// to `0`.
+    #[serde(default)]
+    pub schema_version: u32,
     pub plonky2_variants: Vec<Plonky2ParsedTest>,
 }
 
+/// A parsed test as written to a single line of the NDJSON file output by
+/// `eth_test_parser --output-generation-inputs-jsonl`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ParsedTestNdjsonEntry {
+    /// The path that the parsed test would have been written to, relative to
+    /// the parser output directory. Ends with
+    /// `<GROUP>/<SUB_GROUP>/<TEST_FILE>.cbor`.
+    pub path: PathBuf,
+    pub test: ParsedTestManifest,
+}
+
+/// Only the version tag of a `ParsedTestManifest`.
+///
+/// Deserializing this instead of the full manifest allows checking the
+/// version even if the rest of the manifest no longer matches the current
+/// format.
+#[derive(Debug, Deserialize)]
+pub struct ParsedTestManifestVersion {
+    #[serde(default)]
+    pub schema_version: u32,
+}
+
 pub struct FilteredVariantsOutput {
     pub variants: Vec<TestVariantRunInfo>,
     pub tot_variants_without_filter: usize,
@@ -1,4 +1,6 @@
-use flexi_logger::Logger;
+use flexi_logger::{LogSpecBuilder, LogSpecification, Logger};
+use log::LevelFilter;
+use sha2::{Digest, Sha256};
 
 #[macro_export]
 macro_rules! unwrap_or_continue {
@@ -20,8 +22,30 @@ macro_rules! unwrap_or_return {
     };
 }
 
-pub fn init_env_logger() {
-    let _ = Logger::try_with_env_or_str("plonky2::util::timing=info")
-        .unwrap()
-        .start();
+/// The SHA-256 of `bytes` as a lowercase hex string.
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Our own crates, which `level_override` applies to.
+const WORKSPACE_CRATES: [&str; 3] = ["common", "eth_test_parser", "evm_test_runner"];
+
+/// Initializes logging from `RUST_LOG`. If `level_override` is set, it takes
+/// precedence over `RUST_LOG` for our own crates.
+pub fn init_env_logger(level_override: Option<LevelFilter>) {
+    let spec = LogSpecification::env_or_parse("plonky2::util::timing=info").unwrap();
+
+    let spec = match level_override {
+        Some(level) => {
+            let mut builder = LogSpecBuilder::from_module_filters(spec.module_filters());
+            for krate in WORKSPACE_CRATES {
+                builder.module(krate, level);
+            }
+
+            builder.build()
+        }
+        None => spec,
+    };
+
+    let _ = Logger::with(spec).start();
 }
@@ -1,13 +1,182 @@
-use std::path::PathBuf;
+use std::{num::NonZeroUsize, path::PathBuf};
 
-use clap::Parser;
+use clap::{Args, Parser, Subcommand};
+use ethereum_types::{Address, H256, U256};
+use glob::Pattern;
+
+use crate::config::{DEFAULT_MAX_CONCURRENT_WRITES, DEFAULT_TEST_FIXTURE_SECRET_KEY};
+
+#[derive(Debug, Subcommand)]
+pub(crate) enum Command {
+    /// Write a parsed test (CBOR) with an empty pre-state that runs a single
+    /// transaction built from the given fields, to reproduce an issue without
+    /// the upstream tests. Place it under `<GROUP>/<SUB_GROUP>/` in a parsed
+    /// tests directory to run it with `evm_test_runner`.
+    GenerateTestFixture(TestFixtureArgs),
+}
+
+#[derive(Debug, Args)]
+pub(crate) struct TestFixtureArgs {
+    /// The parsed test file to write. Its file name (without the extension) is
+    /// used as the test name.
+    pub output: PathBuf,
+
+    #[arg(long, default_value = DEFAULT_TEST_FIXTURE_SECRET_KEY)]
+    /// The secret key to sign the transaction with, which determines its
+    /// sender. Defaults to the key used by most upstream tests
+    pub secret_key: H256,
+
+    #[arg(long)]
+    /// The recipient of the transaction. Creates a contract if not given
+    pub to: Option<Address>,
+
+    #[arg(long, value_parser = parse_hex_bytes, default_value = "0x")]
+    /// The calldata (or init code when creating a contract), as hex
+    pub data: Vec<u8>,
+
+    #[arg(long, default_value_t = 21_000)]
+    /// The gas limit of the transaction, which is also used as the block gas
+    /// limit
+    pub gas: u64,
+
+    #[arg(long, value_parser = parse_dec_u256, default_value = "0")]
+    /// The value (in wei) sent with the transaction
+    pub value: U256,
+}
 
 #[derive(Debug, Parser)]
 #[command(author, version, about)]
 pub(crate) struct ProgArgs {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     pub out_path: Option<PathBuf>,
 
     #[arg(short, long, default_value_t = false)]
     /// Allow deserializing without fetching git remote
     pub no_fetch: bool,
+
+    #[arg(long)]
+    /// Only parse the test files whose path (relative to the local Ethereum
+    /// tests repo) matches this glob, e.g.
+    /// `Cancun/BlockchainTests/GeneralStateTests/stEIP1559/*`
+    pub test_subset_by_path: Option<Pattern>,
+
+    #[arg(long, conflicts_with_all = ["no_fetch", "test_subset_by_path"])]
+    /// Only fetch and parse the single test file at this path, relative to the
+    /// root of the Ethereum tests repo. Only the directory of the test is
+    /// added to the sparse checkout of the repo
+    pub fetch_specific: Option<PathBuf>,
+
+    #[arg(long)]
+    /// Write the total CBOR size of each field of the parsed tests to this
+    /// file as JSON, to see which fields take up the most space
+    pub output_cbor_stats: Option<PathBuf>,
+
+    #[arg(long, default_value_t = NonZeroUsize::new(DEFAULT_MAX_CONCURRENT_WRITES).unwrap())]
+    /// The maximum number of parsed test files to write to disk at the same
+    /// time. Lower this on spinning disks, where many concurrent writes slow
+    /// everything down
+    pub max_concurrent_writes: NonZeroUsize,
+
+    #[arg(long)]
+    /// After writing the parsed tests, copy any new or changed files to this
+    /// location. Either a local path or (with the `s3` feature) an
+    /// `s3://<BUCKET>/<PREFIX>` URI
+    pub output_dir_mirror: Option<String>,
+
+    #[arg(long)]
+    /// Write all parsed tests to this file as NDJSON (one test per line, along
+    /// with its path relative to the output directory) instead of writing a
+    /// CBOR file per test. Read it with `evm_test_runner --test-ndjson`
+    pub output_generation_inputs_jsonl: Option<PathBuf>,
+
+    #[arg(long, conflicts_with = "output_generation_inputs_jsonl")]
+    /// After writing the parsed tests, remove any output directories that are
+    /// left empty (e.g. because every test in them failed to parse) and print
+    /// the parse errors of their tests
+    pub prune_output_dir: bool,
+
+    #[arg(long, conflicts_with = "output_generation_inputs_jsonl")]
+    /// After writing the parsed tests, write every upstream test file that has
+    /// no parsed test file in the output directory (whether it failed to
+    /// parse, was filtered out or was never attempted) to this file as a JSON
+    /// array
+    pub output_missing_tests: Option<PathBuf>,
+
+    #[arg(long)]
+    /// Skip writing tests whose generation inputs are identical to those of an
+    /// earlier test, and list which tests they duplicate in `_dedup_map.json`
+    /// in the output directory
+    pub input_dedup: bool,
+
+    #[arg(long)]
+    /// Warn about every parsed test whose expected final state root is the
+    /// zero hash or the genesis state root, which usually means that it was
+    /// not extracted from the upstream test
+    pub validate_expected_hashes: bool,
+
+    #[arg(long, requires = "validate_expected_hashes")]
+    /// Exit with an error if `--validate-expected-hashes` finds any test with a
+    /// suspicious expected hash
+    pub strict: bool,
+
+    #[arg(long)]
+    /// After parsing, print the number of upstream test variants of each fork,
+    /// along with how many of them were parsed and skipped
+    pub test_count_by_fork: bool,
+}
+
+fn parse_hex_bytes(s: &str) -> Result<Vec<u8>, String> {
+    hex::decode(s.strip_prefix("0x").unwrap_or(s))
+        .map_err(|e| format!("Parsing \"{}\" as hex bytes: {}", s, e))
+}
+
+fn parse_dec_u256(s: &str) -> Result<U256, String> {
+    U256::from_dec_str(s).map_err(|e| format!("Parsing \"{}\" as a decimal integer: {}", s, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use ethereum_types::U256;
+
+    use super::{parse_dec_u256, parse_hex_bytes};
+
+    #[test]
+    fn parse_hex_bytes_accepts_optional_prefix() {
+        assert_eq!(
+            parse_hex_bytes("0x60016000").unwrap(),
+            [0x60, 0x01, 0x60, 0x00]
+        );
+        assert_eq!(parse_hex_bytes("6001").unwrap(), [0x60, 0x01]);
+        assert_eq!(parse_hex_bytes("0x").unwrap(), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn parse_hex_bytes_rejects_invalid_hex() {
+        assert!(parse_hex_bytes("0x600").is_err());
+        assert!(parse_hex_bytes("0xzz").is_err());
+    }
+
+    #[test]
+    fn parse_dec_u256_parses_decimal_integers() {
+        assert_eq!(parse_dec_u256("0").unwrap(), U256::zero());
+        assert_eq!(parse_dec_u256("1000000").unwrap(), U256::from(1_000_000));
+        assert_eq!(
+            parse_dec_u256(
+                "115792089237316195423570985008687907853269984665640564039457584007913129639935"
+            )
+            .unwrap(),
+            U256::MAX
+        );
+    }
+
+    #[test]
+    fn parse_dec_u256_rejects_hex_and_overflow() {
+        assert!(parse_dec_u256("0x10").is_err());
+        assert!(parse_dec_u256(
+            "115792089237316195423570985008687907853269984665640564039457584007913129639936"
+        )
+        .is_err());
+    }
 }
@@ -0,0 +1,99 @@
+//! Tracks how much each field of the parsed tests contributes to the size of
+//! the CBOR output. Each field is serialized on its own, so the sizes do not
+//! include the overhead of the surrounding structs.
+use std::{collections::BTreeMap, fs::File, path::Path};
+
+use anyhow::{Context, Result};
+use common::types::ParsedTestManifest;
+use serde::Serialize;
+
+/// The accumulated CBOR size (in bytes) of each field, keyed by its path in
+/// `ParsedTestManifest`.
+#[derive(Debug, Default)]
+pub(crate) struct CborFieldSizes(BTreeMap<&'static str, usize>);
+
+#[derive(Debug, Serialize)]
+struct CborStats {
+    total_bytes: usize,
+    fields: Vec<CborFieldStats>,
+}
+
+#[derive(Debug, Serialize)]
+struct CborFieldStats {
+    name: &'static str,
+    bytes: usize,
+    percentage: f64,
+}
+
+impl CborFieldSizes {
+    pub(crate) fn of_manifest(manifest: &ParsedTestManifest) -> Self {
+        let mut sizes = Self::default();
+        sizes.add("schema_version", &manifest.schema_version);
+
+        for test in manifest.plonky2_variants.iter() {
+            sizes.add("plonky2_variants.test_name", &test.test_name);
+            sizes.add("plonky2_variants.txn_bytes", &test.txn_bytes);
+            sizes.add("plonky2_variants.final_roots", &test.final_roots);
+
+            let metadata = &test.plonky2_metadata;
+            sizes.add("plonky2_variants.plonky2_metadata.tries", &metadata.tries);
+            sizes.add(
+                "plonky2_variants.plonky2_metadata.genesis_state_root",
+                &metadata.genesis_state_root,
+            );
+            sizes.add(
+                "plonky2_variants.plonky2_metadata.contract_code",
+                &metadata.contract_code,
+            );
+            sizes.add(
+                "plonky2_variants.plonky2_metadata.block_metadata",
+                &metadata.block_metadata,
+            );
+            sizes.add(
+                "plonky2_variants.plonky2_metadata.withdrawals",
+                &metadata.withdrawals,
+            );
+        }
+
+        sizes
+    }
+
+    fn add<T: Serialize>(&mut self, field: &'static str, v: &T) {
+        *self.0.entry(field).or_default() += serde_cbor::to_vec(v).unwrap().len();
+    }
+
+    pub(crate) fn merge(&mut self, other: CborFieldSizes) {
+        for (field, size) in other.0 {
+            *self.0.entry(field).or_default() += size;
+        }
+    }
+
+    /// Writes the sizes to `path` as JSON, largest fields first.
+    pub(crate) fn write_to_file(&self, path: &Path) -> Result<()> {
+        let total_bytes = self.0.values().sum();
+
+        let mut fields: Vec<_> = self
+            .0
+            .iter()
+            .map(|(&name, &bytes)| CborFieldStats {
+                name,
+                bytes,
+                percentage: bytes as f64 / total_bytes as f64 * 100.0,
+            })
+            .collect();
+        fields.sort_by(|a, b| b.bytes.cmp(&a.bytes));
+
+        let file =
+            File::create(path).with_context(|| format!("Creating CBOR stats file {:?}", path))?;
+        serde_json::to_writer_pretty(
+            file,
+            &CborStats {
+                total_bytes,
+                fields,
+            },
+        )
+        .with_context(|| "Serializing CBOR stats")?;
+
+        Ok(())
+    }
+}
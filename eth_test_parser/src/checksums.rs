@@ -0,0 +1,101 @@
+//! Writes the SHA-256 of every parsed test file, so that `evm_test_runner
+//! --verify-hashes` can detect files that were corrupted after being written.
+//!
+//! The checksum file uses the format of `sha256sum`, so it can also be checked
+//! by hand with `sha256sum -c checksums.sha256` from the directory it is in.
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use common::{
+    config::{CHECKSUMS_FILE_NAME, MAIN_TEST_DIR},
+    utils::sha256_hex,
+};
+
+/// Writes the checksum of every parsed test file under `out_path` to
+/// `CHECKSUMS_FILE_NAME`, with paths relative to the main test directory.
+/// Returns the number of files written to it.
+pub(crate) fn write_checksums(out_path: &Path) -> Result<usize> {
+    let main_test_dir = out_path.join(MAIN_TEST_DIR);
+
+    let mut test_files = Vec::new();
+    collect_test_files(&main_test_dir, &mut test_files)?;
+    test_files.sort();
+
+    let mut checksums = String::new();
+    for path in test_files.iter() {
+        let bytes = fs::read(path).with_context(|| format!("Reading {:?}", path))?;
+        let rel_path = path.strip_prefix(&main_test_dir)?;
+
+        checksums.push_str(&format!("{}  {}\n", sha256_hex(&bytes), rel_path.display()));
+    }
+
+    let checksums_path = main_test_dir.join(CHECKSUMS_FILE_NAME);
+    fs::write(&checksums_path, checksums)
+        .with_context(|| format!("Writing checksums to {:?}", checksums_path))?;
+
+    Ok(test_files.len())
+}
+
+fn collect_test_files(dir: &Path, test_files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir).with_context(|| format!("Reading directory {:?}", dir))? {
+        let path = entry?.path();
+
+        if path.is_dir() {
+            collect_test_files(&path, test_files)?;
+        } else if path.extension().is_some_and(|ext| ext == "cbor") {
+            test_files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf, process};
+
+    use common::{
+        config::{CHECKSUMS_FILE_NAME, MAIN_TEST_DIR},
+        utils::sha256_hex,
+    };
+
+    use super::write_checksums;
+
+    /// Creates an empty directory to run a test in, unique to this test and
+    /// process.
+    fn scratch_dir(test_name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("eth_test_parser_{}_{}", test_name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    #[test]
+    fn write_checksums_uses_paths_relative_to_main_test_dir() {
+        let out_path = scratch_dir("write_checksums");
+        let sub_group_dir = out_path.join(MAIN_TEST_DIR).join("stExample");
+        fs::create_dir_all(&sub_group_dir).unwrap();
+        fs::write(sub_group_dir.join("b.cbor"), b"second").unwrap();
+        fs::write(sub_group_dir.join("a.cbor"), b"first").unwrap();
+        fs::write(sub_group_dir.join("notes.txt"), b"not a test").unwrap();
+
+        assert_eq!(write_checksums(&out_path).unwrap(), 2);
+
+        let checksums =
+            fs::read_to_string(out_path.join(MAIN_TEST_DIR).join(CHECKSUMS_FILE_NAME)).unwrap();
+        assert_eq!(
+            checksums,
+            format!(
+                "{}  stExample/a.cbor\n{}  stExample/b.cbor\n",
+                sha256_hex(b"first"),
+                sha256_hex(b"second")
+            )
+        );
+
+        fs::remove_dir_all(&out_path).unwrap();
+    }
+}
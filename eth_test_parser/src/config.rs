@@ -8,6 +8,13 @@ pub(crate) const GENERAL_GROUP: &str = MAIN_TEST_DIR;
 pub(crate) const TEST_GROUPS: [&str; 1] = ["GeneralStateTests"];
 // The following subgroups contain subfolders unlike the other test folders.
 pub(crate) const SPECIAL_TEST_SUBGROUPS: [&str; 2] = ["Shanghai", "VMTests"];
+/// The default number of parsed test files to write to disk at the same time.
+/// Works well on SSDs, but should be lowered on spinning disks.
+pub(crate) const DEFAULT_MAX_CONCURRENT_WRITES: usize = 16;
+/// The secret key that signs the transactions of most upstream tests (sender
+/// `0xa94f5374fce5edbc8e2a8697c15331677e6ebf0b`).
+pub(crate) const DEFAULT_TEST_FIXTURE_SECRET_KEY: &str =
+    "0x45a915e4d060149eb4365960e6a7a45f334393093061116b197e3240065ff2d8";
 
 /// These test variants are used for stress testing. As such, they have
 /// unrealistic scenarios that go beyond the provable bounds of the zkEVM.
@@ -0,0 +1,176 @@
+//! Finds parsed tests whose generation inputs are identical to those of an
+//! earlier test with `--input-dedup`, so that only one of them is written.
+//! Upstream tests sometimes only differ in their comments or metadata, which
+//! the parsed tests do not keep.
+use std::{
+    collections::{hash_map::Entry, BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use common::{types::ParsedTestManifest, utils::sha256_hex};
+
+/// The file in the output directory listing which tests were deduplicated
+/// against which.
+pub(crate) const DEDUP_MAP_FILE_NAME: &str = "_dedup_map.json";
+
+/// Hashes the content of every variant of a test, leaving out the test names
+/// (which always differ).
+pub(crate) fn generation_inputs_hash(manifest: &ParsedTestManifest) -> String {
+    let variants: Vec<_> = manifest
+        .plonky2_variants
+        .iter()
+        .map(|t| {
+            let metadata = &t.plonky2_metadata;
+
+            // The contract code is a `HashMap`, which serializes in a different
+            // order every time, so it is sorted first.
+            let contract_code: BTreeMap<_, _> = metadata.contract_code.iter().collect();
+
+            (
+                &t.txn_bytes,
+                &t.final_roots,
+                &metadata.tries,
+                &metadata.genesis_state_root,
+                contract_code,
+                &metadata.block_metadata,
+                &metadata.withdrawals,
+            )
+        })
+        .collect();
+
+    sha256_hex(&serde_cbor::to_vec(&variants).unwrap())
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct InputDedup {
+    /// The first test seen with each hash.
+    originals: HashMap<String, PathBuf>,
+    /// Maps every duplicate test to the test it duplicates.
+    duplicates: BTreeMap<PathBuf, PathBuf>,
+}
+
+impl InputDedup {
+    /// Records the test at `rel_path`. Returns the earlier test that it
+    /// duplicates, if any.
+    pub(crate) fn check(&mut self, rel_path: &Path, hash: String) -> Option<&Path> {
+        match self.originals.entry(hash) {
+            Entry::Occupied(entry) => {
+                let original = entry.into_mut();
+                self.duplicates
+                    .insert(rel_path.to_path_buf(), original.clone());
+                Some(original.as_path())
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(rel_path.to_path_buf());
+                None
+            }
+        }
+    }
+
+    pub(crate) fn duplicates(&self) -> &BTreeMap<PathBuf, PathBuf> {
+        &self.duplicates
+    }
+
+    /// Writes the duplicates to `DEDUP_MAP_FILE_NAME` in `out_path` as a JSON
+    /// object mapping each duplicate to its original.
+    pub(crate) fn write_dedup_map(&self, out_path: &Path) -> Result<()> {
+        let path = out_path.join(DEDUP_MAP_FILE_NAME);
+        fs::create_dir_all(out_path)
+            .with_context(|| format!("Creating directory {:?}", out_path))?;
+        fs::write(&path, serde_json::to_string_pretty(&self.duplicates)?)
+            .with_context(|| format!("Writing the dedup map to {:?}", path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        path::{Path, PathBuf},
+    };
+
+    use common::types::{ExpectedFinalRoots, ParsedTestManifest, Plonky2ParsedTest, TestMetadata};
+    use ethereum_types::H256;
+
+    use super::{generation_inputs_hash, InputDedup};
+    use crate::test_fixture::empty_state_metadata;
+
+    fn test_manifest(test_name: &str, contract_code: HashMap<H256, Vec<u8>>) -> ParsedTestManifest {
+        let test = Plonky2ParsedTest {
+            test_name: test_name.to_string(),
+            txn_bytes: vec![0xf8, 0x63],
+            final_roots: ExpectedFinalRoots {
+                state_root_hash: H256::zero(),
+                txn_trie_root_hash: H256::zero(),
+                receipts_trie_root_hash: H256::zero(),
+            },
+            plonky2_metadata: TestMetadata {
+                contract_code,
+                ..empty_state_metadata(1_000_000)
+            },
+        };
+
+        ParsedTestManifest {
+            schema_version: 0,
+            plonky2_variants: vec![test],
+        }
+    }
+
+    fn contract_code(keys: impl Iterator<Item = u64>) -> HashMap<H256, Vec<u8>> {
+        let mut code = HashMap::new();
+        for k in keys {
+            code.insert(H256::from_low_u64_be(k), vec![k as u8; 4]);
+        }
+
+        code
+    }
+
+    #[test]
+    fn hash_does_not_depend_on_contract_code_order() {
+        let forward = test_manifest("a", contract_code(0..32));
+        let backward = test_manifest("b", contract_code((0..32).rev()));
+
+        assert_eq!(
+            generation_inputs_hash(&forward),
+            generation_inputs_hash(&backward)
+        );
+    }
+
+    #[test]
+    fn hash_depends_on_contract_code() {
+        let manifest = test_manifest("a", contract_code(0..32));
+        let other_manifest = test_manifest("a", contract_code(1..32));
+
+        assert_ne!(
+            generation_inputs_hash(&manifest),
+            generation_inputs_hash(&other_manifest)
+        );
+    }
+
+    #[test]
+    fn input_dedup_maps_duplicates_to_the_first_test() {
+        let mut dedup = InputDedup::default();
+
+        assert_eq!(dedup.check(Path::new("a.cbor"), "x".to_string()), None);
+        assert_eq!(dedup.check(Path::new("b.cbor"), "y".to_string()), None);
+        assert_eq!(
+            dedup.check(Path::new("c.cbor"), "x".to_string()),
+            Some(Path::new("a.cbor"))
+        );
+        assert_eq!(
+            dedup.check(Path::new("d.cbor"), "x".to_string()),
+            Some(Path::new("a.cbor"))
+        );
+
+        let duplicates: Vec<_> = dedup.duplicates().iter().collect();
+        assert_eq!(
+            duplicates,
+            [
+                (&PathBuf::from("c.cbor"), &PathBuf::from("a.cbor")),
+                (&PathBuf::from("d.cbor"), &PathBuf::from("a.cbor")),
+            ]
+        );
+    }
+}
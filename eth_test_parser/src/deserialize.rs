@@ -16,7 +16,7 @@ use serde::{
 };
 use serde_with::serde_as;
 
-use crate::config::UNPROVABLE_VARIANTS;
+use crate::{config::UNPROVABLE_VARIANTS, fork_counts::ForkTestCounts};
 
 #[derive(Deserialize, Debug, Clone)]
 // "self" just points to this module.
@@ -301,12 +301,18 @@ struct ValueJson {
     #[serde(rename = "genesisRLP")]
     pub(crate) genesis_rlp: Option<ByteString>,
     pub(crate) pre: HashMap<H160, PreAccount>,
+    /// The fork that the variant is for.
+    pub(crate) network: Option<String>,
 }
 
 // Wrapper around a regular `HashMap` used to conveniently skip
-// non-Shanghai related tests when deserializing.
+// non-Shanghai related tests when deserializing. Also counts the variants of
+// each fork, including the skipped ones.
 #[derive(Default, Debug)]
-pub(crate) struct TestFile(pub(crate) HashMap<String, TestBody>);
+pub(crate) struct TestFile(
+    pub(crate) HashMap<String, TestBody>,
+    pub(crate) ForkTestCounts,
+);
 
 impl<'de> Deserialize<'de> for TestFile {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
@@ -336,11 +342,17 @@ impl<'de> Deserialize<'de> for TestFile {
             where
                 M: MapAccess<'de>,
             {
-                let mut map = TestFile(HashMap::with_capacity(access.size_hint().unwrap_or(0)));
+                let mut map = TestFile(
+                    HashMap::with_capacity(access.size_hint().unwrap_or(0)),
+                    ForkTestCounts::default(),
+                );
 
                 // While we are parsing many values, we only care about the ones containing
                 // `Shanghai` in their key name.
                 while let Some((key, value)) = access.next_entry::<String, ValueJson>()? {
+                    let num_parsed = map.0.len();
+                    let fork = variant_fork(&key, value.network.as_deref());
+
                     if key.contains("Shanghai")
                         && !UNPROVABLE_VARIANTS.iter().any(|v| key.contains(v))
                     {
@@ -361,6 +373,8 @@ impl<'de> Deserialize<'de> for TestFile {
                             assert_eq!(exception[0].valid, "false".to_string());
                         }
                     }
+
+                    map.1.record(&fork, map.0.len() > num_parsed);
                 }
 
                 Ok(map)
@@ -371,9 +385,18 @@ impl<'de> Deserialize<'de> for TestFile {
     }
 }
 
+/// The fork of a test variant, from its `network` field if present, or else
+/// from the end of its name, eg. `add_d0g0v0_Shanghai`.
+fn variant_fork(variant_name: &str, network: Option<&str>) -> String {
+    network
+        .or_else(|| variant_name.rsplit('_').next())
+        .unwrap_or_default()
+        .to_string()
+}
+
 #[cfg(test)]
 mod tests {
-    use super::ByteString;
+    use super::{variant_fork, ByteString};
 
     const TEST_HEX_STR: &str = "\"0xf863800a83061a8094095e7baea6a6c7c4c2dfeb977efac326af552d87830186a0801ba0ffb600e63115a7362e7811894a91d8ba4330e526f22121c994c4692035dfdfd5a06198379fcac8de3dbfac48b165df4bf88e2088f294b61efb9a65fe2281c76e16\"";
 
@@ -387,4 +410,15 @@ mod tests {
         assert_eq!(byte_str.0[byte_str.0.len() - 1], 0x16);
         assert_eq!(byte_str.0[byte_str.0.len() - 2], 0x6e);
     }
+
+    #[test]
+    fn variant_fork_prefers_network() {
+        assert_eq!(variant_fork("add_d0g0v0_Shanghai", Some("Paris")), "Paris");
+    }
+
+    #[test]
+    fn variant_fork_falls_back_to_variant_name_suffix() {
+        assert_eq!(variant_fork("add_d0g0v0_Shanghai", None), "Shanghai");
+        assert_eq!(variant_fork("add", None), "add");
+    }
 }
@@ -10,8 +10,36 @@ use crate::{
     utils::run_cmd,
 };
 
+/// Fetches only the directory containing the test at `rel_path` (relative to
+/// the root of the tests repo) by narrowing the sparse checkout down to it. The
+/// repo is cloned first if it does not exist yet, and pulled otherwise so that
+/// the test is up to date. The next full fetch resets the sparse checkout.
+pub(crate) fn fetch_specific_test(rel_path: &Path) {
+    let test_dir = rel_path
+        .parent()
+        .unwrap_or_else(|| panic!("The test path {:?} has no parent directory", rel_path));
+
+    if Path::new(&ETH_TESTS_REPO_LOCAL_PATH).exists() {
+        update_remote_tests();
+    } else {
+        clone_sparse_repo();
+    }
+
+    // Cone mode sparse checkouts can only contain whole directories.
+    println!("Setting sparse checkout to {:?}...", test_dir);
+    run_cmd(
+        Command::new("git")
+            .args(["-C", ETH_TESTS_REPO_LOCAL_PATH, "sparse-checkout", "set"])
+            .arg(test_dir),
+    )
+    .unwrap();
+}
+
 pub(crate) fn clone_or_update_remote_tests() {
     if Path::new(&ETH_TESTS_REPO_LOCAL_PATH).exists() {
+        // `--fetch-specific` may have narrowed the sparse checkout down to a
+        // single test directory.
+        set_sparse_checkout_to_general_group();
         update_remote_tests();
     } else {
         download_remote_tests();
@@ -75,6 +103,27 @@ fn update_remote_tests() {
 }
 
 fn download_remote_tests() {
+    clone_sparse_repo();
+    set_sparse_checkout_to_general_group();
+}
+
+fn set_sparse_checkout_to_general_group() {
+    println!(
+        "Setting sparse checkout for test groups... ({})",
+        GENERAL_GROUP
+    );
+    // sparse-checkout out the relevant test group folders.
+    run_cmd(Command::new("git").args([
+        "-C",
+        ETH_TESTS_REPO_LOCAL_PATH,
+        "sparse-checkout",
+        "set",
+        GENERAL_GROUP,
+    ]))
+    .unwrap();
+}
+
+fn clone_sparse_repo() {
     println!("Cloning Ethereum tests repo... ({})", ETH_TESTS_REPO_URL);
 
     // Sparse clone the repository with --depth=1. We do this to avoid large
@@ -93,18 +142,4 @@ fn download_remote_tests() {
         ETH_TESTS_REPO_LOCAL_PATH,
     ]))
     .unwrap();
-
-    println!(
-        "Setting sparse checkout for test groups... ({})",
-        GENERAL_GROUP
-    );
-    // sparse-checkout out the relevant test group folders.
-    run_cmd(Command::new("git").args([
-        "-C",
-        ETH_TESTS_REPO_LOCAL_PATH,
-        "sparse-checkout",
-        "set",
-        GENERAL_GROUP,
-    ]))
-    .unwrap();
 }
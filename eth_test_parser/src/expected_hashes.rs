@@ -0,0 +1,91 @@
+//! Checks the expected final state root of every parsed test with
+//! `--validate-expected-hashes`. A zero root, or one that matches the genesis
+//! state root, usually means that the expected hash was not extracted from the
+//! upstream test and a default was used instead.
+use std::fmt::{self, Display};
+
+use common::types::ParsedTestManifest;
+use ethereum_types::H256;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum SuspiciousHash {
+    Zero,
+    Genesis,
+}
+
+impl Display for SuspiciousHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SuspiciousHash::Zero => write!(f, "expected state root is the zero hash"),
+            SuspiciousHash::Genesis => {
+                write!(f, "expected state root is the genesis state root")
+            }
+        }
+    }
+}
+
+/// Returns the name of every variant of the test with a suspicious expected
+/// state root, along with what is wrong with it.
+pub(crate) fn find_suspicious_expected_hashes(
+    manifest: &ParsedTestManifest,
+) -> Vec<(String, SuspiciousHash)> {
+    manifest
+        .plonky2_variants
+        .iter()
+        .filter_map(|t| {
+            let state_root = t.final_roots.state_root_hash;
+            let issue = if state_root == H256::zero() {
+                SuspiciousHash::Zero
+            } else if state_root == t.plonky2_metadata.genesis_state_root {
+                SuspiciousHash::Genesis
+            } else {
+                return None;
+            };
+
+            Some((t.test_name.clone(), issue))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use common::types::{ExpectedFinalRoots, ParsedTestManifest, Plonky2ParsedTest};
+    use ethereum_types::H256;
+
+    use super::{find_suspicious_expected_hashes, SuspiciousHash};
+    use crate::test_fixture::empty_state_metadata;
+
+    fn test_variant(test_name: &str, state_root_hash: H256) -> Plonky2ParsedTest {
+        Plonky2ParsedTest {
+            test_name: test_name.to_string(),
+            txn_bytes: Vec::new(),
+            final_roots: ExpectedFinalRoots {
+                state_root_hash,
+                txn_trie_root_hash: H256::zero(),
+                receipts_trie_root_hash: H256::zero(),
+            },
+            plonky2_metadata: empty_state_metadata(1_000_000),
+        }
+    }
+
+    #[test]
+    fn finds_zero_and_genesis_state_roots() {
+        let genesis_state_root = empty_state_metadata(1_000_000).genesis_state_root;
+        let manifest = ParsedTestManifest {
+            schema_version: 0,
+            plonky2_variants: vec![
+                test_variant("zero", H256::zero()),
+                test_variant("genesis", genesis_state_root),
+                test_variant("valid", H256::repeat_byte(0x11)),
+            ],
+        };
+
+        assert_eq!(
+            find_suspicious_expected_hashes(&manifest),
+            [
+                ("zero".to_string(), SuspiciousHash::Zero),
+                ("genesis".to_string(), SuspiciousHash::Genesis),
+            ]
+        );
+    }
+}
@@ -0,0 +1,83 @@
+//! Counts the upstream test variants of each fork with `--test-count-by-fork`,
+//! to see which forks the parsed tests cover.
+use std::collections::BTreeMap;
+
+/// The number of test variants of each fork, keyed by the fork name.
+#[derive(Debug, Default)]
+pub(crate) struct ForkTestCounts(BTreeMap<String, ForkTestCount>);
+
+#[derive(Debug, Default)]
+struct ForkTestCount {
+    total: usize,
+    parsed: usize,
+}
+
+impl ForkTestCounts {
+    /// Records a test variant of `fork`, which was either parsed or skipped.
+    pub(crate) fn record(&mut self, fork: &str, parsed: bool) {
+        let count = self.0.entry(fork.to_string()).or_default();
+        count.total += 1;
+        if parsed {
+            count.parsed += 1;
+        }
+    }
+
+    pub(crate) fn merge(&mut self, other: ForkTestCounts) {
+        for (fork, other_count) in other.0 {
+            let count = self.0.entry(fork).or_default();
+            count.total += other_count.total;
+            count.parsed += other_count.parsed;
+        }
+    }
+
+    pub(crate) fn print(&self) {
+        println!(
+            "{:<24} {:>8} {:>8} {:>8}",
+            "Fork", "Tests", "Parsed", "Skipped"
+        );
+        for (fork, count) in self.0.iter() {
+            println!(
+                "{:<24} {:>8} {:>8} {:>8}",
+                fork,
+                count.total,
+                count.parsed,
+                count.total - count.parsed
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ForkTestCounts;
+
+    fn counts(fork_counts: &ForkTestCounts, fork: &str) -> (usize, usize) {
+        let count = &fork_counts.0[fork];
+        (count.total, count.parsed)
+    }
+
+    #[test]
+    fn record_counts_parsed_and_skipped_variants() {
+        let mut fork_counts = ForkTestCounts::default();
+        fork_counts.record("Shanghai", true);
+        fork_counts.record("Shanghai", false);
+        fork_counts.record("Paris", false);
+
+        assert_eq!(counts(&fork_counts, "Shanghai"), (2, 1));
+        assert_eq!(counts(&fork_counts, "Paris"), (1, 0));
+    }
+
+    #[test]
+    fn merge_adds_up_counts_of_each_fork() {
+        let mut fork_counts = ForkTestCounts::default();
+        fork_counts.record("Shanghai", true);
+
+        let mut other = ForkTestCounts::default();
+        other.record("Shanghai", true);
+        other.record("Paris", false);
+        fork_counts.merge(other);
+
+        assert_eq!(counts(&fork_counts, "Shanghai"), (2, 2));
+        assert_eq!(counts(&fork_counts, "Paris"), (1, 0));
+    }
+}
@@ -4,14 +4,17 @@ use std::{
     fs::{self, DirEntry, File},
     io::BufReader,
     path::{Path, PathBuf},
+    sync::{Arc, Mutex},
 };
 
 use anyhow::{anyhow, Result};
 use common::config::GENERATION_INPUTS_DEFAULT_OUTPUT_DIR;
+use glob::Pattern;
 
 use crate::{
     config::{ETH_TESTS_REPO_LOCAL_PATH, GENERAL_GROUP, TEST_GROUPS},
     deserialize::{TestBody, TestFile},
+    fork_counts::ForkTestCounts,
 };
 
 /// Get the default parsed test output directory.
@@ -80,6 +83,9 @@ pub(crate) fn get_test_group_sub_dirs() -> Result<impl Iterator<Item = DirEntry>
 
 /// Generate an iterator over the entire set of inner test case files.
 ///
+/// If `subset` is provided, only the files whose path relative to
+/// `ETH_TESTS_REPO_LOCAL_PATH` matches it are included.
+///
 /// Expected directory structure
 /// ```ignore
 /// // {TestGroupN}
@@ -87,7 +93,7 @@ pub(crate) fn get_test_group_sub_dirs() -> Result<impl Iterator<Item = DirEntry>
 /// // │   ├── {test_case_1}.json  <--- HERE
 /// // │   └── {test_case_n}.json
 /// ```
-pub(crate) fn get_test_files() -> Result<impl Iterator<Item = DirEntry>> {
+pub(crate) fn get_test_files(subset: Option<Pattern>) -> Result<impl Iterator<Item = DirEntry>> {
     let dirs = get_test_group_sub_dirs()?
         .flat_map(|entry| fs::read_dir(entry.path()))
         .flatten()
@@ -95,6 +101,13 @@ pub(crate) fn get_test_files() -> Result<impl Iterator<Item = DirEntry>> {
         .filter(|entry| match entry.path().extension() {
             None => false,
             Some(ext) => ext == "json",
+        })
+        .filter(move |entry| match &subset {
+            None => true,
+            Some(pattern) => entry
+                .path()
+                .strip_prefix(ETH_TESTS_REPO_LOCAL_PATH)
+                .is_ok_and(|rel_path| pattern.matches_path(rel_path)),
         });
 
     Ok(dirs)
@@ -110,30 +123,181 @@ pub(crate) fn prepare_output_dir(out_path: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Get the upstream test files (relative to `ETH_TESTS_REPO_LOCAL_PATH`) that
+/// have no parsed test file in `out_path`, for whatever reason.
+pub(crate) fn get_missing_test_outputs(out_path: &Path) -> Result<Vec<PathBuf>> {
+    let rel_paths = get_test_files(None)?
+        .map(|entry| {
+            entry
+                .path()
+                .strip_prefix(ETH_TESTS_REPO_LOCAL_PATH)
+                .map(Path::to_path_buf)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(missing_test_outputs(out_path, rel_paths))
+}
+
+/// The test files of `rel_paths` whose parsed test file is not in `out_path`,
+/// sorted.
+fn missing_test_outputs(out_path: &Path, rel_paths: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut missing: Vec<_> = rel_paths
+        .into_iter()
+        .filter(|rel_path| !out_path.join(rel_path).with_extension("cbor").exists())
+        .collect();
+    missing.sort();
+
+    missing
+}
+
+/// Remove the empty directories under `out_path`, including the ones that only
+/// contain empty directories. Returns the removed directories, relative to
+/// `out_path`.
+pub(crate) fn prune_empty_output_dirs(out_path: &Path) -> Result<Vec<PathBuf>> {
+    let mut pruned_dirs = Vec::new();
+    for entry in fs::read_dir(out_path)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            prune_empty_dirs(&entry.path(), &mut pruned_dirs)?;
+        }
+    }
+
+    Ok(pruned_dirs
+        .into_iter()
+        .map(|dir| dir.strip_prefix(out_path).map(Path::to_path_buf))
+        .collect::<Result<_, _>>()?)
+}
+
+/// Removes `dir` if it is empty once its empty sub-directories are removed.
+/// Returns whether `dir` was removed.
+fn prune_empty_dirs(dir: &Path, pruned_dirs: &mut Vec<PathBuf>) -> Result<bool> {
+    let mut is_empty = true;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if !(entry.file_type()?.is_dir() && prune_empty_dirs(&entry.path(), pruned_dirs)?) {
+            is_empty = false;
+        }
+    }
+
+    if is_empty {
+        fs::remove_dir(dir)?;
+        pruned_dirs.push(dir.to_path_buf());
+    }
+
+    Ok(is_empty)
+}
+
 /// Generate an iterator containing the deserialized test bodies (`TestBody`)
-/// and their `DirEntry`s.
+/// and their `DirEntry`s. Test files that do not match `subset` are skipped
+/// without being read. If `fork_counts` is given, the variants of each fork
+/// are counted into it as the files are read.
 pub(crate) fn get_deserialized_test_bodies(
+    subset: Option<Pattern>,
+    fork_counts: Option<Arc<Mutex<ForkTestCounts>>>,
 ) -> Result<impl Iterator<Item = Result<(DirEntry, Vec<TestBody>), (String, String)>>> {
-    Ok(get_test_files()?.map(|entry| {
-        let test_body = get_deserialized_test_body(&entry)
+    Ok(get_test_files(subset)?.map(move |entry| {
+        let test_body = get_deserialized_test_body(&entry, fork_counts.as_deref())
             .map_err(|err| (err.to_string(), entry.path().to_string_lossy().to_string()))?;
         Ok((entry, test_body))
     }))
 }
 
-fn get_deserialized_test_body(entry: &DirEntry) -> Result<Vec<TestBody>> {
+fn get_deserialized_test_body(
+    entry: &DirEntry,
+    fork_counts: Option<&Mutex<ForkTestCounts>>,
+) -> Result<Vec<TestBody>> {
     if entry.path().to_str().unwrap().contains("ValueOverflow") {
         return Err(anyhow!(
             "Test has invalid RLP encoding and hence cannot be processed"
         ));
     }
     let buf = BufReader::new(File::open(entry.path())?);
-    let test_file: TestFile = serde_json::from_reader(buf)?;
+    let TestFile(test_bodies, file_fork_counts) = serde_json::from_reader(buf)?;
 
-    let tests: Vec<TestBody> = test_file.0.into_values().collect();
+    if let Some(fork_counts) = fork_counts {
+        fork_counts.lock().unwrap().merge(file_fork_counts);
+    }
+
+    let tests: Vec<TestBody> = test_bodies.into_values().collect();
     if tests.is_empty() {
         Err(anyhow!("No valid tests found"))
     } else {
         anyhow::Ok(tests)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf, process};
+
+    use super::{missing_test_outputs, prune_empty_output_dirs};
+
+    /// Creates an empty directory to run a test in, unique to this test and
+    /// process.
+    fn scratch_dir(test_name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("eth_test_parser_{}_{}", test_name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    #[test]
+    fn prune_empty_output_dirs_removes_nested_empty_dirs() {
+        let out_path = scratch_dir("prune_empty_output_dirs");
+        for dir in ["a/b", "c", "d/e/f", "g"] {
+            fs::create_dir_all(out_path.join(dir)).unwrap();
+        }
+        fs::write(out_path.join("c/test.cbor"), []).unwrap();
+
+        let mut pruned_dirs = prune_empty_output_dirs(&out_path).unwrap();
+        pruned_dirs.sort();
+
+        assert_eq!(
+            pruned_dirs,
+            ["a", "a/b", "d", "d/e", "d/e/f", "g"].map(PathBuf::from)
+        );
+        assert!(out_path.join("c/test.cbor").exists());
+        assert!(!out_path.join("a").exists());
+        assert!(!out_path.join("d").exists());
+
+        fs::remove_dir_all(&out_path).unwrap();
+    }
+
+    #[test]
+    fn prune_empty_output_dirs_keeps_out_path() {
+        let out_path = scratch_dir("prune_keeps_out_path");
+
+        assert!(prune_empty_output_dirs(&out_path).unwrap().is_empty());
+        assert!(out_path.exists());
+
+        fs::remove_dir_all(&out_path).unwrap();
+    }
+
+    #[test]
+    fn missing_test_outputs_lists_tests_without_cbor_file() {
+        let out_path = scratch_dir("missing_test_outputs");
+        let sub_group_dir = out_path.join("GeneralStateTests/stExample");
+        fs::create_dir_all(&sub_group_dir).unwrap();
+        fs::write(sub_group_dir.join("parsed.cbor"), []).unwrap();
+        fs::write(sub_group_dir.join("unparsed.json"), []).unwrap();
+
+        let rel_paths = [
+            "GeneralStateTests/stExample/unparsed.json",
+            "GeneralStateTests/stExample/parsed.json",
+            "GeneralStateTests/stOther/missing.json",
+        ]
+        .map(PathBuf::from);
+
+        assert_eq!(
+            missing_test_outputs(&out_path, rel_paths.to_vec()),
+            [
+                "GeneralStateTests/stExample/unparsed.json",
+                "GeneralStateTests/stOther/missing.json"
+            ]
+            .map(PathBuf::from)
+        );
+
+        fs::remove_dir_all(&out_path).unwrap();
+    }
+}
@@ -1,58 +1,154 @@
-use std::fs::File;
-use std::io::Write;
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
 
-use anyhow::Result;
-use arg_parsing::ProgArgs;
+use anyhow::{anyhow, Context, Result};
+use arg_parsing::{Command, ProgArgs};
+use cbor_stats::CborFieldSizes;
+use checksums::write_checksums;
 use clap::Parser;
-use common::types::ParsedTestManifest;
+use common::config::PARSED_TEST_SCHEMA_VERSION;
+use common::types::{ParsedTestManifest, ParsedTestNdjsonEntry};
 use common::utils::init_env_logger;
-use fs_scaffolding::prepare_output_dir;
+use dedup::{generation_inputs_hash, InputDedup};
+use expected_hashes::find_suspicious_expected_hashes;
+use fork_counts::ForkTestCounts;
+use fs_scaffolding::{get_missing_test_outputs, prepare_output_dir, prune_empty_output_dirs};
 use futures::future::join_all;
+use glob::Pattern;
 use log::warn;
+use mirror::mirror_output_dir;
+use test_fixture::generate_test_fixture;
+use tokio::{
+    fs::{self, File},
+    io::{AsyncWriteExt, BufWriter},
+    sync::Semaphore,
+};
 
 use crate::fs_scaffolding::{get_default_out_dir, get_deserialized_test_bodies};
-use crate::{config::ETH_TESTS_REPO_LOCAL_PATH, eth_tests_fetching::clone_or_update_remote_tests};
+use crate::{
+    config::ETH_TESTS_REPO_LOCAL_PATH,
+    eth_tests_fetching::{clone_or_update_remote_tests, fetch_specific_test},
+};
 
 mod arg_parsing;
+mod cbor_stats;
+mod checksums;
 mod config;
+mod dedup;
 mod deserialize;
 mod eth_tests_fetching;
+mod expected_hashes;
+mod fork_counts;
 mod fs_scaffolding;
+mod mirror;
+mod test_fixture;
 mod trie_builder;
 mod utils;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    init_env_logger();
+    init_env_logger(None);
     let p_args = ProgArgs::parse();
 
     run(p_args).await
 }
 
-async fn run(ProgArgs { no_fetch, out_path }: ProgArgs) -> anyhow::Result<()> {
+async fn run(
+    ProgArgs {
+        command,
+        no_fetch,
+        out_path,
+        test_subset_by_path,
+        fetch_specific,
+        output_cbor_stats,
+        max_concurrent_writes,
+        output_dir_mirror,
+        output_generation_inputs_jsonl,
+        prune_output_dir,
+        output_missing_tests,
+        input_dedup,
+        validate_expected_hashes,
+        strict,
+        test_count_by_fork,
+    }: ProgArgs,
+) -> anyhow::Result<()> {
+    if let Some(Command::GenerateTestFixture(args)) = command {
+        return generate_test_fixture(args);
+    }
+
     let out_path = out_path.map(Ok).unwrap_or_else(get_default_out_dir)?;
 
-    if !no_fetch {
-        // Fetch most recent test json.
-        clone_or_update_remote_tests();
+    let test_subset_by_path = match &fetch_specific {
+        Some(rel_path) => {
+            fetch_specific_test(rel_path);
+            prepare_output_dir(&out_path)?;
 
-        // Create output directories mirroring the structure of source tests.
-        prepare_output_dir(&out_path)?;
-    }
+            Some(Pattern::new(&Pattern::escape(&rel_path.to_string_lossy()))?)
+        }
+        None => {
+            if !no_fetch {
+                // Fetch most recent test json.
+                clone_or_update_remote_tests();
+
+                // Create output directories mirroring the structure of source tests.
+                prepare_output_dir(&out_path)?;
+            }
+
+            test_subset_by_path
+        }
+    };
 
     println!("Converting test json to plonky2 generation inputs");
 
-    let generation_input_handles = get_deserialized_test_bodies()?.filter_map(|res| {
+    let fork_counts = test_count_by_fork.then(|| Arc::new(Mutex::new(ForkTestCounts::default())));
+    let test_bodies = get_deserialized_test_bodies(test_subset_by_path, fork_counts.clone())?;
+    let track_cbor_stats = output_cbor_stats.is_some();
+    let write_ndjson = output_generation_inputs_jsonl.is_some();
+    let mut parse_errors: Vec<(PathBuf, String)> = Vec::new();
+    let generation_input_handles = test_bodies.filter_map(|res| {
         match res {
             Ok((test_dir_entry, test_bodies)) => Some(tokio::task::spawn_blocking(move || {
                 let test_manifest = ParsedTestManifest {
+                    schema_version: PARSED_TEST_SCHEMA_VERSION,
                     plonky2_variants: test_bodies
                         .iter()
                         .map(|t| t.as_plonky2_test_inputs())
                         .collect(),
                 };
 
-                (test_dir_entry, serde_cbor::to_vec(&test_manifest).unwrap())
+                let field_sizes =
+                    track_cbor_stats.then(|| CborFieldSizes::of_manifest(&test_manifest));
+                let dedup_hash = input_dedup.then(|| generation_inputs_hash(&test_manifest));
+                let suspicious_hashes = match validate_expected_hashes {
+                    true => find_suspicious_expected_hashes(&test_manifest),
+                    false => Vec::new(),
+                };
+
+                let mut rel_path = test_dir_entry
+                    .path()
+                    .strip_prefix(ETH_TESTS_REPO_LOCAL_PATH)
+                    .unwrap()
+                    .to_path_buf();
+                rel_path.set_extension("cbor");
+
+                let serialized_test = match write_ndjson {
+                    false => serde_cbor::to_vec(&test_manifest).unwrap(),
+                    true => serde_json::to_vec(&ParsedTestNdjsonEntry {
+                        path: rel_path.clone(),
+                        test: test_manifest,
+                    })
+                    .unwrap(),
+                };
+
+                (
+                    rel_path,
+                    serialized_test,
+                    field_sizes,
+                    dedup_hash,
+                    suspicious_hashes,
+                )
             })),
             Err((err, path_str)) => {
                 // Skip any errors in parsing a test. As the upstream repo changes, we may get
@@ -61,27 +157,156 @@ async fn run(ProgArgs { no_fetch, out_path }: ProgArgs) -> anyhow::Result<()> {
                     "Unable to parse test {} due to error: {}. Skipping!",
                     path_str, err
                 );
+
+                if prune_output_dir {
+                    let path = Path::new(&path_str);
+                    let rel_path = path.strip_prefix(ETH_TESTS_REPO_LOCAL_PATH).unwrap_or(path);
+                    parse_errors.push((rel_path.to_path_buf(), err));
+                }
                 None
             }
         }
     });
 
-    println!(
-        "Writing plonky2 generation input cbor to disk, {:?}",
-        out_path.as_os_str()
-    );
+    let mut ndjson_writer = match &output_generation_inputs_jsonl {
+        Some(ndjson_path) => {
+            println!(
+                "Writing plonky2 generation inputs as NDJSON to {:?}",
+                ndjson_path.as_os_str()
+            );
+            Some(BufWriter::new(File::create(ndjson_path).await?))
+        }
+        None => {
+            println!(
+                "Writing plonky2 generation input cbor to disk, {:?}",
+                out_path.as_os_str()
+            );
+            None
+        }
+    };
+
+    let mut cbor_field_sizes = CborFieldSizes::default();
+    let mut dedup = InputDedup::default();
+    let mut suspicious_hashes = Vec::new();
+    let write_permits = Arc::new(Semaphore::new(max_concurrent_writes.get()));
+    let mut write_handles = Vec::new();
 
     for thread in join_all(generation_input_handles).await {
-        let (test_dir_entry, generation_inputs) = thread.unwrap();
-        let mut path = out_path.join(
-            test_dir_entry
-                .path()
-                .strip_prefix(ETH_TESTS_REPO_LOCAL_PATH)
-                .unwrap(),
+        let (rel_path, generation_inputs, field_sizes, dedup_hash, test_suspicious_hashes) =
+            thread.unwrap();
+        suspicious_hashes.extend(test_suspicious_hashes);
+        if let Some(field_sizes) = field_sizes {
+            cbor_field_sizes.merge(field_sizes);
+        }
+
+        if dedup_hash.is_some_and(|hash| dedup.check(&rel_path, hash).is_some()) {
+            continue;
+        }
+
+        if let Some(writer) = ndjson_writer.as_mut() {
+            writer.write_all(&generation_inputs).await?;
+            writer.write_all(b"\n").await?;
+            continue;
+        }
+
+        let path = out_path.join(rel_path);
+        let write_permits = write_permits.clone();
+        write_handles.push(tokio::spawn(async move {
+            let _permit = write_permits.acquire_owned().await.unwrap();
+            let mut file = File::create(&path)
+                .await
+                .with_context(|| format!("Creating {:?}", path))?;
+            file.write_all(&generation_inputs)
+                .await
+                .with_context(|| format!("Writing {:?}", path))?;
+            file.flush()
+                .await
+                .with_context(|| format!("Writing {:?}", path))
+        }));
+    }
+
+    for handle in join_all(write_handles).await {
+        handle.unwrap()?;
+    }
+
+    if let Some(mut writer) = ndjson_writer {
+        writer.flush().await?;
+    }
+
+    if input_dedup {
+        println!(
+            "Skipped {} tests with the same generation inputs as an earlier test",
+            dedup.duplicates().len()
+        );
+        for (path, original) in dedup.duplicates() {
+            println!("{} (duplicate of {})", path.display(), original.display());
+        }
+
+        dedup.write_dedup_map(&out_path)?;
+    }
+
+    if !write_ndjson {
+        let num_files = write_checksums(&out_path)?;
+        println!("Wrote the checksums of {} parsed test files", num_files);
+    }
+
+    if let Some(missing_tests_path) = output_missing_tests {
+        let missing_tests = get_missing_test_outputs(&out_path)?;
+        println!(
+            "Writing {} tests without a parsed test file to {:?}",
+            missing_tests.len(),
+            missing_tests_path.as_os_str()
+        );
+        fs::write(
+            &missing_tests_path,
+            serde_json::to_string_pretty(&missing_tests)?,
+        )
+        .await?;
+    }
+
+    if let Some(stats_path) = output_cbor_stats {
+        println!("Writing CBOR size stats to {:?}", stats_path.as_os_str());
+        cbor_field_sizes.write_to_file(&stats_path)?;
+    }
+
+    if prune_output_dir {
+        let pruned_dirs = prune_empty_output_dirs(&out_path)?;
+        println!("Pruned {} empty output directories", pruned_dirs.len());
+
+        for dir in pruned_dirs {
+            println!("{}", dir.display());
+            for (path, err) in parse_errors
+                .iter()
+                .filter(|(path, _)| path.parent() == Some(dir.as_path()))
+            {
+                println!("    {}: {}", path.display(), err);
+            }
+        }
+    }
+
+    if let Some(dest) = output_dir_mirror {
+        mirror_output_dir(&out_path, &dest).await?;
+    }
+
+    if let Some(fork_counts) = fork_counts {
+        fork_counts.lock().unwrap().print();
+    }
+
+    if validate_expected_hashes {
+        println!(
+            "Found {} test variants with a suspicious expected state root",
+            suspicious_hashes.len()
         );
-        path.set_extension("cbor");
-        let mut file = File::create(path).unwrap();
-        file.write_all(&generation_inputs).unwrap();
+        for (t_name, issue) in suspicious_hashes.iter() {
+            warn!("{}: {}", t_name, issue);
+        }
+
+        if strict && !suspicious_hashes.is_empty() {
+            return Err(anyhow!(
+                "{} test variants have a suspicious expected state root",
+                suspicious_hashes.len()
+            ));
+        }
     }
 
     Ok(())
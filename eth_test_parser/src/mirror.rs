@@ -0,0 +1,165 @@
+//! Mirrors the parsed test output directory to another location, so that the
+//! parsed tests can be shared between machines (eg. in distributed CI). Like
+//! `rsync`, only files that are new or whose contents changed are copied.
+//! Files that only exist at the destination are left alone.
+//!
+//! The destination is either a local path or an `s3://<BUCKET>/<PREFIX>` URI.
+//! S3 is only available when compiled with the `s3` feature.
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use keccak_hash::keccak;
+
+const S3_URI_PREFIX: &str = "s3://";
+
+/// Copies every new or changed file in `out_path` to `dest`, and prints how
+/// many files were synced.
+pub(crate) async fn mirror_output_dir(out_path: &Path, dest: &str) -> Result<()> {
+    println!("Mirroring {:?} to {}", out_path.as_os_str(), dest);
+
+    let mut files = Vec::new();
+    collect_files(out_path, Path::new(""), &mut files)?;
+
+    let num_synced = match dest.strip_prefix(S3_URI_PREFIX) {
+        #[cfg(feature = "s3")]
+        Some(bucket_and_prefix) => s3::mirror_to_s3(out_path, bucket_and_prefix, &files).await?,
+        #[cfg(not(feature = "s3"))]
+        Some(_) => anyhow::bail!(
+            "Mirroring to S3 requires `eth_test_parser` to be built with the `s3` feature"
+        ),
+        None => mirror_to_local_dir(out_path, Path::new(dest), &files)?,
+    };
+
+    println!(
+        "Synced {} of {} files to {} ({} already up to date)",
+        num_synced,
+        files.len(),
+        dest,
+        files.len() - num_synced
+    );
+
+    Ok(())
+}
+
+/// Recursively collects the paths (relative to `root`) of all files under
+/// `root.join(rel_dir)`.
+fn collect_files(root: &Path, rel_dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let dir = root.join(rel_dir);
+    for entry in fs::read_dir(&dir).with_context(|| format!("Reading directory {:?}", dir))? {
+        let entry = entry?;
+        let rel_path = rel_dir.join(entry.file_name());
+
+        if entry.file_type()?.is_dir() {
+            collect_files(root, &rel_path, files)?;
+        } else {
+            files.push(rel_path);
+        }
+    }
+
+    Ok(())
+}
+
+fn mirror_to_local_dir(src_dir: &Path, dest_dir: &Path, files: &[PathBuf]) -> Result<usize> {
+    let mut num_synced = 0;
+
+    for rel_path in files {
+        let src_path = src_dir.join(rel_path);
+        let dest_path = dest_dir.join(rel_path);
+
+        let src_bytes = fs::read(&src_path).with_context(|| format!("Reading {:?}", src_path))?;
+        let up_to_date = fs::read(&dest_path)
+            .map(|dest_bytes| keccak(dest_bytes) == keccak(&src_bytes))
+            .unwrap_or(false);
+        if up_to_date {
+            continue;
+        }
+
+        if let Some(parent) = dest_path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Creating mirror directory {:?}", parent))?;
+        }
+        fs::write(&dest_path, src_bytes).with_context(|| format!("Writing {:?}", dest_path))?;
+        num_synced += 1;
+    }
+
+    Ok(num_synced)
+}
+
+#[cfg(feature = "s3")]
+mod s3 {
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+    };
+
+    use anyhow::{Context, Result};
+    use aws_sdk_s3::{primitives::ByteStream, Client};
+
+    /// Uploads every file whose MD5 differs from the `ETag` of the object
+    /// already in the bucket. Credentials and the region are read from the
+    /// usual AWS environment variables and config files.
+    pub(super) async fn mirror_to_s3(
+        src_dir: &Path,
+        bucket_and_prefix: &str,
+        files: &[PathBuf],
+    ) -> Result<usize> {
+        let (bucket, prefix) = bucket_and_prefix
+            .split_once('/')
+            .unwrap_or((bucket_and_prefix, ""));
+
+        let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+        let client = Client::new(&config);
+        let mut num_synced = 0;
+
+        for rel_path in files {
+            let key = object_key(prefix, rel_path);
+            let src_path = src_dir.join(rel_path);
+            let src_bytes =
+                fs::read(&src_path).with_context(|| format!("Reading {:?}", src_path))?;
+
+            // The `ETag` of an object uploaded in a single part is the quoted MD5 of
+            // its contents.
+            let local_etag = format!("\"{:x}\"", md5::compute(&src_bytes));
+            let remote_etag = client
+                .head_object()
+                .bucket(bucket)
+                .key(&key)
+                .send()
+                .await
+                .ok()
+                .and_then(|obj| obj.e_tag().map(|t| t.to_string()));
+            if remote_etag.as_deref() == Some(local_etag.as_str()) {
+                continue;
+            }
+
+            client
+                .put_object()
+                .bucket(bucket)
+                .key(&key)
+                .body(ByteStream::from(src_bytes))
+                .send()
+                .await
+                .with_context(|| format!("Uploading {:?} to s3://{}/{}", src_path, bucket, key))?;
+            num_synced += 1;
+        }
+
+        Ok(num_synced)
+    }
+
+    /// S3 keys always use `/` as the separator, regardless of the platform.
+    fn object_key(prefix: &str, rel_path: &Path) -> String {
+        let rel_key = rel_path
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        match prefix.trim_end_matches('/') {
+            "" => rel_key,
+            prefix => format!("{}/{}", prefix, rel_key),
+        }
+    }
+}
@@ -0,0 +1,169 @@
+//! Generates minimal parsed tests from a handful of transaction fields, to
+//! reproduce prover issues without depending on the upstream test corpus.
+//!
+//! The generated test has an empty pre-state, and the transaction is signed
+//! with a zero gas price so that the sender does not need a balance. The
+//! expected final roots and gas used are not known without executing the
+//! transaction, so they are left as zero: the fixture is meant to reproduce
+//! errors hit while executing the transaction, and a run of it is expected to
+//! fail at the final checks otherwise.
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::{Context, Result};
+use common::{
+    config::{ETHEREUM_CHAIN_ID, PARSED_TEST_SCHEMA_VERSION},
+    types::{ExpectedFinalRoots, ParsedTestManifest, Plonky2ParsedTest, TestMetadata},
+};
+use ethereum_types::{Address, H256, U256};
+use evm_arithmetization::{generation::TrieInputs, proof::BlockMetadata};
+use keccak_hash::{keccak, KECCAK_EMPTY};
+use mpt_trie::partial_trie::{HashedPartialTrie, PartialTrie};
+use rlp::RlpStream;
+use secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
+
+use crate::arg_parsing::TestFixtureArgs;
+
+/// The number of fields in a legacy transaction.
+const NUM_LEGACY_TXN_FIELDS: usize = 9;
+
+/// Writes a parsed test (CBOR) with a single variant running the transaction
+/// described by `args`.
+pub(crate) fn generate_test_fixture(args: TestFixtureArgs) -> Result<()> {
+    let secp = Secp256k1::new();
+    let secret_key = SecretKey::from_slice(args.secret_key.as_bytes())
+        .with_context(|| "Parsing the secret key")?;
+
+    println!(
+        "Generating a test fixture for a transaction from {:?}",
+        sender_address(&secp, &secret_key)
+    );
+
+    let test_name = args
+        .output
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let txn_bytes = signed_legacy_txn(&secp, &secret_key, &args);
+
+    let test = Plonky2ParsedTest {
+        test_name,
+        txn_bytes,
+        final_roots: ExpectedFinalRoots {
+            state_root_hash: H256::zero(),
+            txn_trie_root_hash: H256::zero(),
+            receipts_trie_root_hash: H256::zero(),
+        },
+        plonky2_metadata: empty_state_metadata(args.gas),
+    };
+    let manifest = ParsedTestManifest {
+        schema_version: PARSED_TEST_SCHEMA_VERSION,
+        plonky2_variants: vec![test],
+    };
+
+    write_manifest(&manifest, &args.output)?;
+    println!("Wrote test fixture to {:?}", args.output.as_os_str());
+
+    Ok(())
+}
+
+fn sender_address(secp: &Secp256k1<secp256k1::All>, secret_key: &SecretKey) -> Address {
+    let pub_key = PublicKey::from_secret_key(secp, secret_key).serialize_uncompressed();
+
+    // Skip the `0x04` prefix of the uncompressed key.
+    Address::from_slice(&keccak(&pub_key[1..]).0[12..])
+}
+
+/// Signs the transaction as a legacy transaction with EIP-155 replay
+/// protection.
+fn signed_legacy_txn(
+    secp: &Secp256k1<secp256k1::All>,
+    secret_key: &SecretKey,
+    args: &TestFixtureArgs,
+) -> Vec<u8> {
+    let unsigned_txn = legacy_txn_rlp(
+        args,
+        U256::from(ETHEREUM_CHAIN_ID),
+        U256::zero(),
+        U256::zero(),
+    );
+
+    let msg = Message::from_digest(keccak(unsigned_txn).0);
+    let (rec_id, sig) = secp
+        .sign_ecdsa_recoverable(&msg, secret_key)
+        .serialize_compact();
+    let v = U256::from(rec_id.to_i32() as u64 + ETHEREUM_CHAIN_ID * 2 + 35);
+
+    legacy_txn_rlp(
+        args,
+        v,
+        U256::from_big_endian(&sig[..32]),
+        U256::from_big_endian(&sig[32..]),
+    )
+}
+
+fn legacy_txn_rlp(args: &TestFixtureArgs, v: U256, r: U256, s: U256) -> Vec<u8> {
+    let mut stream = RlpStream::new_list(NUM_LEGACY_TXN_FIELDS);
+
+    // Nonce and gas price.
+    stream.append(&U256::zero());
+    stream.append(&U256::zero());
+    stream.append(&args.gas);
+    match &args.to {
+        Some(to) => stream.append(to),
+        // Contract creation.
+        None => stream.append_empty_data(),
+    };
+    stream.append(&args.value);
+    stream.append(&args.data);
+    stream.append(&v);
+    stream.append(&r);
+    stream.append(&s);
+
+    stream.out().to_vec()
+}
+
+/// The metadata of a test with an empty pre-state, in a block with the given
+/// gas limit.
+pub(crate) fn empty_state_metadata(gas_limit: u64) -> TestMetadata {
+    let state_trie = HashedPartialTrie::default();
+    let genesis_state_root = state_trie.hash();
+    let empty_code_hash = H256::from(KECCAK_EMPTY.0);
+
+    TestMetadata {
+        tries: TrieInputs {
+            state_trie,
+            transactions_trie: HashedPartialTrie::default(),
+            receipts_trie: HashedPartialTrie::default(),
+            storage_tries: Vec::new(),
+        },
+        genesis_state_root,
+        contract_code: HashMap::from([(empty_code_hash, Vec::new())]),
+        block_metadata: block_metadata(gas_limit),
+        withdrawals: Vec::new(),
+    }
+}
+
+fn block_metadata(gas_limit: u64) -> BlockMetadata {
+    BlockMetadata {
+        block_beneficiary: Address::zero(),
+        block_timestamp: U256::one(),
+        block_number: U256::one(),
+        block_difficulty: U256::zero(),
+        block_gaslimit: gas_limit.into(),
+        block_chain_id: ETHEREUM_CHAIN_ID.into(),
+        block_base_fee: U256::zero(),
+        block_random: H256::zero(),
+        block_gas_used: U256::zero(),
+        block_bloom: [U256::zero(); 8],
+    }
+}
+
+fn write_manifest(manifest: &ParsedTestManifest, path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Creating fixture directory {:?}", parent))?;
+    }
+
+    let bytes = serde_cbor::to_vec(manifest).with_context(|| "Serializing test fixture")?;
+    fs::write(path, bytes).with_context(|| format!("Writing test fixture {:?}", path))
+}
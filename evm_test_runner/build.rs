@@ -0,0 +1,33 @@
+//! Captures the `rustc` version and the git commit that the runner was built
+//! from, so that they can be included in bug reports (see `--version-info`).
+
+use std::{env, process::Command};
+
+fn main() {
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    println!(
+        "cargo:rustc-env=RUSTC_VERSION={}",
+        command_output(&rustc, &["--version"])
+    );
+    println!(
+        "cargo:rustc-env=GIT_COMMIT_HASH={}",
+        command_output("git", &["rev-parse", "HEAD"])
+    );
+
+    // Rebuild when a new commit is checked out.
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+    println!("cargo:rerun-if-changed=../.git/refs/heads");
+}
+
+/// The trimmed `stdout` of running `cmd`, or `unknown` if it failed (eg.
+/// because we are not building from a git checkout).
+fn command_output(cmd: &str, args: &[&str]) -> String {
+    Command::new(cmd)
+        .args(args)
+        .output()
+        .ok()
+        .filter(|out| out.status.success())
+        .and_then(|out| String::from_utf8(out.stdout).ok())
+        .map(|s| s.trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
@@ -0,0 +1,76 @@
+//! Optional tracking of heap allocations made while running each test.
+//!
+//! Only available when compiled with the `jemallocator` feature.
+//! `evm_arithmetization` already installs `jemalloc` as the global allocator,
+//! so we read its statistics instead of providing our own allocator. jemalloc
+//! only keeps cumulative allocation counts per thread, and proof generation is
+//! spread across the rayon thread pool, so a snapshot sums the counts of the
+//! current thread and of every thread in the pool. Taking a snapshot before
+//! and after a test gives the number of bytes that the test allocated and
+//! deallocated. Allocations made by other tests running at the same time on
+//! the pool are counted as well.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Heap allocation totals, either since the start of the process or over the
+/// course of a single test.
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, Serialize)]
+pub(crate) struct AllocStats {
+    pub(crate) bytes_allocated: u64,
+    pub(crate) bytes_deallocated: u64,
+}
+
+#[cfg(feature = "jemallocator")]
+pub(crate) use jemalloc_counting::{alloc_stats_since, current_totals};
+
+#[cfg(feature = "jemallocator")]
+mod jemalloc_counting {
+    use jemalloc_ctl::thread;
+
+    use super::AllocStats;
+
+    /// The allocation totals of the calling thread since it was started.
+    fn thread_totals() -> AllocStats {
+        // These only fail if jemalloc was built without statistics, in which
+        // case there is nothing to report.
+        let read = |counter: Result<thread::ThreadLocal<u64>, _>| {
+            counter.map(|c| c.get()).unwrap_or_default()
+        };
+
+        AllocStats {
+            bytes_allocated: read(thread::allocatedp::read()),
+            bytes_deallocated: read(thread::deallocatedp::read()),
+        }
+    }
+
+    /// The allocation totals of the current thread and of the rayon thread
+    /// pool.
+    pub(crate) fn current_totals() -> AllocStats {
+        rayon::broadcast(|_| thread_totals())
+            .into_iter()
+            .chain(std::iter::once(thread_totals()))
+            .fold(
+                AllocStats {
+                    bytes_allocated: 0,
+                    bytes_deallocated: 0,
+                },
+                |acc, t| AllocStats {
+                    bytes_allocated: acc.bytes_allocated + t.bytes_allocated,
+                    bytes_deallocated: acc.bytes_deallocated + t.bytes_deallocated,
+                },
+            )
+    }
+
+    /// The allocations made since the `before` snapshot was taken.
+    pub(crate) fn alloc_stats_since(before: &AllocStats) -> AllocStats {
+        let now = current_totals();
+
+        AllocStats {
+            bytes_allocated: now.bytes_allocated.saturating_sub(before.bytes_allocated),
+            bytes_deallocated: now
+                .bytes_deallocated
+                .saturating_sub(before.bytes_deallocated),
+        }
+    }
+}
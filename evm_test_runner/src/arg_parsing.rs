@@ -1,7 +1,8 @@
 use std::path::PathBuf;
 
-use clap::{Parser, ValueEnum};
+use clap::{Parser, Subcommand, ValueEnum};
 use common::types::VariantFilterType;
+use regex::Regex;
 
 #[derive(Clone, Debug, ValueEnum)]
 pub(crate) enum ReportType {
@@ -15,9 +16,97 @@ pub(crate) enum ReportType {
     Summary,
 }
 
+/// The type of the transaction of a test (see EIP-2718).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub(crate) enum TxnType {
+    /// Untyped transactions from before EIP-2718.
+    Legacy,
+
+    /// Transactions with an access list (type `0x01`).
+    Eip2930,
+
+    /// Transactions with a priority fee (type `0x02`).
+    Eip1559,
+
+    /// Blob transactions (type `0x03`).
+    Eip4844,
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub(crate) enum Command {
+    /// Prove a minimal block without any transactions to check that the prover
+    /// is working, without running any tests.
+    SanityCheck,
+
+    /// Print the JSON schema (draft 7) of the results written to the
+    /// `--checkpoint` file and exit.
+    OutputSchema,
+
+    /// Merge several results files (in the format of `--checkpoint`, eg. from
+    /// runs on different machines) into one with the same format. Tests that
+    /// are in more than one file are listed, and only keep their result from
+    /// the first file.
+    MergeResults {
+        /// The results files to merge.
+        #[arg(required = true, num_args = 2..)]
+        files: Vec<PathBuf>,
+
+        /// The file to write the merged results to.
+        #[arg(long, default_value = "merged_results.json")]
+        output: PathBuf,
+    },
+
+    /// Export the persistent test state (the last result of each test, along
+    /// with how many times it has run, passed and failed) to a CSV file for
+    /// analysis in a spreadsheet.
+    ExportStateCsv {
+        /// The CSV file to write to.
+        output: PathBuf,
+    },
+
+    /// Print a chart of how long a test took in each run recorded with
+    /// `--persist-timing-history`.
+    ShowTimingTrends {
+        /// The test (variant) to show the timings of, as
+        /// `<group>/<sub-group>/<test name>`.
+        test_key: String,
+    },
+
+    /// Print statistics on the parsed tests (transaction types, gas used and
+    /// pre-state account counts) without running them.
+    TestMetadataReport {
+        /// Print the statistics as JSON.
+        #[arg(long, default_value_t = false)]
+        json: bool,
+
+        /// Only include tests whose name matches this regex.
+        #[arg(long)]
+        filter: Option<Regex>,
+    },
+
+    /// Run a single variant of a parsed test and print its `TestRunResult` to
+    /// `stdout` as JSON. Used by `--test-isolation` to run each test in its
+    /// own process.
+    RunSingle {
+        /// The parsed test (CBOR) file to run.
+        test_file: PathBuf,
+
+        /// The index of the variant in the test file to run.
+        #[arg(long, default_value_t = 0)]
+        variant: usize,
+
+        /// Read the contents of `test_file` from `stdin` instead of from disk.
+        #[arg(long, default_value_t = false)]
+        stdin: bool,
+    },
+}
+
 #[derive(Debug, Parser)]
 #[clap(author, version, about)]
 pub(crate) struct ProgArgs {
+    #[command(subcommand)]
+    pub(crate) command: Option<Command>,
+
     /// An optional path to a blacklist file containing test variants to prevent
     /// from running. This can be used to skip particularly heavy or badly
     /// configured tests.
@@ -61,9 +150,573 @@ pub(crate) struct ProgArgs {
     #[arg(short, long, default_value_t = false)]
     pub(crate) simple_progress_indicator: bool,
 
+    /// Print the fields of the transaction of each test (sender, recipient,
+    /// nonce, gas limit, value and data) before running it. Only logged at the
+    /// `debug` level unless `--simple-progress-indicator` is used, so that the
+    /// progress bar is not interrupted.
+    #[arg(long, default_value_t = false)]
+    pub(crate) output_transaction_details: bool,
+
     /// Add/remove the persistent test pass state from the upstream parsed
     /// tests. If a new test exists upstream, we add an entry to the persistent
     /// state. If it's removed, we purge it from our persistent state.
     #[arg(short = 'u', long, default_value_t = false)]
     pub(crate) update_persistent_state_from_upstream: bool,
+
+    /// Lock the persistent test pass state file while reading and writing it,
+    /// and merge in any changes made to it by another runner since it was read
+    /// instead of overwriting them (the latest run of each test wins). Use
+    /// this when several runners share the same state file.
+    #[arg(long, default_value_t = false)]
+    pub(crate) watch_persistent_state: bool,
+
+    /// Write the versions of the runner and `plonky2` along with the
+    /// persistent test pass state, and warn if the state was written by
+    /// different versions, as its passes and failures may not be comparable
+    /// with those of this run.
+    #[arg(long, default_value_t = false)]
+    pub(crate) test_runner_version_in_state: bool,
+
+    /// Clear the persistent test pass state if it was written by different
+    /// versions of the runner or `plonky2`.
+    #[arg(
+        long,
+        default_value_t = false,
+        requires = "test_runner_version_in_state"
+    )]
+    pub(crate) clear_stale_state: bool,
+
+    /// Lower the block gas limit of every test to this value before running.
+    /// Tests with a gas limit already below this value are not affected. This
+    /// can speed up proving significantly, but may give incorrect results for
+    /// tests that are sensitive to the gas limit.
+    #[arg(long)]
+    pub(crate) gas_limit_override: Option<u64>,
+
+    /// Skip tests whose parsed CBOR file is larger than this many bytes,
+    /// without reading them in. Large inputs tend to be the slowest to prove
+    /// and use the most memory.
+    #[arg(long)]
+    pub(crate) max_cbor_size: Option<u64>,
+
+    /// Skip tests whose contract code uses any of these opcodes, given as
+    /// comma-separated hex bytes. Useful for opcodes that the prover does not
+    /// support yet, as every test that uses them is bound to fail.
+    ///
+    /// Eg: `0x5f,0x49`
+    #[arg(long, value_delimiter = ',', value_parser = parse_opcode)]
+    pub(crate) opcode_blacklist: Vec<u8>,
+
+    /// Only run tests whose transaction is of this type. Can be given more
+    /// than once to run the tests of any of the types.
+    #[arg(long, value_enum)]
+    pub(crate) test_type: Vec<TxnType>,
+
+    /// Write how many tests use each opcode (and whether the prover implements
+    /// it) to `opcode_stats.json` once the tests are read in, to find opcodes
+    /// that the tests barely cover.
+    #[arg(long, default_value_t = false)]
+    pub(crate) opcode_statistics: bool,
+
+    /// Write a graph (in Graphviz DOT format) to this path that connects tests
+    /// with accounts in common in their pre-state, and print the tests with
+    /// the most connections. A bug with one account can fail all the tests
+    /// that share it.
+    #[arg(long)]
+    pub(crate) test_dependency_graph: Option<PathBuf>,
+
+    /// Read the tests from an NDJSON file written by `eth_test_parser
+    /// --output-generation-inputs-jsonl` instead of from the parsed test
+    /// directory.
+    #[arg(long, conflicts_with_all = ["test_stdin", "max_cbor_size"])]
+    pub(crate) test_ndjson: Option<PathBuf>,
+
+    /// Download a `.tar.gz` archive of parsed tests from this URL and read the
+    /// tests from it instead of from the local parsed test directory. The
+    /// archive is checked against the SHA-256 in `<URL>.sha256` before being
+    /// extracted to `fetched_tests`.
+    #[arg(long, conflicts_with_all = ["test_stdin", "test_ndjson"])]
+    pub(crate) network_fetch_tests: Option<String>,
+
+    /// Check each parsed test file against the `checksums.sha256` file written
+    /// by the parser before reading it in. Files that do not match are skipped
+    /// and listed after the run.
+    #[arg(long, default_value_t = false, conflicts_with_all = ["test_stdin", "test_ndjson"])]
+    pub(crate) verify_hashes: bool,
+
+    /// Periodically write the results accumulated so far to a checkpoint file
+    /// in this directory. If a checkpoint already exists when starting a run,
+    /// the run can be resumed from it.
+    #[arg(long)]
+    pub(crate) checkpoint: Option<PathBuf>,
+
+    /// The number of tests to run between each checkpoint write.
+    #[arg(long, default_value_t = 100, requires = "checkpoint")]
+    pub(crate) checkpoint_interval: usize,
+
+    /// Always resume from an existing checkpoint instead of prompting.
+    #[arg(long, default_value_t = false, requires = "checkpoint")]
+    pub(crate) resume_checkpoint: bool,
+
+    /// Write checkpoints on a background thread instead of pausing the run
+    /// while they are written. Any pending checkpoint is still written before
+    /// the run ends.
+    #[arg(long, default_value_t = false, requires = "checkpoint")]
+    pub(crate) async_state_writes: bool,
+
+    /// Once the run completes, write its results to `passed.json`,
+    /// `failed.json` and `skipped.json` in this directory, each only with the
+    /// tests of that status. Uses the same format as the checkpoint file.
+    #[arg(long)]
+    pub(crate) split_output_by_status: Option<PathBuf>,
+
+    /// Once the run completes, write the results of each sub-group to its own
+    /// `<GROUP>__<SUB_GROUP>.json` file in this directory, so that they can be
+    /// processed in parallel. Uses the same format as the checkpoint file.
+    #[arg(long)]
+    pub(crate) output_subgroup_reports: Option<PathBuf>,
+
+    /// Once the run completes, write the results to this file as a flat CSV
+    /// with one row per test (group, sub-group, name, status, time taken and
+    /// the error of failed tests), for analysis in a spreadsheet.
+    #[arg(long)]
+    pub(crate) output_csv: Option<PathBuf>,
+
+    /// Start the file written by `--output-csv` with a UTF-8 byte order mark,
+    /// so that Excel does not mistake its encoding.
+    #[arg(long, default_value_t = false, requires = "output_csv")]
+    pub(crate) csv_bom: bool,
+
+    /// Write a package to this directory for every failing test, with
+    /// everything needed to reproduce the failure: a copy of its parsed test
+    /// file, the flags and versions of the run, and a script that runs it
+    /// again.
+    #[arg(long)]
+    pub(crate) emit_counterexample: Option<PathBuf>,
+
+    /// Group failed tests by their error message in the report, showing each
+    /// unique message once along with the number of tests that hit it.
+    #[arg(long, default_value_t = false)]
+    pub(crate) aggregate_errors: bool,
+
+    /// When aggregating errors, also list every test that failed with each
+    /// message.
+    #[arg(long, default_value_t = false, requires = "aggregate_errors")]
+    pub(crate) verbose_errors: bool,
+
+    /// Print a table that sorts the tests that failed with an EVM error into
+    /// broad categories (stack, arithmetic, opcode, memory or other) based on
+    /// their error message, with a few example tests per category.
+    #[arg(long, default_value_t = false)]
+    pub(crate) group_failures_by_error_type: bool,
+
+    /// Print the full error of each failed test in a box below the test
+    /// report, with numbers (eg. hashes) highlighted. Only applies to the
+    /// `test` report.
+    #[arg(long, default_value_t = false)]
+    pub(crate) pretty_print_errors: bool,
+
+    /// When running in GitHub Actions (`GITHUB_ACTIONS=true`), emit an error
+    /// annotation on the parsed test file of each failed test, so that
+    /// failures show up in the PR diff view. Does nothing otherwise.
+    #[arg(long, default_value_t = false)]
+    pub(crate) ci_annotations: bool,
+
+    /// Abort if any parsed test was written with a different schema version
+    /// than the one this runner expects, instead of failing with a confusing
+    /// deserialization error (or silently ignoring new fields).
+    #[arg(long, default_value_t = false)]
+    pub(crate) test_version_check: bool,
+
+    /// Before reading in any test, check the schema version of every parsed
+    /// test file, and abort with a list of all the files that do not match
+    /// the version this runner expects. Unlike `--test-version-check`, this
+    /// lists every stale file instead of stopping at the first one.
+    #[arg(long, default_value_t = false, conflicts_with_all = ["test_stdin", "test_ndjson"])]
+    pub(crate) verify_cbor_schema_version: bool,
+
+    /// Exit with an error if the fraction of tests that passed is below this
+    /// value (between `0` and `1`, or a percentage). Skipped tests are not
+    /// counted. Useful as a CI gate for suites with known failures.
+    ///
+    /// Eg: `0.95`, `95%`
+    #[arg(long, value_parser = parse_rate)]
+    pub(crate) expected_pass_rate: Option<f64>,
+
+    /// Exit with an error if fewer than this many tests passed, eg. because
+    /// the parsed test directory is empty or the filters exclude almost every
+    /// test.
+    #[arg(long)]
+    pub(crate) min_passing_tests: Option<usize>,
+
+    /// Allow up to this fraction of tests (between `0` and `1`, or a
+    /// percentage) to fail, with a warning that lists the failed tests. Exit
+    /// with an error if more tests fail. Skipped tests are not counted.
+    ///
+    /// Eg: `0.02`, `2%`
+    #[arg(long, value_parser = parse_rate)]
+    pub(crate) expected_max_fail_rate: Option<f64>,
+
+    /// Exit with an error if any test timed out (see `--test-timeout`), after
+    /// listing the tests that did. Timed out tests always count as failures
+    /// in the pass and fail rates.
+    #[arg(long, default_value_t = false, requires = "test_timeout")]
+    pub(crate) timeout_is_failure: bool,
+
+    /// Exit with an error if any test was skipped without running (eg. by
+    /// `--max-cbor-size` or `--auto-skip-slow`), after listing the skipped
+    /// tests and why they were skipped.
+    #[arg(long, default_value_t = false)]
+    pub(crate) assert_no_skipped: bool,
+
+    /// Exit with an error if any sub-group ran fewer than this many tests
+    /// (`1` if no value is given), after listing the offending sub-groups.
+    /// Catches sub-groups left empty by a failed parser run. Skipped tests do
+    /// not count as having run, and neither do tests excluded by filters.
+    #[arg(long, num_args = 0..=1, default_missing_value = "1")]
+    pub(crate) assert_subgroup_coverage: Option<usize>,
+
+    /// After the report, list every test that was skipped without running,
+    /// along with why it was skipped and which option caused it.
+    #[arg(long, default_value_t = false)]
+    pub(crate) explain_skipped: bool,
+
+    /// Exit with an error if fewer than this many tests that failed in their
+    /// last run pass in this run. Lists the tests that now pass. Useful as a
+    /// CI gate when a change is expected to fix a known number of tests.
+    #[arg(long)]
+    pub(crate) expected_new_passes: Option<usize>,
+
+    /// Exit with an error if any `EvmErr` message of this run does not appear
+    /// in the results file at this path (a `--checkpoint` file, see
+    /// `output-schema`). Only new messages count: messages that just occur
+    /// more or less often than before do not.
+    #[arg(long)]
+    pub(crate) fail_on_new_evm_errors: Option<PathBuf>,
+
+    /// Fail tests where the gas used reported by the proof does not match the
+    /// gas used in the expected block header. Has no effect with
+    /// `--witness-only`, as no proof is generated.
+    #[arg(long, default_value_t = false)]
+    pub(crate) strict_gas_accounting: bool,
+
+    /// Let the first panic in the prover terminate the process (with a stack
+    /// trace if `RUST_BACKTRACE` is set) instead of marking the test as failed
+    /// and continuing with the other tests. Useful for debugging.
+    #[arg(long, default_value_t = false, conflicts_with = "worker_count")]
+    pub(crate) abort_on_panic: bool,
+
+    /// Run the tests in the order that they were read in. By default, tests
+    /// that failed in their last run are run first, followed by tests that
+    /// have never been run, and then tests that previously passed.
+    #[arg(long, default_value_t = false)]
+    pub(crate) no_prioritise: bool,
+
+    /// Run each test in a separate child process, so that a test can not
+    /// affect the global state of the prover for the tests after it. Slower,
+    /// as a new process is started for every test.
+    #[arg(long, default_value_t = false, conflicts_with = "abort_on_panic")]
+    pub(crate) test_isolation: bool,
+
+    /// Read the parsed test files of every test into memory before the run
+    /// starts, and hand them to the child processes of `--test-isolation`
+    /// instead of having each child read its file while other tests are being
+    /// proven. Helps on disks or network file systems with slow seeks. Without
+    /// `--test-isolation`, all tests are already read in before the run.
+    #[arg(long, default_value_t = false, requires = "test_isolation")]
+    pub(crate) preload_cbor: bool,
+
+    /// Prove each test twice at the same time and fail it if the two proofs
+    /// differ, to catch non-determinism caused by races on global state in the
+    /// prover. This doubles the cost of every test, so a full run requires
+    /// `--test-filter` to be set.
+    #[arg(long, default_value_t = false, conflicts_with = "witness_only")]
+    pub(crate) check_determinism: bool,
+
+    /// Write the state, transactions and receipts trie roots (before and after
+    /// the transaction) committed to by the proof of each passing test to
+    /// `<DIR>/<TEST_NAME>.merkle.json`.
+    #[arg(long, conflicts_with = "witness_only")]
+    pub(crate) output_merkle_proofs: Option<PathBuf>,
+
+    /// Write the public values of the proof of each test (passing or failing)
+    /// to `<DIR>/<TEST_NAME>.public_values.json`, along with the values
+    /// expected by the test. Tests that fail before a proof is generated have
+    /// no public values.
+    #[arg(long, conflicts_with = "witness_only")]
+    pub(crate) output_public_values: Option<PathBuf>,
+
+    /// Write the number of rows of each STARK table in the proof of each test,
+    /// along with the constraint degree and FRI parameters it was proven with,
+    /// to `<DIR>/<TEST_NAME>.structure.json`.
+    #[arg(long, conflicts_with = "witness_only")]
+    pub(crate) log_proof_structure: Option<PathBuf>,
+
+    /// Write the proof of each passing test as JSON to
+    /// `<DIR>/<TEST_NAME>.proof.json`, to check it with an external verifier.
+    #[arg(long, conflicts_with = "witness_only")]
+    pub(crate) output_proof_json: Option<PathBuf>,
+
+    /// With `--output-proof-json`, also write the proofs of tests that fail
+    /// after a proof is generated (eg. because it does not verify). Tests that
+    /// fail before a proof is generated have no proof to write.
+    #[arg(long, default_value_t = false, requires = "output_proof_json")]
+    pub(crate) output_all_proofs: bool,
+
+    /// Record the size of the serialized proof of each test, and print the
+    /// mean, minimum, maximum and 99th percentile proof sizes after the run.
+    /// The sizes are also included in the results written by `--checkpoint`
+    /// and `--split-output-by-status`.
+    #[arg(long, default_value_t = false, conflicts_with = "witness_only")]
+    pub(crate) output_proof_size: bool,
+
+    /// After the run, print a histogram of the sizes of the parsed test files
+    /// of the tests, and how strongly their size correlates with the time the
+    /// tests took. Helps to pick a value for `--max-cbor-size`.
+    #[arg(long, default_value_t = false, conflicts_with_all = ["test_stdin", "test_ndjson"])]
+    pub(crate) output_size_stats: bool,
+
+    /// Log the receipts trie root of the proof of each test next to the one
+    /// expected by the test, at the `debug` level. The receipts root is not
+    /// checked yet, so this is the only way to see where they differ.
+    #[arg(long, default_value_t = false, conflicts_with = "witness_only")]
+    pub(crate) output_receipt_root_diff: bool,
+
+    /// Prove and verify tests with the `StarkConfig` in this JSON file instead
+    /// of `StarkConfig::standard_fast_config()`. Fields left out keep their
+    /// default value. See `prover_config.rs` for the available fields and how
+    /// they affect security and performance.
+    #[arg(long, conflicts_with = "witness_only")]
+    pub(crate) prover_config_file: Option<PathBuf>,
+
+    /// Pass every test immediately without running the prover. For working on
+    /// the runner itself (progress output and reports) without waiting on
+    /// proofs. The results are reported as mock passes and are not recorded in
+    /// the persistent test state.
+    #[arg(long, default_value_t = false)]
+    pub(crate) mock_prove: bool,
+
+    /// Once this many tests of a sub-group have failed, skip its remaining
+    /// tests (tests that are already running still finish). Saves time on
+    /// sub-groups where most tests fail with the same error.
+    #[arg(long)]
+    pub(crate) max_subgroup_failures: Option<usize>,
+
+    /// Stop the whole run as soon as a test fails with an EVM error whose
+    /// message matches this regex, for errors that mean that running more
+    /// tests is pointless (eg. running out of memory). Fails the run.
+    #[arg(long)]
+    pub(crate) early_abort_pattern: Option<Regex>,
+
+    /// The number of tests to run at the same time. Idle workers steal queued
+    /// tests from busy ones, so a few slow tests do not hold up the rest.
+    #[arg(long, default_value_t = 1)]
+    pub(crate) worker_count: usize,
+
+    /// With `--worker-count`, give each worker a whole sub-group at a time and
+    /// run its tests one after the other, instead of spreading the tests of a
+    /// sub-group over all workers. Progress is only recorded once a whole
+    /// sub-group completes.
+    #[arg(long, default_value_t = false)]
+    pub(crate) parallel_subgroups: bool,
+
+    /// Run all tests a second time after the first run completes, and mark
+    /// tests that passed in only one of the two runs as flaky.
+    #[arg(long, default_value_t = false)]
+    pub(crate) track_flaky_tests: bool,
+
+    /// Before running any test, write the names of all tests that are going
+    /// to run (after applying any filters and blacklists) to this file as a
+    /// JSON array, in execution order.
+    #[arg(long)]
+    pub(crate) emit_test_plan: Option<PathBuf>,
+
+    /// Print how long the run should take (based on how long each test took
+    /// in its last run) without running any test. Takes all filters, skips
+    /// and `--worker-count` into account.
+    #[arg(long, default_value_t = false)]
+    pub(crate) estimate_total_time: bool,
+
+    /// The log level (`trace`, `debug`, `info`, `warn` or `error`) of the
+    /// runner. Takes precedence over `RUST_LOG` for the runner's own modules,
+    /// but `RUST_LOG` is still used for everything else (eg. `plonky2`).
+    #[arg(long)]
+    pub(crate) log_level: Option<log::Level>,
+
+    /// Print the versions of the runner, `plonky2` and `rustc`, along with the
+    /// git commit that the runner was built from, and exit. Include this in
+    /// bug reports.
+    #[arg(long, default_value_t = false)]
+    pub(crate) version_info: bool,
+
+    /// Read a single parsed test (in the CBOR format written by
+    /// `eth_test_parser`) from `stdin` instead of the parsed test directory,
+    /// and print the result of each of its variants to `stdout` as a line of
+    /// JSON. The persistent test state is not updated.
+    ///
+    /// Eg: `evm_test_runner --test-stdin < test.cbor | jq .status`
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with_all = ["test_filter", "report_type", "worker_count"]
+    )]
+    pub(crate) test_stdin: bool,
+
+    /// Before the full run, run this many randomly selected tests and abort if
+    /// the prover panics or generates a proof that does not verify for any of
+    /// them, which usually points to a broken prover setup rather than a bug
+    /// found by the test. The smoke test results are included in the final
+    /// report, and only recorded in the persistent test state if the full run
+    /// goes ahead.
+    #[arg(long)]
+    pub(crate) smoke_test_first: Option<usize>,
+
+    /// Skip tests whose duration in their last run was above the 99th
+    /// percentile of the last durations of all tests. Durations are recorded
+    /// in the persistent test state on every run, so the threshold follows the
+    /// latest results.
+    #[arg(long, default_value_t = false)]
+    pub(crate) auto_skip_slow: bool,
+
+    /// Forget the recorded test durations, so that `--auto-skip-slow` doesn't
+    /// skip any test until they have been run again.
+    #[arg(long, default_value_t = false)]
+    pub(crate) reset_auto_skip: bool,
+
+    /// Pass tests that passed in each of their last `--noop-proof-min-passes`
+    /// runs without running the prover. This trusts the persistent test state
+    /// completely, so a regression in these tests goes unnoticed. Tests passed
+    /// this way are not recorded as new runs in the persistent test state.
+    #[arg(long, default_value_t = false)]
+    pub(crate) noop_proof_for_passing: bool,
+
+    /// Fail every test that has never been run according to the persistent
+    /// test state without running it, for CI runs where every test must have
+    /// been validated by an earlier run. Catches newly added tests that the
+    /// state has not caught up with.
+    #[arg(long, default_value_t = false)]
+    pub(crate) fail_if_persistent_state_missing: bool,
+
+    /// The number of consecutive runs a test must have passed in for
+    /// `--noop-proof-for-passing` to pass it without proving.
+    #[arg(long, default_value_t = 3, requires = "noop_proof_for_passing")]
+    pub(crate) noop_proof_min_passes: u64,
+
+    /// Only run this many randomly selected tests (after applying any filters
+    /// and blacklists). The selected tests are logged at the `info` level.
+    #[arg(long)]
+    pub(crate) test_subset_random: Option<usize>,
+
+    /// The seed used to select the tests for `--smoke-test-first` and
+    /// `--test-subset-random`. If not given, a random seed is used and printed
+    /// so that the selection can be reproduced.
+    #[arg(long)]
+    pub(crate) seed: Option<u64>,
+
+    /// Append how long each test took to `timing_history.jsonl` after the run,
+    /// to track performance trends across runs with `show-timing-trends`.
+    #[arg(long, default_value_t = false)]
+    pub(crate) persist_timing_history: bool,
+
+    /// Write every file the runner opens, creates, reads or lists (along with
+    /// how long it took) to `io_trace.jsonl`. The operations are also logged
+    /// at the `trace` level regardless of this flag.
+    #[arg(long, default_value_t = false)]
+    pub(crate) trace_io: bool,
+
+    /// Append the memory, CPU and file descriptor usage of the runner to
+    /// `resource_timeline.jsonl` every this many seconds during the run, to
+    /// spot memory leaks or gradual slowdowns over long runs.
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub(crate) monitor_state_drift: Option<u64>,
+
+    /// Sample the CPU usage of the runner during the run and write it as a
+    /// flame graph SVG to this path. Covers all tests of the run together.
+    /// Child processes are not sampled, so this can not be combined with
+    /// `--test-isolation`.
+    #[arg(long, conflicts_with = "test_isolation")]
+    pub(crate) output_flame_graph: Option<PathBuf>,
+
+    /// Record the number of bytes allocated and deallocated on the heap by
+    /// each test, and print the tests that allocated the most after the run.
+    #[cfg(feature = "jemallocator")]
+    #[arg(long, default_value_t = false, conflicts_with = "worker_count")]
+    pub(crate) trace_malloc: bool,
+
+    /// Send a desktop notification with the number of passed/failed tests once
+    /// the run finishes, or with the error if it fails.
+    #[cfg(feature = "notify")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) notify_on_completion: bool,
+
+    /// Append a summary of the run (timestamp, `plonky2` version,
+    /// passed/failed/skipped counts and duration) to the Google Sheet with
+    /// this spreadsheet ID. Requires network access.
+    #[cfg(feature = "gsheet")]
+    #[arg(long, requires = "gsheet_credentials")]
+    pub(crate) export_to_gsheet: Option<String>,
+
+    /// The service account JSON key file used to authenticate with the Google
+    /// Sheets API for `--export-to-gsheet`.
+    #[cfg(feature = "gsheet")]
+    #[arg(long, requires = "export_to_gsheet")]
+    pub(crate) gsheet_credentials: Option<PathBuf>,
+}
+
+fn parse_opcode(s: &str) -> Result<u8, String> {
+    let digits = s.strip_prefix("0x").unwrap_or(s);
+
+    u8::from_str_radix(digits, 16).map_err(|_| format!("Expected a hex opcode byte, but got {}", s))
+}
+
+/// Parses a fraction between `0` and `1`, or a percentage (eg. `95%`).
+fn parse_rate(s: &str) -> Result<f64, String> {
+    let rate: f64 = match s.strip_suffix('%') {
+        Some(percentage) => percentage.trim().parse::<f64>().map(|p| p / 100.0),
+        None => s.parse(),
+    }
+    .map_err(|e| format!("{}", e))?;
+
+    match (0.0..=1.0).contains(&rate) {
+        true => Ok(rate),
+        false => Err(format!(
+            "Expected a value between 0 and 1, but got {}",
+            rate
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_rate;
+
+    #[test]
+    fn parse_rate_of_fraction() {
+        assert_eq!(parse_rate("0.95"), Ok(0.95));
+        assert_eq!(parse_rate("0"), Ok(0.0));
+        assert_eq!(parse_rate("1"), Ok(1.0));
+    }
+
+    #[test]
+    fn parse_rate_of_percentage() {
+        assert_eq!(parse_rate("95%"), Ok(0.95));
+        assert_eq!(parse_rate("0%"), Ok(0.0));
+        assert_eq!(parse_rate("100%"), Ok(1.0));
+        assert_eq!(parse_rate("2.5%"), Ok(0.025));
+    }
+
+    #[test]
+    fn parse_rate_rejects_out_of_range_values() {
+        assert!(parse_rate("1.01").is_err());
+        assert!(parse_rate("-0.1").is_err());
+        assert!(parse_rate("101%").is_err());
+        assert!(parse_rate("NaN").is_err());
+    }
+
+    #[test]
+    fn parse_rate_rejects_non_numbers() {
+        assert!(parse_rate("").is_err());
+        assert!(parse_rate("%").is_err());
+        assert!(parse_rate("high").is_err());
+    }
 }
@@ -0,0 +1,465 @@
+//! Periodically saves the results accumulated so far during a run to disk so
+//! that an interrupted run can be resumed later.
+//!
+//! Note that this is distinct from the persistent run state, which only tracks
+//! the last pass/fail state of each individual test across runs.
+
+use std::{
+    collections::HashSet,
+    fs,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread::{self, JoinHandle},
+};
+
+use anyhow::Context;
+use log::{info, warn};
+use schemars::schema_for;
+
+use crate::{
+    io_trace::{trace_io, IoOp},
+    plonky2_runner::{TestGroupRunResults, TestRunResult, TestStatus, TestSubGroupRunResults},
+};
+
+const CHECKPOINT_FILE_NAME: &str = "checkpoint.json";
+
+#[derive(Debug)]
+pub(crate) struct Checkpoint {
+    path: PathBuf,
+    interval: usize,
+    tests_since_last_write: usize,
+    results: Vec<TestGroupRunResults>,
+    /// Set with `--async-state-writes`.
+    writer: Option<CheckpointWriter>,
+}
+
+/// Writes checkpoints on a background thread, so that serializing and writing
+/// them does not hold up recording the results of other tests. Only the latest
+/// pending checkpoint is written if several are queued up. Any pending
+/// checkpoint is written before the writer is dropped.
+#[derive(Debug)]
+struct CheckpointWriter {
+    send: Option<mpsc::Sender<Vec<TestGroupRunResults>>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl CheckpointWriter {
+    fn spawn(path: PathBuf) -> Self {
+        let (send, recv) = mpsc::channel::<Vec<TestGroupRunResults>>();
+        let handle = thread::spawn(move || {
+            while let Ok(mut results) = recv.recv() {
+                // Skip straight to the latest checkpoint.
+                while let Ok(newer_results) = recv.try_recv() {
+                    results = newer_results;
+                }
+
+                write_checkpoint(&path, &results);
+            }
+        });
+
+        Self {
+            send: Some(send),
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for CheckpointWriter {
+    fn drop(&mut self) {
+        // Dropping the sender stops the thread once it has written everything.
+        self.send.take();
+
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Checkpoint {
+    /// Creates a new checkpoint in `dir`. `prev_results` should contain any
+    /// results that we resumed from so that they are not lost when the
+    /// checkpoint is overwritten. With `async_writes`, the checkpoint is
+    /// written on a background thread.
+    pub(crate) fn new(
+        dir: &Path,
+        interval: usize,
+        prev_results: Vec<TestGroupRunResults>,
+        async_writes: bool,
+    ) -> Self {
+        let path = checkpoint_path(dir);
+
+        Self {
+            writer: async_writes.then(|| CheckpointWriter::spawn(path.clone())),
+            path,
+            interval,
+            tests_since_last_write: 0,
+            results: prev_results,
+        }
+    }
+
+    /// Records the result of a test and writes the checkpoint to disk if
+    /// `interval` tests have completed since the last write.
+    pub(crate) fn record_test_result(
+        &mut self,
+        group_name: &str,
+        sub_group_name: &str,
+        res: &TestRunResult,
+    ) {
+        push_test_result(&mut self.results, group_name, sub_group_name, res.clone());
+        self.tests_since_last_write += 1;
+
+        if self.tests_since_last_write >= self.interval {
+            self.write_to_disk();
+        }
+    }
+
+    /// Writes the checkpoint to disk (or queues it up to be written with
+    /// `--async-state-writes`).
+    pub(crate) fn write_to_disk(&mut self) {
+        self.tests_since_last_write = 0;
+
+        match self.writer.as_ref().and_then(|w| w.send.as_ref()) {
+            Some(send) => {
+                // The writer only stops once dropped, so this can not fail.
+                let _ = send.send(self.results.clone());
+            }
+            None => write_checkpoint(&self.path, &self.results),
+        }
+    }
+}
+
+/// Failing to write a checkpoint is not worth aborting a long run over, so we
+/// only log any errors.
+fn write_checkpoint(path: &Path, results: &[TestGroupRunResults]) {
+    if let Err(err) = write_checkpoint_intern(path, results) {
+        warn!("Unable to write checkpoint to {:?}: {:#}", path, err);
+    }
+}
+
+fn write_checkpoint_intern(path: &Path, results: &[TestGroupRunResults]) -> anyhow::Result<()> {
+    info!("Writing checkpoint to {:?}...", path);
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Creating checkpoint directory {:?}", parent))?;
+    }
+
+    // Write to a temp file first so that an interruption mid-write does not
+    // corrupt the previous checkpoint.
+    let tmp_path = path.with_extension("json.tmp");
+    let file = trace_io(IoOp::Create, &tmp_path, || fs::File::create(&tmp_path))
+        .with_context(|| format!("Creating checkpoint file {:?}", tmp_path))?;
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer_pretty(&mut writer, results).with_context(|| "Serializing checkpoint")?;
+    writer
+        .flush()
+        .with_context(|| format!("Writing checkpoint file {:?}", tmp_path))?;
+    fs::rename(&tmp_path, path)
+        .with_context(|| format!("Moving checkpoint into place at {:?}", path))?;
+
+    Ok(())
+}
+
+fn checkpoint_path(dir: &Path) -> PathBuf {
+    dir.join(CHECKPOINT_FILE_NAME)
+}
+
+/// Loads the results from an existing checkpoint in `dir` (if any).
+pub(crate) fn load_checkpoint_if_exists(
+    dir: &Path,
+) -> anyhow::Result<Option<Vec<TestGroupRunResults>>> {
+    let path = checkpoint_path(dir);
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    load_results_file(&path).map(Some)
+}
+
+/// Loads the results from a file in the same format as a checkpoint.
+pub(crate) fn load_results_file(path: &Path) -> anyhow::Result<Vec<TestGroupRunResults>> {
+    let bytes = trace_io(IoOp::Read, path, || fs::read(path))
+        .with_context(|| format!("Reading results file {:?}", path))?;
+
+    serde_json::from_slice(&bytes).with_context(|| format!("Deserializing results file {:?}", path))
+}
+
+/// Removes the checkpoint in `dir`. Called once a run has completed, as there
+/// is nothing left to resume.
+pub(crate) fn remove_checkpoint(dir: &Path) -> anyhow::Result<()> {
+    let path = checkpoint_path(dir);
+    if path.exists() {
+        fs::remove_file(&path).with_context(|| format!("Removing checkpoint {:?}", path))?;
+    }
+
+    Ok(())
+}
+
+/// Prints the JSON schema of the checkpoint file to `stdout`. The checkpoint
+/// holds the same results that the reports are generated from, so this also
+/// lets external tools consume the results of a run.
+pub(crate) fn print_checkpoint_schema() -> anyhow::Result<()> {
+    let schema = schema_for!(Vec<TestGroupRunResults>);
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&schema).with_context(|| "Serializing checkpoint schema")?
+    );
+
+    Ok(())
+}
+
+/// Asks the user on `stdin` whether they want to resume from the checkpoint
+/// found in `dir`.
+pub(crate) fn prompt_resume_from_checkpoint(dir: &Path) -> anyhow::Result<bool> {
+    print!(
+        "Found an existing checkpoint at {:?}. Resume from it? [y/N] ",
+        checkpoint_path(dir)
+    );
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Gets the names of every test that has a result in a checkpoint.
+pub(crate) fn checkpointed_test_names(results: &[TestGroupRunResults]) -> HashSet<String> {
+    results
+        .iter()
+        .flat_map(|g| g.sub_group_res.iter())
+        .flat_map(|sub_g| sub_g.test_res.iter())
+        .map(|t| t.name.clone())
+        .collect()
+}
+
+/// Merges the results of a resumed run into the results from the checkpoint
+/// it resumed from.
+pub(crate) fn merge_test_results(
+    mut prev_results: Vec<TestGroupRunResults>,
+    new_results: Vec<TestGroupRunResults>,
+) -> Vec<TestGroupRunResults> {
+    for g in new_results {
+        for sub_g in g.sub_group_res {
+            for t in sub_g.test_res {
+                push_test_result(&mut prev_results, &g.name, &sub_g.name, t);
+            }
+        }
+    }
+
+    prev_results
+}
+
+/// Merges the results files at `paths` (eg. from runs on different machines)
+/// into one. A test that is in more than one file only keeps its result from
+/// the first one, and is returned as a duplicate.
+pub(crate) fn merge_results_files(
+    paths: &[PathBuf],
+) -> anyhow::Result<(Vec<TestGroupRunResults>, Vec<String>)> {
+    let mut merged = Vec::new();
+    let mut seen_t_names = HashSet::new();
+    let mut duplicate_t_names = Vec::new();
+
+    for path in paths {
+        for g in load_results_file(path)? {
+            for sub_g in g.sub_group_res {
+                for t in sub_g.test_res {
+                    if !seen_t_names.insert(t.name.clone()) {
+                        duplicate_t_names.push(t.name);
+                        continue;
+                    }
+
+                    push_test_result(&mut merged, &g.name, &sub_g.name, t);
+                }
+            }
+        }
+    }
+
+    Ok((merged, duplicate_t_names))
+}
+
+/// Writes results to `path` in the same format as a checkpoint.
+pub(crate) fn write_results_file(
+    path: &Path,
+    results: &[TestGroupRunResults],
+) -> anyhow::Result<()> {
+    let file = trace_io(IoOp::Create, path, || fs::File::create(path))
+        .with_context(|| format!("Creating {:?}", path))?;
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer_pretty(&mut writer, results)
+        .with_context(|| format!("Serializing results to {:?}", path))?;
+    writer
+        .flush()
+        .with_context(|| format!("Writing results to {:?}", path))
+}
+
+/// Writes the results of a run to `<dir>/passed.json`, `<dir>/failed.json` and
+/// `<dir>/skipped.json`, each in the same format as a checkpoint but only with
+/// the tests of that status. Ignored tests are not written to any of them.
+pub(crate) fn write_results_split_by_status(
+    dir: &Path,
+    results: &[TestGroupRunResults],
+) -> anyhow::Result<()> {
+    let mut passed = Vec::new();
+    let mut failed = Vec::new();
+    let mut skipped = Vec::new();
+
+    for g in results {
+        for sub_g in g.sub_group_res.iter() {
+            for t in sub_g.test_res.iter() {
+                let split_results = match &t.status {
+                    status if status.passed() => &mut passed,
+                    TestStatus::Skipped(_) => &mut skipped,
+                    TestStatus::Ignored => continue,
+                    _ => &mut failed,
+                };
+                push_test_result(split_results, &g.name, &sub_g.name, t.clone());
+            }
+        }
+    }
+
+    fs::create_dir_all(dir).with_context(|| format!("Creating directory {:?}", dir))?;
+    for (file_name, split_results) in [
+        ("passed.json", passed),
+        ("failed.json", failed),
+        ("skipped.json", skipped),
+    ] {
+        write_results_file(&dir.join(file_name), &split_results)?;
+    }
+
+    Ok(())
+}
+
+/// Writes the results of each sub-group of a run to
+/// `<dir>/<group>__<sub_group>.json`, in the same format as a checkpoint but
+/// only with that sub-group.
+pub(crate) fn write_results_split_by_subgroup(
+    dir: &Path,
+    results: &[TestGroupRunResults],
+) -> anyhow::Result<()> {
+    fs::create_dir_all(dir).with_context(|| format!("Creating directory {:?}", dir))?;
+
+    for g in results {
+        for sub_g in g.sub_group_res.iter() {
+            let sub_group_results = [TestGroupRunResults {
+                name: g.name.clone(),
+                sub_group_res: vec![sub_g.clone()],
+            }];
+            let file_name = format!("{}__{}.json", g.name, sub_g.name);
+
+            write_results_file(&dir.join(file_name), &sub_group_results)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn push_test_result(
+    results: &mut Vec<TestGroupRunResults>,
+    group_name: &str,
+    sub_group_name: &str,
+    res: TestRunResult,
+) {
+    let group_idx = match results.iter().position(|g| g.name == group_name) {
+        Some(idx) => idx,
+        None => {
+            results.push(TestGroupRunResults {
+                name: group_name.to_string(),
+                sub_group_res: Vec::new(),
+            });
+            results.len() - 1
+        }
+    };
+    let sub_groups = &mut results[group_idx].sub_group_res;
+
+    let sub_group_idx = match sub_groups.iter().position(|g| g.name == sub_group_name) {
+        Some(idx) => idx,
+        None => {
+            sub_groups.push(TestSubGroupRunResults {
+                name: sub_group_name.to_string(),
+                test_res: Vec::new(),
+            });
+            sub_groups.len() - 1
+        }
+    };
+
+    sub_groups[sub_group_idx].test_res.push(res);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::merge_test_results;
+    use crate::plonky2_runner::{
+        TestGroupRunResults, TestRunResult, TestStatus, TestSubGroupRunResults,
+    };
+
+    fn test_res(name: &str) -> TestRunResult {
+        TestRunResult {
+            name: name.to_string(),
+            status: TestStatus::PassedProof,
+            alloc_stats: None,
+            duration: None,
+            proof_size_bytes: None,
+        }
+    }
+
+    fn group_res(name: &str, sub_groups: Vec<(&str, Vec<&str>)>) -> TestGroupRunResults {
+        TestGroupRunResults {
+            name: name.to_string(),
+            sub_group_res: sub_groups
+                .into_iter()
+                .map(|(sub_g_name, t_names)| TestSubGroupRunResults {
+                    name: sub_g_name.to_string(),
+                    test_res: t_names.into_iter().map(test_res).collect(),
+                })
+                .collect(),
+        }
+    }
+
+    fn t_names(res: &[TestGroupRunResults]) -> Vec<(&str, &str, &str)> {
+        res.iter()
+            .flat_map(|g| {
+                g.sub_group_res.iter().flat_map(move |sub_g| {
+                    sub_g
+                        .test_res
+                        .iter()
+                        .map(move |t| (g.name.as_str(), sub_g.name.as_str(), t.name.as_str()))
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn merge_test_results_appends_to_existing_sub_groups() {
+        let prev = vec![group_res("g1", vec![("s1", vec!["a"])])];
+        let new = vec![group_res("g1", vec![("s1", vec!["b"]), ("s2", vec!["c"])])];
+
+        let merged = merge_test_results(prev, new);
+
+        assert_eq!(merged.len(), 1);
+        assert_eq!(
+            t_names(&merged),
+            [("g1", "s1", "a"), ("g1", "s1", "b"), ("g1", "s2", "c")]
+        );
+    }
+
+    #[test]
+    fn merge_test_results_adds_new_groups() {
+        let prev = vec![group_res("g1", vec![("s1", vec!["a"])])];
+        let new = vec![group_res("g2", vec![("s1", vec!["b"])])];
+
+        let merged = merge_test_results(prev, new);
+
+        assert_eq!(t_names(&merged), [("g1", "s1", "a"), ("g2", "s1", "b")]);
+    }
+
+    #[test]
+    fn merge_test_results_with_no_previous_results() {
+        let new = vec![group_res("g1", vec![("s1", vec!["a", "b"])])];
+
+        let merged = merge_test_results(Vec::new(), new);
+
+        assert_eq!(t_names(&merged), [("g1", "s1", "a"), ("g1", "s1", "b")]);
+    }
+}
@@ -0,0 +1,66 @@
+//! Emits GitHub Actions workflow commands that annotate the parsed test file
+//! of each failed test with `--ci-annotations`, so that failures show up in
+//! the PR diff view. Does nothing when not running in GitHub Actions.
+
+use std::{collections::HashMap, env, path::PathBuf};
+
+use crate::{
+    plonky2_runner::{TestGroupRunResults, TestStatus},
+    test_dir_reading::ParsedTestGroup,
+};
+
+/// Whether we are running in a GitHub Actions workflow.
+pub(crate) fn running_in_github_actions() -> bool {
+    env::var("GITHUB_ACTIONS").is_ok_and(|v| v == "true")
+}
+
+/// Gets the path of the parsed test file of every test that was read from one.
+pub(crate) fn get_test_paths(parsed_tests: &[ParsedTestGroup]) -> HashMap<String, PathBuf> {
+    parsed_tests
+        .iter()
+        .flat_map(|g| g.sub_groups.iter())
+        .flat_map(|sub_g| sub_g.tests.iter())
+        .filter_map(|t| t.path.clone().map(|path| (t.name.clone(), path)))
+        .collect()
+}
+
+/// Prints an `::error` annotation for every failed test, pointing at its
+/// parsed test file.
+pub(crate) fn emit_failure_annotations(
+    res: &[TestGroupRunResults],
+    t_paths: &HashMap<String, PathBuf>,
+) {
+    let failed_tests = res
+        .iter()
+        .flat_map(|g| g.sub_group_res.iter())
+        .flat_map(|sub_g| sub_g.test_res.iter())
+        .filter(|t| {
+            !t.status.passed() && !matches!(t.status, TestStatus::Ignored | TestStatus::Skipped(_))
+        });
+
+    for test in failed_tests {
+        let Some(path) = t_paths.get(&test.name) else {
+            continue;
+        };
+
+        println!(
+            "::error file={},title={}::{}",
+            escape_property(&path.to_string_lossy()),
+            escape_property(&test.name),
+            escape_data(&test.status.to_string())
+        );
+    }
+}
+
+/// Escapes the message of a workflow command.
+fn escape_data(s: &str) -> String {
+    s.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escapes a property value of a workflow command, which additionally can not
+/// contain the `:` and `,` separators.
+fn escape_property(s: &str) -> String {
+    escape_data(s).replace(':', "%3A").replace(',', "%2C")
+}
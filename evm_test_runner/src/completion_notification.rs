@@ -0,0 +1,58 @@
+//! Optional desktop notification once a run finishes, so that long runs can be
+//! left in the background.
+//!
+//! Only available when compiled with the `notify` feature. Uses libnotify on
+//! Linux, the notification centre on macOS and toasts on Windows.
+
+use std::sync::OnceLock;
+
+use log::warn;
+use notify_rust::Notification;
+
+use crate::plonky2_runner::{TestGroupRunResults, TestStatusCounts};
+
+const NOTIFICATION_SUMMARY: &str = "EVM test run finished";
+
+/// The counts of the tests of the run, once all of them have run.
+static RUN_COUNTS: OnceLock<TestStatusCounts> = OnceLock::new();
+
+/// Records the results of the run for the notification, before the checks
+/// that can still fail the run.
+pub(crate) fn record_run_results(res: &[TestGroupRunResults]) {
+    let _ = RUN_COUNTS.set(TestStatusCounts::new(res));
+}
+
+/// Notifies that the run finished, with the number of tests that passed and
+/// failed if they all ran, and why the run stopped if it did not succeed.
+/// `res` is the result of the whole run, where `Ok(true)` means that it was
+/// interrupted.
+pub(crate) fn notify_run_finished(res: &anyhow::Result<bool>) {
+    let counts = RUN_COUNTS.get().map(|counts| {
+        format!(
+            "{} passed, {} failed, {} skipped",
+            counts.passed, counts.failed, counts.skipped
+        )
+    });
+
+    let body = match (res, counts) {
+        (Ok(true), _) => "The run was interrupted".to_string(),
+        (Ok(false), Some(counts)) => counts,
+        // Nothing was run (eg. a subcommand or `--emit-test-plan`).
+        (Ok(false), None) => return,
+        (Err(err), Some(counts)) => format!("{}. The run failed: {:#}", counts, err),
+        (Err(err), None) => format!("The run failed: {:#}", err),
+    };
+
+    send_notification(&body);
+}
+
+fn send_notification(body: &str) {
+    // Not being able to notify is no reason to fail the run.
+    if let Err(err) = Notification::new()
+        .summary(NOTIFICATION_SUMMARY)
+        .body(body)
+        .show()
+    {
+        warn!("Unable to send a notification: {}", err);
+    }
+}
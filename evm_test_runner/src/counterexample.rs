@@ -0,0 +1,189 @@
+//! Writes a self-contained package for every failing test with
+//! `--emit-counterexample`, so that the failure can be reproduced (eg. by
+//! someone working on the prover) without the rest of the parsed tests.
+//!
+//! Each package is a directory named after the test containing:
+//! - The parsed test file that the test was read from.
+//! - `config.json`: the arguments of the run, the status of the test and the
+//!   versions of the runner and the prover.
+//! - `command.sh`: runs the test again with `run-single`, with the same flags
+//!   that affect how it is proven.
+
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+use std::{
+    collections::HashMap,
+    env,
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use serde::Serialize;
+
+use crate::{
+    io_trace::{trace_io, IoOp},
+    plonky2_runner::{TestGroupRunResults, TestRunConfig, TestStatus},
+    test_dir_reading::ParsedTestGroup,
+    test_isolation::run_single_args,
+    version_info::VersionInfo,
+};
+
+const CONFIG_FILE_NAME: &str = "config.json";
+const COMMAND_FILE_NAME: &str = "command.sh";
+const PROVER_CONFIG_FILE_NAME: &str = "prover_config.json";
+
+/// Where a test was read from.
+#[derive(Clone, Debug)]
+pub(crate) struct TestSource {
+    path: PathBuf,
+    variant_idx: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct CounterexampleConfig<'a> {
+    test_name: &'a str,
+    variant_idx: usize,
+    status: &'a TestStatus,
+    runner_args: Vec<String>,
+    versions: VersionInfo,
+}
+
+/// Gets the parsed test file and variant of every test that was read from
+/// disk.
+pub(crate) fn get_test_sources(parsed_tests: &[ParsedTestGroup]) -> HashMap<String, TestSource> {
+    parsed_tests
+        .iter()
+        .flat_map(|g| g.sub_groups.iter())
+        .flat_map(|sub_g| sub_g.tests.iter())
+        .filter_map(|t| {
+            t.path.clone().map(|path| {
+                let source = TestSource {
+                    path,
+                    variant_idx: t.info.variant_idx,
+                };
+
+                (t.name.clone(), source)
+            })
+        })
+        .collect()
+}
+
+/// Writes a package to `dir` for every failed test in `res`. Returns the
+/// number of packages written.
+pub(crate) fn write_counterexamples(
+    dir: &Path,
+    res: &[TestGroupRunResults],
+    t_sources: &HashMap<String, TestSource>,
+    config: &TestRunConfig,
+) -> anyhow::Result<usize> {
+    let failed_tests = res
+        .iter()
+        .flat_map(|g| g.sub_group_res.iter())
+        .flat_map(|sub_g| sub_g.test_res.iter())
+        .filter(|t| t.status.failed());
+
+    let mut num_written = 0;
+    for test in failed_tests {
+        // Tests read from `stdin` have no file to copy.
+        let Some(source) = t_sources.get(&test.name) else {
+            continue;
+        };
+
+        write_counterexample(
+            &dir.join(&test.name),
+            &test.name,
+            &test.status,
+            source,
+            config,
+        )
+        .with_context(|| format!("Writing the counterexample for {}", test.name))?;
+        num_written += 1;
+    }
+
+    Ok(num_written)
+}
+
+fn write_counterexample(
+    package_dir: &Path,
+    test_name: &str,
+    status: &TestStatus,
+    source: &TestSource,
+    config: &TestRunConfig,
+) -> anyhow::Result<()> {
+    fs::create_dir_all(package_dir)
+        .with_context(|| format!("Creating directory {:?}", package_dir))?;
+
+    let test_file_name = source
+        .path
+        .file_name()
+        .ok_or_else(|| anyhow!("The test path {:?} has no file name", source.path))?;
+    copy_into_package(&source.path, &package_dir.join(test_file_name))?;
+
+    // The reproduction should only depend on the files in the package, and
+    // should not write anywhere else.
+    let mut repro_config = config.clone();
+    repro_config.merkle_proofs_dir = None;
+    repro_config.public_values_dir = None;
+    repro_config.proof_structure_dir = None;
+    repro_config.proof_json_dir = None;
+    if let Some(path) = &config.prover_config_file {
+        copy_into_package(path, &package_dir.join(PROVER_CONFIG_FILE_NAME))?;
+        repro_config.prover_config_file = Some(PROVER_CONFIG_FILE_NAME.into());
+    }
+
+    let counterexample_config = CounterexampleConfig {
+        test_name,
+        variant_idx: source.variant_idx,
+        status,
+        runner_args: env::args().collect(),
+        versions: VersionInfo::get(),
+    };
+    let config_path = package_dir.join(CONFIG_FILE_NAME);
+    let json = serde_json::to_string_pretty(&counterexample_config)?;
+    trace_io(IoOp::Write, &config_path, || fs::write(&config_path, json))
+        .with_context(|| format!("Writing {:?}", config_path))?;
+
+    let args = run_single_args(Path::new(test_file_name), source.variant_idx, &repro_config);
+    let command = format!(
+        "#!/bin/sh\n\
+         # Reproduces the failure of {}.\n\
+         cd \"$(dirname \"$0\")\" || exit 1\n\
+         exec evm_test_runner {}\n",
+        test_name,
+        args.iter().map(shell_quote).collect::<Vec<_>>().join(" ")
+    );
+    let command_path = package_dir.join(COMMAND_FILE_NAME);
+    trace_io(IoOp::Write, &command_path, || {
+        fs::write(&command_path, command)
+    })
+    .with_context(|| format!("Writing {:?}", command_path))?;
+    #[cfg(unix)]
+    fs::set_permissions(&command_path, fs::Permissions::from_mode(0o755))?;
+
+    Ok(())
+}
+
+fn copy_into_package(from: &Path, to: &Path) -> anyhow::Result<()> {
+    trace_io(IoOp::Write, to, || fs::copy(from, to))
+        .with_context(|| format!("Copying {:?} to {:?}", from, to))?;
+
+    Ok(())
+}
+
+/// Quotes `arg` for a POSIX shell, unless it only contains characters that
+/// never need quoting.
+fn shell_quote(arg: &OsString) -> String {
+    let arg = arg.to_string_lossy();
+
+    if !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:".contains(c))
+    {
+        return arg.into_owned();
+    }
+
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
@@ -0,0 +1,89 @@
+//! Writes the results of a run as a flat CSV with `--output-csv`, one row per
+//! test, for analysis in a spreadsheet.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::Context;
+use serde::Serialize;
+
+use crate::{
+    io_trace::{trace_io, IoOp},
+    plonky2_runner::{TestGroupRunResults, TestStatus},
+};
+
+/// Lets Excel know that the file is UTF-8.
+const UTF8_BOM: &[u8] = b"\xef\xbb\xbf";
+
+#[derive(Debug, Serialize)]
+struct CsvTestResult<'a> {
+    group: &'a str,
+    subgroup: &'a str,
+    test_name: &'a str,
+    status: &'static str,
+    /// Empty for tests that were skipped.
+    elapsed_ms: Option<u128>,
+    /// Empty unless the test failed.
+    error_message: String,
+}
+
+/// Writes the result of every test to `path` as CSV, with a header row. Fields
+/// are only quoted if they need to be (eg. if they contain a comma). With
+/// `bom`, the file starts with a UTF-8 byte order mark.
+pub(crate) fn write_results_csv(
+    path: &Path,
+    results: &[TestGroupRunResults],
+    bom: bool,
+) -> anyhow::Result<()> {
+    let mut file = trace_io(IoOp::Create, path, || File::create(path))
+        .map(BufWriter::new)
+        .with_context(|| format!("Creating results CSV file {:?}", path))?;
+    if bom {
+        file.write_all(UTF8_BOM)?;
+    }
+
+    let mut writer = csv::Writer::from_writer(file);
+    for g in results {
+        for sub_g in g.sub_group_res.iter() {
+            for t in sub_g.test_res.iter() {
+                writer
+                    .serialize(CsvTestResult {
+                        group: &g.name,
+                        subgroup: &sub_g.name,
+                        test_name: &t.name,
+                        status: status_name(&t.status),
+                        elapsed_ms: t.duration.map(|d| d.as_millis()),
+                        error_message: match t.status.failed() {
+                            true => t.status.to_string(),
+                            false => String::new(),
+                        },
+                    })
+                    .with_context(|| "Serializing results CSV row")?;
+            }
+        }
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+fn status_name(status: &TestStatus) -> &'static str {
+    match status {
+        TestStatus::PassedWitness => "passed_witness",
+        TestStatus::PassedProof => "passed_proof",
+        TestStatus::Ignored => "ignored",
+        TestStatus::EvmErr(_) => "evm_error",
+        TestStatus::ProofVerificationFailed(_) => "proof_verification_failed",
+        TestStatus::IncorrectGasUsage { .. } => "incorrect_gas_usage",
+        TestStatus::TimedOut => "timed_out",
+        TestStatus::Panicked(_) => "panicked",
+        TestStatus::Skipped(_) => "skipped",
+        TestStatus::Flaky { .. } => "flaky",
+        TestStatus::NonDeterministicProof => "non_deterministic_proof",
+        TestStatus::NotPreviouslyRun => "not_previously_run",
+        TestStatus::MockPassed => "mock_passed",
+    }
+}
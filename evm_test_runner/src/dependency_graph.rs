@@ -0,0 +1,115 @@
+//! Writes a graph of the tests that share pre-state accounts with
+//! `--test-dependency-graph`, in Graphviz DOT format. A bug affecting the
+//! handling of one account can fail every test that has it, so the tests with
+//! the most connections are good places to start debugging.
+//!
+//! Accounts are identified by their key in the state trie (the hash of their
+//! address), as the tests do not keep the addresses themselves.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    fs,
+    path::Path,
+};
+
+use anyhow::Context;
+use mpt_trie::{nibbles::Nibbles, partial_trie::PartialTrie, trie_ops::ValOrHash};
+
+use crate::{
+    io_trace::{trace_io, IoOp},
+    test_dir_reading::ParsedTestGroup,
+};
+
+/// Accounts in the pre-state of more tests than this (such as the usual sender
+/// and coinbase) are left out of the graph, as they would connect almost every
+/// test to every other one.
+const MAX_TESTS_PER_SHARED_ACCOUNT: usize = 50;
+
+/// The number of most connected tests to print.
+const NUM_MOST_CONNECTED_TESTS_TO_REPORT: usize = 10;
+
+/// Writes the graph of the tests that share pre-state accounts to `path`, with
+/// the number of accounts shared by two tests as the weight of their edge.
+/// Tests that share no account with any other test are left out.
+pub(crate) fn write_test_dependency_graph(
+    path: &Path,
+    parsed_tests: &[ParsedTestGroup],
+) -> anyhow::Result<()> {
+    let tests: Vec<_> = parsed_tests
+        .iter()
+        .flat_map(|g| g.sub_groups.iter())
+        .flat_map(|sub_g| sub_g.tests.iter())
+        .collect();
+
+    let mut tests_by_account: HashMap<Nibbles, Vec<usize>> = HashMap::new();
+    for (t_idx, test) in tests.iter().enumerate() {
+        for (key, _) in test
+            .info
+            .gen_inputs
+            .tries
+            .state_trie
+            .items()
+            .filter(|(_, v)| matches!(v, ValOrHash::Val(_)))
+        {
+            tests_by_account.entry(key).or_default().push(t_idx);
+        }
+    }
+
+    // The number of accounts shared by each pair of connected tests.
+    let mut edges: BTreeMap<(usize, usize), usize> = BTreeMap::new();
+    for t_idxs in tests_by_account
+        .values()
+        .filter(|t_idxs| t_idxs.len() <= MAX_TESTS_PER_SHARED_ACCOUNT)
+    {
+        for (i, &t1) in t_idxs.iter().enumerate() {
+            for &t2 in t_idxs[i + 1..].iter() {
+                *edges.entry((t1, t2)).or_default() += 1;
+            }
+        }
+    }
+
+    let mut dot = String::from("graph tests {\n");
+    for ((t1, t2), num_shared) in edges.iter() {
+        writeln!(
+            dot,
+            "  {:?} -- {:?} [weight={}];",
+            tests[*t1].name, tests[*t2].name, num_shared
+        )?;
+    }
+    dot.push_str("}\n");
+
+    trace_io(IoOp::Write, path, || fs::write(path, dot))
+        .with_context(|| format!("Writing test dependency graph to {:?}", path))?;
+
+    println!(
+        "Wrote a graph of {} connections between tests to {:?}",
+        edges.len(),
+        path
+    );
+
+    let mut degrees = vec![0; tests.len()];
+    for (t1, t2) in edges.keys() {
+        degrees[*t1] += 1;
+        degrees[*t2] += 1;
+    }
+
+    let mut most_connected: Vec<_> = degrees
+        .into_iter()
+        .enumerate()
+        .filter(|(_, degree)| *degree > 0)
+        .collect();
+    most_connected.sort_unstable_by(|(_, d1), (_, d2)| d2.cmp(d1));
+
+    if !most_connected.is_empty() {
+        println!("Most connected tests:");
+        for (t_idx, degree) in most_connected
+            .into_iter()
+            .take(NUM_MOST_CONNECTED_TESTS_TO_REPORT)
+        {
+            println!("  {}: {} tests", tests[t_idx].name, degree);
+        }
+    }
+
+    Ok(())
+}
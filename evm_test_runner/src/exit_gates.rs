@@ -0,0 +1,390 @@
+//! The checks that make a completed run exit with an error, eg. if the pass
+//! rate is below `--expected-pass-rate`. What each check needs is collected
+//! from the results before the report is generated, as writing the summary
+//! report consumes the results.
+
+use std::collections::{BTreeSet, HashSet};
+
+use anyhow::anyhow;
+use console::style;
+
+use crate::plonky2_runner::{
+    get_evm_err_msgs, get_failed_tests, get_new_passes, get_skipped_tests,
+    get_sub_groups_below_coverage, get_timed_out_tests, SkipReason, TestGroupRunResults,
+    TestStatusCounts,
+};
+
+/// The options that enable each exit gate.
+#[derive(Clone, Debug)]
+pub(crate) struct ExitGateOptions {
+    pub(crate) expected_pass_rate: Option<f64>,
+    pub(crate) min_passing_tests: Option<usize>,
+    pub(crate) expected_max_fail_rate: Option<f64>,
+    pub(crate) timeout_is_failure: bool,
+    pub(crate) assert_no_skipped: bool,
+    pub(crate) assert_subgroup_coverage: Option<usize>,
+    pub(crate) expected_new_passes: Option<usize>,
+}
+
+#[derive(Debug)]
+pub(crate) struct ExitGates {
+    opts: ExitGateOptions,
+    counts: TestStatusCounts,
+    failed_tests: Option<Vec<String>>,
+    timed_out_tests: Option<Vec<String>>,
+    skipped_tests: Option<Vec<(String, SkipReason)>>,
+    uncovered_sub_groups: Option<Vec<(String, usize)>>,
+    new_passes: Option<Vec<String>>,
+    new_evm_err_msgs: Option<Vec<String>>,
+}
+
+impl ExitGates {
+    /// Collects what the gates enabled in `opts` need from `test_res`.
+    /// `previously_failed_t_names` is required by `--expected-new-passes`, and
+    /// `baseline_evm_err_msgs` enables `--fail-on-new-evm-errors`.
+    pub(crate) fn new(
+        test_res: &[TestGroupRunResults],
+        opts: ExitGateOptions,
+        previously_failed_t_names: Option<HashSet<String>>,
+        baseline_evm_err_msgs: Option<BTreeSet<String>>,
+    ) -> Self {
+        Self {
+            counts: TestStatusCounts::new(test_res),
+            failed_tests: opts
+                .expected_max_fail_rate
+                .map(|_| get_failed_tests(test_res)),
+            timed_out_tests: opts
+                .timeout_is_failure
+                .then(|| get_timed_out_tests(test_res)),
+            skipped_tests: opts.assert_no_skipped.then(|| get_skipped_tests(test_res)),
+            uncovered_sub_groups: opts
+                .assert_subgroup_coverage
+                .map(|min_tests| get_sub_groups_below_coverage(test_res, min_tests)),
+            new_passes: previously_failed_t_names.map(|t_names| get_new_passes(test_res, &t_names)),
+            new_evm_err_msgs: baseline_evm_err_msgs.map(|baseline_msgs| {
+                get_evm_err_msgs(test_res)
+                    .into_iter()
+                    .filter(|msg| !baseline_msgs.contains(msg))
+                    .collect()
+            }),
+            opts,
+        }
+    }
+
+    /// Checks every enabled gate in turn, and fails with the first one that
+    /// the run does not pass.
+    pub(crate) fn check(&self) -> anyhow::Result<()> {
+        self.check_pass_rate()?;
+        self.check_min_passing_tests()?;
+        self.check_max_fail_rate()?;
+        self.check_no_timed_out_tests()?;
+        self.check_no_skipped_tests()?;
+        self.check_subgroup_coverage()?;
+        self.check_new_passes()?;
+        self.check_no_new_evm_errors()
+    }
+
+    fn check_pass_rate(&self) -> anyhow::Result<()> {
+        let Some(expected_pass_rate) = self.opts.expected_pass_rate else {
+            return Ok(());
+        };
+
+        let pass_rate = self.counts.pass_rate().unwrap_or(0.0);
+        println!(
+            "Pass rate: {:.2}% (expected at least {:.2}%)",
+            pass_rate * 100.0,
+            expected_pass_rate * 100.0
+        );
+
+        if pass_rate < expected_pass_rate {
+            return Err(anyhow!(
+                "The pass rate of {:.2}% is below the expected {:.2}%",
+                pass_rate * 100.0,
+                expected_pass_rate * 100.0
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn check_min_passing_tests(&self) -> anyhow::Result<()> {
+        let counts = &self.counts;
+        let Some(min_passing_tests) = self.opts.min_passing_tests else {
+            return Ok(());
+        };
+        if counts.passed >= min_passing_tests {
+            return Ok(());
+        }
+
+        let reason = if counts.num_run() == 0 && counts.skipped == 0 {
+            "no tests were read in (is the parsed test directory empty, or do the filters \
+             exclude every test?)"
+                .to_string()
+        } else if counts.num_run() == 0 {
+            format!("all {} tests were skipped", counts.skipped)
+        } else {
+            format!(
+                "{} tests failed and {} were skipped",
+                counts.failed, counts.skipped
+            )
+        };
+
+        Err(anyhow!(
+            "Only {} tests passed, but expected at least {}: {}",
+            counts.passed,
+            min_passing_tests,
+            reason
+        ))
+    }
+
+    fn check_max_fail_rate(&self) -> anyhow::Result<()> {
+        let (Some(expected_max_fail_rate), Some(failed_tests)) =
+            (self.opts.expected_max_fail_rate, &self.failed_tests)
+        else {
+            return Ok(());
+        };
+        if failed_tests.is_empty() {
+            return Ok(());
+        }
+
+        let fail_rate = self.counts.fail_rate().unwrap_or(0.0);
+        let max_failures = (expected_max_fail_rate * self.counts.num_run() as f64).floor() as usize;
+
+        println!(
+            "{}",
+            style(format!(
+                "Warning: {} tests failed ({:.2}%, at most {:.2}% allowed):",
+                failed_tests.len(),
+                fail_rate * 100.0,
+                expected_max_fail_rate * 100.0
+            ))
+            .yellow()
+            .bold()
+        );
+        for t_name in failed_tests.iter() {
+            println!("  {}", t_name);
+        }
+
+        if failed_tests.len() > max_failures {
+            return Err(anyhow!(
+                "{} more tests failed than the {} allowed by the maximum fail rate of {:.2}%",
+                failed_tests.len() - max_failures,
+                max_failures,
+                expected_max_fail_rate * 100.0
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn check_no_timed_out_tests(&self) -> anyhow::Result<()> {
+        let Some(timed_out_tests) = &self.timed_out_tests else {
+            return Ok(());
+        };
+        if timed_out_tests.is_empty() {
+            return Ok(());
+        }
+
+        println!("Timed out tests:");
+        for t_name in timed_out_tests.iter() {
+            println!("  {}", t_name);
+        }
+
+        Err(anyhow!("{} tests timed out", timed_out_tests.len()))
+    }
+
+    fn check_no_skipped_tests(&self) -> anyhow::Result<()> {
+        let Some(skipped_tests) = &self.skipped_tests else {
+            return Ok(());
+        };
+        if skipped_tests.is_empty() {
+            return Ok(());
+        }
+
+        println!("Skipped tests:");
+        for (t_name, reason) in skipped_tests.iter() {
+            println!("  {} ({})", t_name, reason);
+        }
+
+        Err(anyhow!("{} tests were skipped", skipped_tests.len()))
+    }
+
+    fn check_subgroup_coverage(&self) -> anyhow::Result<()> {
+        let (Some(min_tests), Some(uncovered_sub_groups)) = (
+            self.opts.assert_subgroup_coverage,
+            &self.uncovered_sub_groups,
+        ) else {
+            return Ok(());
+        };
+        if uncovered_sub_groups.is_empty() {
+            return Ok(());
+        }
+
+        println!("Sub-groups with fewer than {} tests run:", min_tests);
+        for (sub_g_name, num_run) in uncovered_sub_groups.iter() {
+            println!("  {} ({} tests)", sub_g_name, num_run);
+        }
+
+        Err(anyhow!(
+            "{} sub-groups ran fewer than {} tests",
+            uncovered_sub_groups.len(),
+            min_tests
+        ))
+    }
+
+    fn check_new_passes(&self) -> anyhow::Result<()> {
+        let (Some(expected_new_passes), Some(new_passes)) =
+            (self.opts.expected_new_passes, &self.new_passes)
+        else {
+            return Ok(());
+        };
+
+        println!(
+            "{} previously failing tests now pass (expected at least {}):",
+            new_passes.len(),
+            expected_new_passes
+        );
+        for t_name in new_passes.iter() {
+            println!("  {}", t_name);
+        }
+
+        if new_passes.len() < expected_new_passes {
+            return Err(anyhow!(
+                "Only {} previously failing tests now pass, but expected at least {}",
+                new_passes.len(),
+                expected_new_passes
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn check_no_new_evm_errors(&self) -> anyhow::Result<()> {
+        let Some(new_evm_err_msgs) = &self.new_evm_err_msgs else {
+            return Ok(());
+        };
+        if new_evm_err_msgs.is_empty() {
+            return Ok(());
+        }
+
+        println!("EVM errors that are not in the baseline:");
+        for msg in new_evm_err_msgs.iter() {
+            println!("  {}", msg);
+        }
+
+        Err(anyhow!(
+            "{} new EVM errors compared to the baseline",
+            new_evm_err_msgs.len()
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ExitGateOptions, ExitGates};
+    use crate::plonky2_runner::{
+        TestGroupRunResults, TestRunResult, TestStatus, TestSubGroupRunResults,
+    };
+
+    fn no_gates() -> ExitGateOptions {
+        ExitGateOptions {
+            expected_pass_rate: None,
+            min_passing_tests: None,
+            expected_max_fail_rate: None,
+            timeout_is_failure: false,
+            assert_no_skipped: false,
+            assert_subgroup_coverage: None,
+            expected_new_passes: None,
+        }
+    }
+
+    /// A run with `num_passed` passing tests followed by `num_failed` failing
+    /// ones.
+    fn test_res(num_passed: usize, num_failed: usize) -> Vec<TestGroupRunResults> {
+        let test_res = (0..num_passed + num_failed)
+            .map(|i| TestRunResult {
+                name: format!("test_{}", i),
+                status: match i < num_passed {
+                    true => TestStatus::PassedProof,
+                    false => TestStatus::TimedOut,
+                },
+                alloc_stats: None,
+                duration: None,
+                proof_size_bytes: None,
+            })
+            .collect();
+
+        vec![TestGroupRunResults {
+            name: "group".to_string(),
+            sub_group_res: vec![TestSubGroupRunResults {
+                name: "sub_group".to_string(),
+                test_res,
+            }],
+        }]
+    }
+
+    #[test]
+    fn no_gates_pass_any_run() {
+        assert!(ExitGates::new(&test_res(0, 5), no_gates(), None, None)
+            .check()
+            .is_ok());
+    }
+
+    #[test]
+    fn pass_rate_gate() {
+        let opts = ExitGateOptions {
+            expected_pass_rate: Some(0.75),
+            ..no_gates()
+        };
+
+        assert!(ExitGates::new(&test_res(3, 1), opts.clone(), None, None)
+            .check()
+            .is_ok());
+        assert!(ExitGates::new(&test_res(2, 2), opts, None, None)
+            .check()
+            .is_err());
+    }
+
+    #[test]
+    fn max_fail_rate_gate_allows_failures_up_to_the_rate() {
+        let opts = ExitGateOptions {
+            expected_max_fail_rate: Some(0.25),
+            ..no_gates()
+        };
+
+        assert!(ExitGates::new(&test_res(3, 1), opts.clone(), None, None)
+            .check()
+            .is_ok());
+        assert!(ExitGates::new(&test_res(2, 2), opts, None, None)
+            .check()
+            .is_err());
+    }
+
+    #[test]
+    fn timeout_gate_fails_on_timed_out_tests() {
+        let opts = ExitGateOptions {
+            timeout_is_failure: true,
+            ..no_gates()
+        };
+
+        assert!(ExitGates::new(&test_res(3, 0), opts.clone(), None, None)
+            .check()
+            .is_ok());
+        assert!(ExitGates::new(&test_res(3, 1), opts, None, None)
+            .check()
+            .is_err());
+    }
+
+    #[test]
+    fn min_passing_tests_gate_explains_empty_runs() {
+        let opts = ExitGateOptions {
+            min_passing_tests: Some(1),
+            ..no_gates()
+        };
+
+        let err = ExitGates::new(&test_res(0, 0), opts, None, None)
+            .check()
+            .unwrap_err();
+        assert!(err.to_string().contains("no tests were read in"));
+    }
+}
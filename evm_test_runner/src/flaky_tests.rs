@@ -0,0 +1,70 @@
+//! Detects flaky tests by comparing the results of two runs of the same tests.
+//! A test is considered flaky if it passed in one run but not in the other.
+
+use std::{collections::HashMap, mem};
+
+use crate::plonky2_runner::{TestGroupRunResults, TestStatus};
+
+/// Marks every test in `first_run` that passed in only one of the two runs as
+/// `TestStatus::Flaky`. Returns the names of the flaky tests.
+pub(crate) fn mark_flaky_tests(
+    first_run: &mut [TestGroupRunResults],
+    second_run: Vec<TestGroupRunResults>,
+) -> Vec<String> {
+    let mut second_run_statuses: HashMap<_, _> = second_run
+        .into_iter()
+        .flat_map(|g| g.sub_group_res)
+        .flat_map(|sub_g| sub_g.test_res)
+        .map(|t| (t.name, t.status))
+        .collect();
+
+    let mut flaky_t_names = Vec::new();
+    for test in first_run
+        .iter_mut()
+        .flat_map(|g| g.sub_group_res.iter_mut())
+        .flat_map(|sub_g| sub_g.test_res.iter_mut())
+    {
+        let Some(second_status) = second_run_statuses.remove(&test.name) else {
+            continue;
+        };
+
+        if test.status.passed() != second_status.passed() {
+            let first_status = mem::replace(&mut test.status, TestStatus::Ignored);
+            test.status = TestStatus::Flaky {
+                first_run: Box::new(first_status),
+                second_run: Box::new(second_status),
+            };
+            flaky_t_names.push(test.name.clone());
+        }
+    }
+
+    flaky_t_names
+}
+
+/// Print every flaky test along with its result in each run to the terminal.
+pub(crate) fn output_flaky_test_report_for_terminal(res: &[TestGroupRunResults]) {
+    let flaky_tests: Vec<_> = res
+        .iter()
+        .flat_map(|g| g.sub_group_res.iter())
+        .flat_map(|sub_g| sub_g.test_res.iter())
+        .filter(|t| matches!(t.status, TestStatus::Flaky { .. }))
+        .collect();
+
+    if flaky_tests.is_empty() {
+        println!("No flaky tests found.");
+        return;
+    }
+
+    println!("Found {} flaky tests:", flaky_tests.len());
+    for test in flaky_tests {
+        if let TestStatus::Flaky {
+            first_run,
+            second_run,
+        } = &test.status
+        {
+            println!("{}", test.name);
+            println!("    First run: {}", first_run);
+            println!("    Second run: {}", second_run);
+        }
+    }
+}
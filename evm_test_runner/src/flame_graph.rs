@@ -0,0 +1,54 @@
+//! Profiles the runner with `--output-flame-graph` and writes the samples as a
+//! flame graph SVG, to see where the prover spends its CPU time.
+//!
+//! The runner is sampled (not instrumented) for the whole run, so the flame
+//! graph aggregates every test. Only the runner process itself can be sampled,
+//! which is why this can not be combined with `--test-isolation`.
+
+use std::{fs::File, path::PathBuf};
+
+use anyhow::Context;
+use pprof::{ProfilerGuard, ProfilerGuardBuilder};
+
+/// How many times per second the stacks of every thread are sampled.
+const SAMPLING_FREQUENCY_HZ: i32 = 99;
+
+/// Samples the runner until the flame graph is written.
+pub(crate) struct FlameGraphProfiler {
+    guard: ProfilerGuard<'static>,
+    path: PathBuf,
+}
+
+impl FlameGraphProfiler {
+    pub(crate) fn start(path: PathBuf) -> anyhow::Result<Self> {
+        let guard = ProfilerGuardBuilder::default()
+            .frequency(SAMPLING_FREQUENCY_HZ)
+            .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+            .build()
+            .with_context(|| "Starting the profiler")?;
+
+        println!("Profiling the run for a flame graph...");
+
+        Ok(Self { guard, path })
+    }
+
+    /// Stops sampling and writes the flame graph to the path it was started
+    /// with.
+    pub(crate) fn write_flame_graph(self) -> anyhow::Result<()> {
+        let report = self
+            .guard
+            .report()
+            .build()
+            .with_context(|| "Building the profiling report")?;
+
+        let file = File::create(&self.path)
+            .with_context(|| format!("Creating flame graph {:?}", self.path))?;
+        report
+            .flamegraph(file)
+            .with_context(|| format!("Writing flame graph to {:?}", self.path))?;
+
+        println!("Wrote flame graph to {:?}", self.path);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,87 @@
+//! Optional integration that appends a summary of a run to a Google Sheet, to
+//! track results over time.
+//!
+//! Only available when compiled with the `gsheet` feature. Requires network
+//! access and a Google Cloud service account that has been given edit access
+//! to the spreadsheet.
+
+use std::{path::Path, time::Duration};
+
+use anyhow::Context;
+use google_sheets4::{
+    api::ValueRange,
+    hyper::{self, client::HttpConnector},
+    hyper_rustls::{self, HttpsConnector},
+    oauth2, Sheets,
+};
+use serde_json::Value;
+
+use crate::{
+    plonky2_runner::{TestGroupRunResults, TestStatusCounts},
+    version_info::PLONKY2_VERSION,
+};
+
+/// The range to append rows to. The Sheets API appends after the last row of
+/// the table found in this range.
+const APPEND_RANGE: &str = "A1";
+
+/// Appends a row with the timestamp, `plonky2` version, number of
+/// passed/failed/skipped tests and the total duration of the run to the first
+/// sheet of the spreadsheet.
+pub(crate) async fn export_run_summary_to_gsheet(
+    spreadsheet_id: &str,
+    credentials_path: &Path,
+    res: &[TestGroupRunResults],
+    run_duration: Duration,
+) -> anyhow::Result<()> {
+    println!(
+        "Exporting run summary to Google Sheet {}...",
+        spreadsheet_id
+    );
+
+    let hub = create_sheets_hub(credentials_path).await?;
+
+    let counts = TestStatusCounts::new(res);
+    let row = vec![
+        Value::from(chrono::Utc::now().to_rfc3339()),
+        Value::from(PLONKY2_VERSION),
+        Value::from(counts.passed),
+        Value::from(counts.failed),
+        Value::from(counts.skipped),
+        Value::from(humantime::format_duration(run_duration).to_string()),
+    ];
+    let req = ValueRange {
+        values: Some(vec![row]),
+        ..Default::default()
+    };
+
+    hub.spreadsheets()
+        .values_append(req, spreadsheet_id, APPEND_RANGE)
+        .value_input_option("USER_ENTERED")
+        .doit()
+        .await
+        .with_context(|| format!("Appending run summary to spreadsheet {}", spreadsheet_id))?;
+
+    Ok(())
+}
+
+async fn create_sheets_hub(
+    credentials_path: &Path,
+) -> anyhow::Result<Sheets<HttpsConnector<HttpConnector>>> {
+    let key = oauth2::read_service_account_key(credentials_path)
+        .await
+        .with_context(|| format!("Reading service account key {:?}", credentials_path))?;
+    let auth = oauth2::ServiceAccountAuthenticator::builder(key)
+        .build()
+        .await
+        .with_context(|| "Creating service account authenticator")?;
+
+    let connector = hyper_rustls::HttpsConnectorBuilder::new()
+        .with_native_roots()
+        .with_context(|| "Loading the native root certificates")?
+        .https_only()
+        .enable_http1()
+        .build();
+
+    Ok(Sheets::new(hyper::Client::builder().build(connector), auth))
+}
@@ -0,0 +1,99 @@
+//! Reports the sizes of the parsed test files of a run with
+//! `--output-size-stats`, along with how much they predict proving times, to
+//! help pick a value for `--max-cbor-size`.
+
+use std::{collections::HashMap, fs};
+
+use crate::{plonky2_runner::TestGroupRunResults, test_dir_reading::ParsedTestGroup};
+
+/// The width of the bar of the largest bucket in the histogram.
+const MAX_HISTOGRAM_BAR_WIDTH: usize = 40;
+
+/// Gets the size (in bytes) of the parsed test file of every test that was
+/// read from disk.
+pub(crate) fn get_test_file_sizes(parsed_tests: &[ParsedTestGroup]) -> HashMap<String, u64> {
+    parsed_tests
+        .iter()
+        .flat_map(|g| g.sub_groups.iter())
+        .flat_map(|sub_g| sub_g.tests.iter())
+        .filter_map(|t| {
+            let size = fs::metadata(t.path.as_ref()?).ok()?.len();
+            Some((t.name.clone(), size))
+        })
+        .collect()
+}
+
+/// Prints a histogram of the file sizes of the tests of a run (in power of two
+/// KB buckets), and the correlation between the file size and the time taken
+/// by the tests that ran.
+pub(crate) fn output_size_stats_for_terminal(
+    res: &[TestGroupRunResults],
+    t_file_sizes: &HashMap<String, u64>,
+) {
+    let tests: Vec<_> = res
+        .iter()
+        .flat_map(|g| g.sub_group_res.iter())
+        .flat_map(|sub_g| sub_g.test_res.iter())
+        .filter_map(|t| Some((*t_file_sizes.get(&t.name)?, t.duration)))
+        .collect();
+    if tests.is_empty() {
+        println!("No test file sizes were recorded");
+        return;
+    }
+
+    // Bucket `i` holds the files of less than `2^i` KB (and at least half that).
+    let mut buckets = Vec::new();
+    for (size, _) in tests.iter() {
+        let bucket = (size.div_ceil(1024).max(1) as f64).log2().ceil() as usize;
+        if buckets.len() <= bucket {
+            buckets.resize(bucket + 1, 0);
+        }
+        buckets[bucket] += 1;
+    }
+
+    let first_bucket = buckets.iter().position(|n| *n > 0).unwrap_or(0);
+    let max_count = buckets.iter().copied().max().unwrap_or(1);
+
+    println!("Test file sizes of {} tests:", tests.len());
+    for (bucket, count) in buckets.iter().enumerate().skip(first_bucket) {
+        let label = match bucket {
+            0 => "<= 1 KB".to_string(),
+            _ => format!("{} - {} KB", 1u64 << (bucket - 1), 1u64 << bucket),
+        };
+        let bar_width = count * MAX_HISTOGRAM_BAR_WIDTH / max_count;
+
+        println!("{:>20}: {:<6} {}", label, count, "#".repeat(bar_width));
+    }
+
+    let timed: Vec<_> = tests
+        .iter()
+        .filter_map(|(size, duration)| Some((*size as f64, duration.as_ref()?.as_secs_f64())))
+        .collect();
+    match pearson_correlation(&timed) {
+        Some(r) => println!(
+            "Correlation between file size and time taken ({} tests): {:.3}",
+            timed.len(),
+            r
+        ),
+        None => println!("Not enough tests ran to correlate file size and time taken"),
+    }
+}
+
+fn pearson_correlation(samples: &[(f64, f64)]) -> Option<f64> {
+    if samples.len() < 2 {
+        return None;
+    }
+
+    let n = samples.len() as f64;
+    let mean_x = samples.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = samples.iter().map(|(_, y)| y).sum::<f64>() / n;
+
+    let (mut cov, mut var_x, mut var_y) = (0.0, 0.0, 0.0);
+    for (x, y) in samples {
+        cov += (x - mean_x) * (y - mean_y);
+        var_x += (x - mean_x).powi(2);
+        var_y += (y - mean_y).powi(2);
+    }
+
+    (var_x > 0.0 && var_y > 0.0).then(|| cov / (var_x * var_y).sqrt())
+}
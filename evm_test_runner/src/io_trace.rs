@@ -0,0 +1,107 @@
+//! Records the file operations of the runner with `--trace-io`, along with
+//! how long each one took, to debug slow I/O (eg. on network file systems).
+//!
+//! Every traced operation is also logged at the `trace` level. The records are
+//! only written to `io_trace.jsonl` once tracing is enabled.
+
+use std::{
+    fs::{File, OpenOptions},
+    future::Future,
+    io::{LineWriter, Write},
+    path::Path,
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use log::{trace, warn};
+use serde::Serialize;
+
+const IO_TRACE_PATH_STR: &str = "io_trace.jsonl";
+
+static IO_TRACE: OnceLock<Mutex<LineWriter<File>>> = OnceLock::new();
+
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum IoOp {
+    Open,
+    Create,
+    Read,
+    Write,
+    ReadDir,
+}
+
+#[derive(Debug, Serialize)]
+struct IoTraceEntry<'a> {
+    timestamp: DateTime<Utc>,
+    op: IoOp,
+    path: &'a Path,
+    elapsed_us: u64,
+    succeeded: bool,
+}
+
+/// Starts writing every traced operation to `io_trace.jsonl` (truncating it).
+pub(crate) fn enable_io_trace() -> anyhow::Result<()> {
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(IO_TRACE_PATH_STR)
+        .with_context(|| format!("Creating I/O trace {}", IO_TRACE_PATH_STR))?;
+
+    println!("Writing file operations to {}...", IO_TRACE_PATH_STR);
+    let _ = IO_TRACE.set(Mutex::new(LineWriter::new(file)));
+
+    Ok(())
+}
+
+/// Runs the file operation `f` on `path` and traces it.
+pub(crate) fn trace_io<T, E>(
+    op: IoOp,
+    path: &Path,
+    f: impl FnOnce() -> Result<T, E>,
+) -> Result<T, E> {
+    let start = Instant::now();
+    let res = f();
+    record_io_op(op, path, start.elapsed(), res.is_ok());
+
+    res
+}
+
+/// Like `trace_io`, but for async file operations.
+pub(crate) async fn trace_io_async<T, E>(
+    op: IoOp,
+    path: &Path,
+    fut: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    let start = Instant::now();
+    let res = fut.await;
+    record_io_op(op, path, start.elapsed(), res.is_ok());
+
+    res
+}
+
+fn record_io_op(op: IoOp, path: &Path, elapsed: Duration, succeeded: bool) {
+    trace!("{:?} {:?} took {:?}", op, path, elapsed);
+
+    let Some(writer) = IO_TRACE.get() else {
+        return;
+    };
+
+    let entry = IoTraceEntry {
+        timestamp: Utc::now(),
+        op,
+        path,
+        elapsed_us: elapsed.as_micros() as u64,
+        succeeded,
+    };
+
+    let mut writer = writer.lock().unwrap();
+    if let Err(err) = serde_json::to_writer(&mut *writer, &entry)
+        .map_err(Into::into)
+        .and_then(|_| writeln!(writer))
+    {
+        warn!("Unable to write to the I/O trace: {}", err);
+    }
+}
@@ -1,43 +1,142 @@
 #![feature(let_chains)]
 
-use std::{collections::HashSet, rc::Rc, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    rc::Rc,
+    sync::Arc,
+    time::Duration,
+};
 
 use anyhow::anyhow;
 use arg_parsing::{ProgArgs, ReportType};
+use checkpoint::{
+    checkpointed_test_names, load_checkpoint_if_exists, load_results_file, merge_test_results,
+    prompt_resume_from_checkpoint, remove_checkpoint, write_results_split_by_status,
+    write_results_split_by_subgroup, Checkpoint,
+};
+use ci_annotations::{emit_failure_annotations, get_test_paths, running_in_github_actions};
 use clap::Parser;
-use common::utils::init_env_logger;
+use common::{config::PARSED_TEST_SCHEMA_VERSION, utils::init_env_logger};
+use console::style;
+use counterexample::{get_test_sources, write_counterexamples};
+use csv_export::write_results_csv;
+use dependency_graph::write_test_dependency_graph;
+use evm_arithmetization::StarkConfig;
+use exit_gates::{ExitGateOptions, ExitGates};
+use flaky_tests::{mark_flaky_tests, output_flaky_test_report_for_terminal};
+use flame_graph::FlameGraphProfiler;
 use futures::executor::block_on;
+use input_size_stats::{get_test_file_sizes, output_size_stats_for_terminal};
+use io_trace::enable_io_trace;
 use log::info;
+use opcode_blacklist::find_blacklisted_opcode;
+use opcode_statistics::write_opcode_statistics;
 use persistent_run_state::{
     load_blacklist, load_existing_pass_state_from_disk_if_exists_or_create,
+    load_watched_pass_state_from_disk_if_exists_or_create, PassState,
+};
+use plonky2_runner::{
+    get_evm_err_msgs, get_skipped_tests, run_plonky2_tests, RunAborted, SkipReason, TestQueue,
+    TestRunConfig,
 };
-use plonky2_runner::run_plonky2_tests;
-use report_generation::output_test_report_for_terminal;
-use test_dir_reading::{get_default_parsed_tests_path, read_in_all_parsed_tests};
+use prover_config::load_stark_config;
+use report_generation::{
+    output_alloc_report_for_terminal, output_error_categories_for_terminal,
+    output_proof_size_report_for_terminal, output_skipped_tests_for_terminal,
+    output_test_report_for_terminal, ErrorReportOptions, SkipOptions,
+};
+use resource_monitor::ResourceMonitor;
+use run_time_estimate::print_total_time_estimate;
+use smoke_test::smoke_tests_passed;
+use subcommands::{run_command, run_tests_from_stdin, TestSource};
+use test_dir_reading::{
+    find_schema_version_mismatches, get_default_parsed_tests_path, read_in_all_parsed_tests,
+    read_in_all_parsed_tests_from_ndjson,
+};
+use test_fetching::fetch_test_archive;
+use test_isolation::PreloadedCbor;
+use test_metadata_report::is_txn_type;
+use test_plan::write_test_plan;
+use timing_history::append_to_timing_history;
 use tokio::{
     runtime::{self},
     sync::mpsc,
 };
+use version_info::print_version_info;
 
 use crate::report_generation::write_overall_status_report_summary_to_file;
 
+mod alloc_tracing;
 mod arg_parsing;
+mod checkpoint;
+mod ci_annotations;
+#[cfg(feature = "notify")]
+mod completion_notification;
+mod counterexample;
+mod csv_export;
+mod dependency_graph;
+mod exit_gates;
+mod flaky_tests;
+mod flame_graph;
+#[cfg(feature = "gsheet")]
+mod gsheet_export;
+mod input_size_stats;
+mod io_trace;
+mod merkle_proofs;
+mod opcode_blacklist;
+mod opcode_statistics;
 mod persistent_run_state;
 mod plonky2_runner;
+mod proof_json;
+mod proof_serialization;
+mod proof_structure;
+mod prover_config;
+mod public_values;
 mod report_generation;
+mod resource_monitor;
+mod run_time_estimate;
+mod sanity_check;
+mod smoke_test;
+mod subcommands;
 mod test_dir_reading;
+mod test_fetching;
+mod test_isolation;
+mod test_metadata_report;
+mod test_plan;
+mod timing_history;
+mod txn_details;
+mod version_info;
+mod work_stealing;
+
+/// The number of tests to list in the allocation report with `--trace-malloc`.
+const NUM_TOP_ALLOCATING_TESTS_TO_REPORT: usize = 20;
+
+/// Tests slower than this percentile of all tests in their last run are skipped
+/// with `--auto-skip-slow`.
+const AUTO_SKIP_SLOW_PERCENTILE: f64 = 0.99;
 
 // Oneshot is ideal here, but I can't get it to the abort handler.
 pub(crate) type ProcessAbortedRecv = mpsc::Receiver<()>;
 
 fn main() -> anyhow::Result<()> {
-    init_env_logger();
+    let p_args = ProgArgs::parse();
+    init_env_logger(p_args.log_level.map(|l| l.to_level_filter()));
+
+    #[cfg(feature = "notify")]
+    let notify_on_completion = p_args.notify_on_completion;
 
     let rt = runtime::Builder::new_multi_thread()
         .enable_all()
         .build()
         .expect("Creating Tokio runtime");
-    let res = rt.block_on(run());
+    let res = rt.block_on(run(p_args));
+
+    // Every way out of `run` ends up here, so that failed and interrupted runs
+    // are notified too.
+    #[cfg(feature = "notify")]
+    if notify_on_completion {
+        completion_notification::notify_run_finished(&res);
+    }
 
     match res {
         // True if we exited without an error but need to stop any Plonky2 threads.
@@ -51,10 +150,11 @@ fn main() -> anyhow::Result<()> {
     res.map(|_| ())
 }
 
-async fn run() -> anyhow::Result<bool> {
-    let abort_recv = init_ctrl_c_handler();
+async fn run(p_args: ProgArgs) -> anyhow::Result<bool> {
+    let mut abort_recv = init_ctrl_c_handler();
 
     let ProgArgs {
+        command,
         test_filter,
         report_type,
         variant_filter,
@@ -63,52 +163,265 @@ async fn run() -> anyhow::Result<bool> {
         test_timeout,
         blacklist_path,
         simple_progress_indicator,
+        output_transaction_details,
         update_persistent_state_from_upstream,
-    } = ProgArgs::parse();
-    let mut persistent_test_state = load_existing_pass_state_from_disk_if_exists_or_create();
+        watch_persistent_state,
+        test_runner_version_in_state,
+        clear_stale_state,
+        gas_limit_override,
+        max_cbor_size,
+        opcode_blacklist,
+        test_type,
+        opcode_statistics,
+        test_dependency_graph,
+        verify_hashes,
+        test_ndjson,
+        network_fetch_tests,
+        checkpoint: checkpoint_dir,
+        checkpoint_interval,
+        resume_checkpoint,
+        async_state_writes,
+        split_output_by_status,
+        output_subgroup_reports,
+        output_csv,
+        csv_bom,
+        emit_counterexample,
+        aggregate_errors,
+        verbose_errors,
+        group_failures_by_error_type,
+        pretty_print_errors,
+        ci_annotations,
+        expected_pass_rate,
+        min_passing_tests,
+        expected_max_fail_rate,
+        timeout_is_failure,
+        assert_no_skipped,
+        assert_subgroup_coverage,
+        explain_skipped,
+        expected_new_passes,
+        fail_on_new_evm_errors,
+        test_version_check,
+        verify_cbor_schema_version,
+        emit_test_plan,
+        estimate_total_time,
+        log_level: _,
+        version_info,
+        test_stdin,
+        smoke_test_first,
+        auto_skip_slow,
+        reset_auto_skip,
+        noop_proof_for_passing,
+        fail_if_persistent_state_missing,
+        noop_proof_min_passes,
+        test_subset_random,
+        seed,
+        worker_count,
+        parallel_subgroups,
+        track_flaky_tests,
+        strict_gas_accounting,
+        abort_on_panic,
+        no_prioritise,
+        test_isolation,
+        preload_cbor,
+        check_determinism,
+        output_merkle_proofs,
+        output_public_values,
+        log_proof_structure,
+        output_proof_json,
+        output_all_proofs,
+        output_proof_size,
+        output_size_stats,
+        output_receipt_root_diff,
+        prover_config_file,
+        max_subgroup_failures,
+        early_abort_pattern,
+        mock_prove,
+        persist_timing_history,
+        trace_io,
+        monitor_state_drift,
+        output_flame_graph,
+        #[cfg(feature = "jemallocator")]
+        trace_malloc,
+        #[cfg(feature = "notify")]
+        notify_on_completion,
+        #[cfg(feature = "gsheet")]
+        export_to_gsheet,
+        #[cfg(feature = "gsheet")]
+        gsheet_credentials,
+    } = p_args;
+
+    #[cfg(not(feature = "jemallocator"))]
+    let trace_malloc = false;
+
+    if version_info {
+        print_version_info();
+        return Ok(false);
+    }
+
+    if trace_io {
+        enable_io_trace()?;
+    }
+
+    let mut run_config = TestRunConfig {
+        simple_progress_indicator,
+        output_transaction_details,
+        output_proof_size,
+        output_receipt_root_diff,
+        witness_only,
+        test_timeout: test_timeout.map(|t| t.into()),
+        gas_limit_override,
+        trace_malloc,
+        strict_gas_accounting,
+        abort_on_panic,
+        test_isolation,
+        preloaded_cbor: None,
+        check_determinism,
+        merkle_proofs_dir: output_merkle_proofs,
+        public_values_dir: output_public_values,
+        proof_structure_dir: log_proof_structure,
+        proof_json_dir: output_proof_json,
+        output_all_proofs,
+        stark_config: match &prover_config_file {
+            Some(path) => load_stark_config(path)?,
+            None => StarkConfig::standard_fast_config(),
+        },
+        prover_config_file,
+        test_version_check,
+        max_subgroup_failures,
+        early_abort_pattern,
+        mock_prove,
+        worker_count,
+        parallel_subgroups,
+    };
+
+    if let Some(command) = command {
+        let test_source = TestSource {
+            test_ndjson: test_ndjson.as_deref(),
+            network_fetch_tests: network_fetch_tests.as_deref(),
+            variant_filter: variant_filter.as_ref(),
+            test_version_check,
+        };
+        run_command(command, test_source, &run_config).await?;
+        return Ok(false);
+    }
+
+    if test_stdin {
+        run_tests_from_stdin(variant_filter, test_version_check, &run_config)?;
+        return Ok(false);
+    }
 
-    let filters_used = test_filter.is_some() || variant_filter.is_some();
+    // Only checked for full runs, as the single test commands (which child
+    // processes use with `--test-isolation`) don't take a filter.
+    if check_determinism && test_filter.is_none() {
+        return Err(anyhow!("`--check-determinism` requires `--test-filter`"));
+    }
 
-    // Load blacklisted tests if any
-    let blacklisted_t_names = if let Some(path) = blacklist_path {
-        load_blacklist(&path)
-            .map_err(|_| anyhow!("Could not retrieve blacklisted test variants"))?
-    } else {
-        HashSet::new()
+    let mut persistent_test_state = match watch_persistent_state {
+        false => load_existing_pass_state_from_disk_if_exists_or_create(),
+        true => load_watched_pass_state_from_disk_if_exists_or_create()?,
     };
 
-    // `ignored_t_names` contains both previously "passed" tests and "blacklisted"
-    // tests, if the corresponding flags are on.
+    if test_runner_version_in_state {
+        persistent_test_state.check_version(clear_stale_state)?;
+    }
+
+    let filters_used = test_filter.is_some() || variant_filter.is_some() || max_cbor_size.is_some();
+
+    // Load blacklisted tests if any. They are read in like any other test and
+    // only skipped once queued, so that they are reported as skipped.
+    let blacklist = match &blacklist_path {
+        Some(path) => load_blacklist(path)
+            .map_err(|_| anyhow!("Could not retrieve blacklisted test variants"))?,
+        None => HashMap::new(),
+    };
+
+    // Load the results of a previous interrupted run if we are resuming from a
+    // checkpoint. Tests that already have a result are not run again.
+    let mut checkpointed_t_names = HashSet::new();
+    let resumed_results = match &checkpoint_dir {
+        Some(dir) => match load_checkpoint_if_exists(dir)? {
+            Some(res) if resume_checkpoint || prompt_resume_from_checkpoint(dir)? => {
+                println!("Resuming from checkpoint...");
+                checkpointed_t_names.extend(checkpointed_test_names(&res));
+                Some(res)
+            }
+            _ => None,
+        },
+        None => None,
+    };
+
+    // `ignored_t_names` contains both previously "passed" tests and tests that
+    // already have a result in the checkpoint, if the corresponding flags are on.
     let ignored_t_names: Option<Arc<HashSet<String>>> = match skip_passed {
         true => {
             let mut passed_t_names: HashSet<String> = persistent_test_state
                 .get_tests_that_have_passed(witness_only)
                 .map(|t| t.to_string())
                 .collect();
-            passed_t_names.extend(blacklisted_t_names);
+            passed_t_names.extend(checkpointed_t_names);
 
             Some(Arc::new(passed_t_names))
         }
         false => {
-            if blacklisted_t_names.is_empty() {
+            if checkpointed_t_names.is_empty() {
                 None
             } else {
-                Some(Arc::new(blacklisted_t_names))
+                Some(Arc::new(checkpointed_t_names))
             }
         }
     };
 
-    let parsed_tests_path = get_default_parsed_tests_path()?;
+    let fetched_tests_path = match &network_fetch_tests {
+        Some(url) => Some(fetch_test_archive(url).await?),
+        None => None,
+    };
+    let parsed_tests_path = || match &fetched_tests_path {
+        Some(path) => Ok(path.clone()),
+        None => get_default_parsed_tests_path(),
+    };
+
+    if verify_cbor_schema_version {
+        let mismatches = find_schema_version_mismatches(&parsed_tests_path()?)?;
+
+        if !mismatches.is_empty() {
+            println!("Parsed tests with a different schema version:");
+            for (path, schema_version) in mismatches.iter() {
+                match schema_version {
+                    Some(v) => println!("  {:?} (version {})", path, v),
+                    None => println!("  {:?} (unreadable version)", path),
+                }
+            }
 
-    let parsed_tests = Rc::new(
-        read_in_all_parsed_tests(
-            &parsed_tests_path,
+            return Err(anyhow!(
+                "{} parsed tests do not have schema version {}. Re-run the parser from the same \
+                 checkout as the runner.",
+                mismatches.len(),
+                PARSED_TEST_SCHEMA_VERSION
+            ));
+        }
+    }
+
+    let parsed_tests = Rc::new(match &test_ndjson {
+        Some(ndjson_path) => read_in_all_parsed_tests_from_ndjson(
+            ndjson_path,
             test_filter.clone(),
-            variant_filter,
-            ignored_t_names,
-        )
-        .await?,
-    );
+            variant_filter.clone(),
+            ignored_t_names.clone(),
+            test_version_check,
+        )?,
+        None => {
+            read_in_all_parsed_tests(
+                &parsed_tests_path()?,
+                test_filter.clone(),
+                variant_filter.clone(),
+                ignored_t_names.clone(),
+                test_version_check,
+                max_cbor_size,
+                verify_hashes,
+            )
+            .await?
+        }
+    });
 
     if update_persistent_state_from_upstream {
         println!("Updating persisted test pass state from locally downloaded tests...");
@@ -120,7 +433,27 @@ async fn run() -> anyhow::Result<bool> {
             // If filters are used, then we need to reparse the tests.
             // `add_remove_entries_from_upstream_tests` requires all the tests in the test directory
             // in order to function correctly.
-            true => Rc::new(read_in_all_parsed_tests(&parsed_tests_path, None, None, None).await?),
+            true => Rc::new(match &test_ndjson {
+                Some(ndjson_path) => read_in_all_parsed_tests_from_ndjson(
+                    ndjson_path,
+                    None,
+                    None,
+                    None,
+                    test_version_check,
+                )?,
+                None => {
+                    read_in_all_parsed_tests(
+                        &parsed_tests_path()?,
+                        None,
+                        None,
+                        None,
+                        test_version_check,
+                        None,
+                        false,
+                    )
+                    .await?
+                }
+            }),
         };
 
         let t_names = parsed_tests
@@ -138,34 +471,419 @@ async fn run() -> anyhow::Result<bool> {
     // Remove the Rc since we no longer need it.
     let parsed_tests = Rc::try_unwrap(parsed_tests).unwrap();
 
-    let test_res = match run_plonky2_tests(
+    if opcode_statistics {
+        write_opcode_statistics(&parsed_tests)?;
+    }
+
+    if let Some(path) = &test_dependency_graph {
+        write_test_dependency_graph(path, &parsed_tests)?;
+    }
+
+    // Load the baseline up front so that a bad path does not waste a run.
+    let baseline_evm_err_msgs = fail_on_new_evm_errors
+        .as_deref()
+        .map(load_results_file)
+        .transpose()?
+        .map(|res| get_evm_err_msgs(&res));
+
+    // The state is updated as tests run, so remember which tests failed before
+    // this run.
+    let previously_failed_t_names = expected_new_passes
+        .is_some()
+        .then(|| persistent_test_state.get_tests_that_have_failed());
+
+    let t_paths =
+        (ci_annotations && running_in_github_actions()).then(|| get_test_paths(&parsed_tests));
+    let t_sources = emit_counterexample
+        .is_some()
+        .then(|| get_test_sources(&parsed_tests));
+    let t_file_sizes = output_size_stats.then(|| get_test_file_sizes(&parsed_tests));
+
+    let mut test_queue = TestQueue::new(
         parsed_tests,
-        simple_progress_indicator,
-        &mut persistent_test_state,
-        abort_recv,
-        witness_only,
-        test_timeout.map(|t| t.into()),
+        (!no_prioritise).then_some(&persistent_test_state),
+    );
+
+    if let Some(path) = &blacklist_path {
+        test_queue.skip_blacklisted_tests(path, &blacklist);
+    }
+
+    if !test_type.is_empty() {
+        test_queue.retain_tests_by(|t| {
+            test_type
+                .iter()
+                .any(|ty| is_txn_type(t.info.gen_inputs.signed_txn.as_deref(), *ty))
+        });
+    }
+
+    if reset_auto_skip {
+        println!("Clearing recorded test durations...");
+        persistent_test_state.clear_durations();
+    }
+
+    if fail_if_persistent_state_missing {
+        let not_previously_run_t_names: HashSet<String> = test_queue
+            .test_names()
+            .filter(|t_name| !persistent_test_state.has_previous_run(t_name))
+            .map(|t_name| t_name.to_string())
+            .collect();
+        println!(
+            "Failing {} tests that have never been run before...",
+            not_previously_run_t_names.len()
+        );
+
+        test_queue.fail_tests_not_previously_run(&not_previously_run_t_names);
+    }
+
+    if auto_skip_slow {
+        let slow_t_names = persistent_test_state.get_slow_tests(AUTO_SKIP_SLOW_PERCENTILE);
+        println!("Skipping {} slow tests...", slow_t_names.len());
+
+        test_queue.skip_tests(&slow_t_names, SkipReason::AutoSkippedSlow);
+    }
+
+    if !opcode_blacklist.is_empty() {
+        test_queue.skip_tests_by(|t| {
+            find_blacklisted_opcode(&t.info.gen_inputs, &opcode_blacklist)
+                .map(SkipReason::BlacklistedOpcode)
+        });
+    }
+
+    if noop_proof_for_passing {
+        let passing_t_names = persistent_test_state
+            .get_consistently_passing_tests(noop_proof_min_passes, witness_only);
+        println!(
+            "{}",
+            style(format!(
+                "Warning: passing {} tests that passed in their last {} runs without proving \
+                 them (--noop-proof-for-passing). Regressions in these tests will not be \
+                 detected!",
+                passing_t_names.len(),
+                noop_proof_min_passes
+            ))
+            .yellow()
+            .bold()
+        );
+
+        test_queue.pass_tests_without_proving(&passing_t_names, witness_only);
+    }
+
+    if let Some(n) = test_subset_random {
+        let seed = seed.unwrap_or_else(rand::random);
+        println!("Randomly selecting {} tests (seed: {})...", n, seed);
+
+        test_queue.retain_random_sample(n, seed);
+        for t_name in test_queue.test_names() {
+            info!("Selected {}", t_name);
+        }
+    }
+
+    let smoke_test_queue = smoke_test_first.map(|n| {
+        let seed = seed.unwrap_or_else(rand::random);
+        println!("Running {} smoke tests first (seed: {})...", n, seed);
+
+        test_queue.split_off_random_sample(n, seed)
+    });
+
+    if let Some(path) = &emit_test_plan {
+        // Smoke tests run before everything else.
+        let t_names = smoke_test_queue
+            .iter()
+            .flat_map(|q| q.test_names())
+            .chain(test_queue.test_names());
+        write_test_plan(path, t_names)?;
+    }
+
+    if estimate_total_time {
+        let t_names = smoke_test_queue
+            .iter()
+            .flat_map(|q| q.test_names())
+            .chain(test_queue.test_names());
+        print_total_time_estimate(t_names, &persistent_test_state, worker_count);
+        return Ok(false);
+    }
+
+    if preload_cbor {
+        let t_paths = smoke_test_queue
+            .iter()
+            .flat_map(|q| q.test_paths())
+            .chain(test_queue.test_paths());
+        let preloaded_cbor = PreloadedCbor::read(t_paths)?;
+
+        println!(
+            "Preloaded {} parsed test files ({:.1} MiB)",
+            preloaded_cbor.num_files(),
+            preloaded_cbor.num_bytes() as f64 / (1024.0 * 1024.0)
+        );
+        run_config.preloaded_cbor = Some(preloaded_cbor);
+    }
+
+    // Stops sampling when dropped, including on early returns.
+    let resource_monitor = monitor_state_drift
+        .map(|secs| ResourceMonitor::start(Duration::from_secs(secs)))
+        .transpose()?;
+
+    let flame_graph_profiler = output_flame_graph
+        .map(FlameGraphProfiler::start)
+        .transpose()?;
+
+    let checkpoint = checkpoint_dir.as_ref().map(|dir| {
+        Checkpoint::new(
+            dir,
+            checkpoint_interval,
+            resumed_results.clone().unwrap_or_default(),
+            async_state_writes,
+        )
+    });
+
+    #[cfg(feature = "gsheet")]
+    let run_start = std::time::Instant::now();
+
+    let smoke_test_res = match smoke_test_queue {
+        Some(smoke_test_queue) => {
+            // The smoke tests are not part of the main queue, so they are recorded
+            // once the full run goes ahead.
+            let res = match run_plonky2_tests(
+                smoke_test_queue,
+                run_config.clone(),
+                None,
+                &mut abort_recv,
+                None,
+            ) {
+                Ok(r) => r,
+                Err(aborted) => {
+                    persistent_test_state.write_to_disk();
+                    return aborted_run_result(aborted);
+                }
+            };
+
+            if !smoke_tests_passed(&res) {
+                persistent_test_state.write_to_disk();
+                return Err(anyhow!("Smoke tests failed in the prover"));
+            }
+            if !mock_prove {
+                persistent_test_state.record_results(&res);
+            }
+
+            Some(res)
+        }
+        None => None,
+    };
+
+    let test_res = match run_plonky2_tests(
+        test_queue,
+        run_config.clone(),
+        // Mock results say nothing about the prover, so they are not recorded.
+        (!mock_prove).then_some(&mut persistent_test_state),
+        &mut abort_recv,
+        checkpoint,
     ) {
         Ok(r) => r,
-        Err(_) => {
+        Err(aborted) => {
             persistent_test_state.write_to_disk();
-            return Ok(true);
+            return aborted_run_result(aborted);
+        }
+    };
+
+    let mut test_res = match smoke_test_res {
+        Some(smoke_test_res) => merge_test_results(test_res, smoke_test_res),
+        None => test_res,
+    };
+
+    if track_flaky_tests {
+        println!("Running the tests a second time to detect flaky tests...");
+
+        let parsed_tests = match &test_ndjson {
+            Some(ndjson_path) => read_in_all_parsed_tests_from_ndjson(
+                ndjson_path,
+                test_filter.clone(),
+                variant_filter,
+                ignored_t_names,
+                test_version_check,
+            )?,
+            None => {
+                read_in_all_parsed_tests(
+                    &parsed_tests_path()?,
+                    test_filter.clone(),
+                    variant_filter,
+                    ignored_t_names,
+                    test_version_check,
+                    max_cbor_size,
+                    verify_hashes,
+                )
+                .await?
+            }
+        };
+
+        let mut test_queue = TestQueue::new(
+            parsed_tests,
+            (!no_prioritise).then_some(&persistent_test_state),
+        );
+
+        if let Some(path) = &blacklist_path {
+            test_queue.skip_blacklisted_tests(path, &blacklist);
+        }
+
+        // Only the first run is recorded in the persistent state, so that each test
+        // counts as run once. Flaky tests are marked as failed below.
+        let second_test_res =
+            match run_plonky2_tests(test_queue, run_config.clone(), None, &mut abort_recv, None) {
+                Ok(r) => r,
+                Err(aborted) => {
+                    persistent_test_state.write_to_disk();
+                    return aborted_run_result(aborted);
+                }
+            };
+
+        // Make sure that flaky tests do not show up as passed in the persistent
+        // state just because they happened to pass in the second run.
+        for t_name in mark_flaky_tests(&mut test_res, second_test_res) {
+            persistent_test_state.override_last_pass_state(&t_name, PassState::Failed);
         }
+
+        output_flaky_test_report_for_terminal(&test_res);
+    }
+
+    drop(resource_monitor);
+
+    if let Some(profiler) = flame_graph_profiler {
+        profiler.write_flame_graph()?;
+    }
+
+    #[cfg(feature = "gsheet")]
+    let run_duration = run_start.elapsed();
+
+    let test_res = match resumed_results {
+        Some(prev_res) => merge_test_results(prev_res, test_res),
+        None => test_res,
     };
 
+    #[cfg(feature = "notify")]
+    if notify_on_completion {
+        completion_notification::record_run_results(&test_res);
+    }
+
+    // The run completed, so there is nothing left to resume.
+    if let Some(dir) = &checkpoint_dir {
+        remove_checkpoint(dir)?;
+    }
+
+    if let Some(dir) = &split_output_by_status {
+        write_results_split_by_status(dir, &test_res)?;
+    }
+
+    if let Some(dir) = &output_subgroup_reports {
+        write_results_split_by_subgroup(dir, &test_res)?;
+    }
+
+    if let Some(path) = &output_csv {
+        write_results_csv(path, &test_res, csv_bom)?;
+    }
+
+    if let (Some(dir), Some(t_sources)) = (&emit_counterexample, &t_sources) {
+        let num_written = write_counterexamples(dir, &test_res, t_sources, &run_config)?;
+        println!("Wrote {} counterexamples to {:?}", num_written, dir);
+    }
+
+    if trace_malloc {
+        output_alloc_report_for_terminal(&test_res, NUM_TOP_ALLOCATING_TESTS_TO_REPORT);
+    }
+
+    if output_proof_size {
+        output_proof_size_report_for_terminal(&test_res);
+    }
+
+    if let Some(t_file_sizes) = &t_file_sizes {
+        output_size_stats_for_terminal(&test_res, t_file_sizes);
+    }
+
+    if persist_timing_history {
+        append_to_timing_history(&test_res)?;
+    }
+
+    #[cfg(feature = "gsheet")]
+    if let (Some(spreadsheet_id), Some(credentials_path)) = (&export_to_gsheet, &gsheet_credentials)
+    {
+        gsheet_export::export_run_summary_to_gsheet(
+            spreadsheet_id,
+            credentials_path,
+            &test_res,
+            run_duration,
+        )
+        .await?;
+    }
+
+    let skipped_tests = (explain_skipped || verify_hashes).then(|| get_skipped_tests(&test_res));
+    let exit_gates = ExitGates::new(
+        &test_res,
+        ExitGateOptions {
+            expected_pass_rate,
+            min_passing_tests,
+            expected_max_fail_rate,
+            timeout_is_failure,
+            assert_no_skipped,
+            assert_subgroup_coverage,
+            expected_new_passes,
+        },
+        previously_failed_t_names,
+        baseline_evm_err_msgs,
+    );
+
+    let err_opts = ErrorReportOptions {
+        aggregate_errors,
+        verbose_errors,
+        pretty_print_errors,
+    };
+
+    if let Some(t_paths) = &t_paths {
+        emit_failure_annotations(&test_res, t_paths);
+    }
+
+    if group_failures_by_error_type {
+        output_error_categories_for_terminal(&test_res);
+    }
+
     match report_type {
         ReportType::Test => {
             info!("Outputting test results to stdout...");
-            output_test_report_for_terminal(&test_res, test_filter.clone());
+            output_test_report_for_terminal(&test_res, test_filter.clone(), err_opts);
         }
         ReportType::Summary => {
             info!("Generating test results markdown...");
-            write_overall_status_report_summary_to_file(test_res)?;
+            write_overall_status_report_summary_to_file(test_res, err_opts)?;
+        }
+    }
+
+    if explain_skipped && let Some(skipped_tests) = &skipped_tests {
+        let skip_opts = SkipOptions {
+            max_cbor_size,
+            max_subgroup_failures,
+        };
+        output_skipped_tests_for_terminal(skipped_tests, skip_opts);
+    }
+
+    if verify_hashes && let Some(skipped_tests) = &skipped_tests {
+        let corrupted_t_names: Vec<_> = skipped_tests
+            .iter()
+            .filter(|(_, reason)| matches!(reason, SkipReason::CorruptedInput))
+            .map(|(t_name, _)| t_name)
+            .collect();
+
+        if !corrupted_t_names.is_empty() {
+            println!(
+                "\nTests with corrupted inputs ({}):",
+                corrupted_t_names.len()
+            );
+            for t_name in corrupted_t_names {
+                println!("  {}", t_name);
+            }
         }
     }
 
     persistent_test_state.write_to_disk();
 
+    exit_gates.check()?;
+
     Ok(false)
 }
 
@@ -180,3 +898,17 @@ fn init_ctrl_c_handler() -> ProcessAbortedRecv {
 
     recv
 }
+
+/// Runs that were interrupted stop without an error, while runs stopped by
+/// `--early-abort-pattern` fail.
+fn aborted_run_result(aborted: RunAborted) -> anyhow::Result<bool> {
+    match aborted {
+        RunAborted::Interrupted => Ok(true),
+        RunAborted::EarlyAbortPattern { t_name, err_msg } => Err(anyhow!(
+            "Aborted the run because the test {} failed with an EVM error matching \
+             `--early-abort-pattern`: {}",
+            t_name,
+            err_msg
+        )),
+    }
+}
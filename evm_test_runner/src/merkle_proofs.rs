@@ -0,0 +1,61 @@
+//! Writes the trie roots committed to by the proof of each passing test with
+//! `--output-merkle-proofs`, for external tools that audit the proofs.
+//!
+//! The public values of a proof only contain the roots of the tries, not any
+//! Merkle paths. The roots before the transaction are the roots of the
+//! (partial) tries in the test inputs, which can be used to check any path
+//! into the pre-state.
+
+use std::{fs, path::Path};
+
+use anyhow::Context;
+use ethereum_types::H256;
+use evm_arithmetization::proof::{PublicValues, TrieRoots};
+use serde::Serialize;
+
+use crate::io_trace::{trace_io, IoOp};
+
+#[derive(Debug, Serialize)]
+struct TestTrieRoots {
+    test_name: String,
+    trie_roots_before: TrieRootsOutput,
+    trie_roots_after: TrieRootsOutput,
+}
+
+#[derive(Debug, Serialize)]
+struct TrieRootsOutput {
+    state_root: H256,
+    transactions_root: H256,
+    receipts_root: H256,
+}
+
+impl From<&TrieRoots> for TrieRootsOutput {
+    fn from(v: &TrieRoots) -> Self {
+        Self {
+            state_root: v.state_root,
+            transactions_root: v.transactions_root,
+            receipts_root: v.receipts_root,
+        }
+    }
+}
+
+/// Writes the trie roots of the public values of a proof to
+/// `<dir>/<test_name>.merkle.json`.
+pub(crate) fn write_trie_roots(
+    dir: &Path,
+    test_name: &str,
+    public_values: &PublicValues,
+) -> anyhow::Result<()> {
+    let roots = TestTrieRoots {
+        test_name: test_name.to_string(),
+        trie_roots_before: (&public_values.trie_roots_before).into(),
+        trie_roots_after: (&public_values.trie_roots_after).into(),
+    };
+
+    fs::create_dir_all(dir).with_context(|| format!("Creating directory {:?}", dir))?;
+
+    let path = dir.join(format!("{}.merkle.json", test_name));
+    let json = serde_json::to_string_pretty(&roots)?;
+    trace_io(IoOp::Write, &path, || fs::write(&path, json))
+        .with_context(|| format!("Writing trie roots to {:?}", path))
+}
@@ -0,0 +1,81 @@
+//! Finds tests that use an opcode passed to `--opcode-blacklist`, so that they
+//! can be skipped instead of proven when the opcode is known to be
+//! unsupported.
+
+use evm_arithmetization::generation::GenerationInputs;
+
+// The opcodes that are followed by immediate data, which must not be mistaken
+// for opcodes.
+const PUSH1: u8 = 0x60;
+const PUSH32: u8 = 0x7f;
+
+/// Returns the first opcode of `blacklist` found in the code of any contract
+/// in `inputs`.
+pub(crate) fn find_blacklisted_opcode(inputs: &GenerationInputs, blacklist: &[u8]) -> Option<u8> {
+    inputs
+        .contract_code
+        .values()
+        .find_map(|code| find_opcode_in_code(code, blacklist))
+}
+
+fn find_opcode_in_code(code: &[u8], blacklist: &[u8]) -> Option<u8> {
+    code_opcodes(code).find(|opcode| blacklist.contains(opcode))
+}
+
+/// Iterates over the opcodes of `code`, skipping any immediate data.
+pub(crate) fn code_opcodes(code: &[u8]) -> impl Iterator<Item = u8> + '_ {
+    let mut pc = 0;
+
+    std::iter::from_fn(move || {
+        let opcode = *code.get(pc)?;
+        pc += match opcode {
+            PUSH1..=PUSH32 => (opcode - PUSH1) as usize + 2,
+            _ => 1,
+        };
+
+        Some(opcode)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{code_opcodes, find_opcode_in_code};
+
+    #[test]
+    fn code_opcodes_skips_push_immediates() {
+        // PUSH1 0x5f, PUSH2 0x4949, ADD
+        let code = [0x60, 0x5f, 0x61, 0x49, 0x49, 0x01];
+
+        assert_eq!(code_opcodes(&code).collect::<Vec<_>>(), [0x60, 0x61, 0x01]);
+    }
+
+    #[test]
+    fn code_opcodes_skips_push32_immediate() {
+        let mut code = vec![0x7f];
+        code.extend([0x5f; 32]);
+        code.push(0x00);
+
+        assert_eq!(code_opcodes(&code).collect::<Vec<_>>(), [0x7f, 0x00]);
+    }
+
+    #[test]
+    fn code_opcodes_stops_at_truncated_push() {
+        // PUSH3 with only one byte of immediate data left.
+        let code = [0x01, 0x62, 0x5f];
+
+        assert_eq!(code_opcodes(&code).collect::<Vec<_>>(), [0x01, 0x62]);
+    }
+
+    #[test]
+    fn code_opcodes_of_empty_code() {
+        assert_eq!(code_opcodes(&[]).count(), 0);
+    }
+
+    #[test]
+    fn find_opcode_in_code_ignores_push_immediates() {
+        let code = [0x60, 0x5f, 0x01];
+
+        assert_eq!(find_opcode_in_code(&code, &[0x5f]), None);
+        assert_eq!(find_opcode_in_code(&code, &[0x5f, 0x01]), Some(0x01));
+    }
+}
@@ -1,41 +1,206 @@
 use std::{
     collections::{HashMap, HashSet},
-    fs::File,
-    io::{BufRead, BufReader, Result as IoResult},
-    path::PathBuf,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, ErrorKind, Read, Result as IoResult, Seek},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
 };
 
+use anyhow::Context;
 use chrono::{DateTime, Utc};
-use log::info;
+use fs2::FileExt;
+use log::{info, warn};
 use serde::{Deserialize, Serialize};
 
-use crate::plonky2_runner::TestStatus;
+use crate::{
+    io_trace::{trace_io, IoOp},
+    plonky2_runner::{TestGroupRunResults, TestStatus},
+    version_info::PLONKY2_VERSION,
+};
 
 const PASS_STATE_PATH_STR: &str = "test_pass_state.csv";
 
+/// Stored next to the state file, as the CSV can not hold anything but entries.
+const PASS_STATE_VERSION_PATH_STR: &str = "test_pass_state_version.json";
+
+/// The versions that the persistent state was last written with (see
+/// `--test-runner-version-in-state`).
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+struct StateVersion {
+    evm_test_runner: String,
+    plonky2: String,
+}
+
+impl StateVersion {
+    fn current() -> Self {
+        Self {
+            evm_test_runner: env!("CARGO_PKG_VERSION").to_string(),
+            plonky2: PLONKY2_VERSION.to_string(),
+        }
+    }
+}
+
 #[derive(Debug, Default)]
-pub(crate) struct TestRunEntries(HashMap<String, RunEntry>);
+pub(crate) struct TestRunEntries {
+    entries: HashMap<String, RunEntry>,
+    /// Whether changes made to the state file by other processes are merged
+    /// in before writing it (see `--watch-persistent-state`).
+    watched: bool,
+    /// When the state file was last modified as of reading it in. `None` if
+    /// it did not exist.
+    modified_when_read: Option<SystemTime>,
+    /// Whether the versions of the runner are written along with the state
+    /// (see `--test-runner-version-in-state`).
+    record_version: bool,
+}
 
 impl TestRunEntries {
     pub(crate) fn write_to_disk(self) {
         println!("Persisting test pass state to disk...");
 
+        if self.record_version
+            && let Err(err) = write_state_version()
+        {
+            warn!("Unable to write the test pass state version: {:#}", err);
+        }
+
+        if self.watched {
+            // Falling back to overwriting the file could drop the changes of
+            // other processes, so the state of this run is lost instead.
+            if let Err(err) = self.merge_and_write_to_disk() {
+                warn!("Unable to write the test pass state: {:#}", err);
+            }
+            return;
+        }
+
         let data = self.into_serializable();
-        let mut writer = csv::Writer::from_path(PASS_STATE_PATH_STR).unwrap();
+        let mut writer = trace_io(IoOp::Create, Path::new(PASS_STATE_PATH_STR), || {
+            csv::Writer::from_path(PASS_STATE_PATH_STR)
+        })
+        .unwrap();
 
         for entry in data {
             writer.serialize(entry).unwrap();
         }
     }
 
+    /// Locks the state file and, if another process wrote to it since it was
+    /// read in, merges its entries in before overwriting it. For each test,
+    /// whichever entry has the latest run wins.
+    fn merge_and_write_to_disk(mut self) -> anyhow::Result<()> {
+        let path = Path::new(PASS_STATE_PATH_STR);
+        let mut file = trace_io(IoOp::Open, path, || {
+            OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(path)
+        })
+        .with_context(|| format!("Opening test pass state {:?}", path))?;
+        FileExt::lock_exclusive(&file)
+            .with_context(|| format!("Locking test pass state {:?}", path))?;
+
+        if file.metadata()?.modified().ok() != self.modified_when_read {
+            println!("The test pass state was changed by another process, merging...");
+            self.merge(read_entries(&file)?);
+        }
+
+        file.set_len(0)?;
+        file.rewind()?;
+
+        let mut writer = csv::Writer::from_writer(&file);
+        for entry in self.into_serializable() {
+            writer.serialize(entry)?;
+        }
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Warns if the state was last written by a different version of the
+    /// runner or `plonky2`, as results from before the update may not be
+    /// comparable with the new ones. With `clear_stale`, the state is also
+    /// cleared. The current versions are written along with the state from
+    /// now on.
+    pub(crate) fn check_version(&mut self, clear_stale: bool) -> anyhow::Result<()> {
+        self.record_version = true;
+
+        let Some(prev_version) = read_state_version()? else {
+            return Ok(());
+        };
+
+        let version = StateVersion::current();
+        if prev_version == version {
+            return Ok(());
+        }
+
+        warn!(
+            "The test pass state was written by evm_test_runner {} with plonky2 {}, but this is \
+             evm_test_runner {} with plonky2 {}. Passes and failures from before may not be \
+             comparable with those of this run.",
+            prev_version.evm_test_runner,
+            prev_version.plonky2,
+            version.evm_test_runner,
+            version.plonky2
+        );
+
+        if clear_stale {
+            println!("Clearing the stale test pass state...");
+            self.entries.clear();
+        }
+
+        Ok(())
+    }
+
+    /// Takes the entry of each test in `other` that ran after ours.
+    fn merge(&mut self, other: TestRunEntries) {
+        for (t_name, other_entry) in other.entries {
+            match self.entries.get(&t_name) {
+                Some(entry) if entry.last_run >= other_entry.last_run => (),
+                _ => {
+                    self.entries.insert(t_name, other_entry);
+                }
+            }
+        }
+    }
+
+    /// Writes the state of every test to `path` as a CSV file meant for
+    /// analysis in a spreadsheet, with a header row.
+    pub(crate) fn export_csv(self, path: &Path) -> anyhow::Result<()> {
+        let mut writer = trace_io(IoOp::Create, path, || csv::Writer::from_path(path))
+            .with_context(|| format!("Creating state CSV file {:?}", path))?;
+
+        for entry in self.into_serializable() {
+            writer
+                .serialize(ExportedRunEntry {
+                    test_name: entry.test_name,
+                    last_status: entry.pass_state,
+                    last_run_timestamp: entry.last_run,
+                    run_count: entry.run_count,
+                    pass_count: entry.pass_count,
+                    fail_count: entry.fail_count,
+                })
+                .with_context(|| "Serializing state CSV entry")?;
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+
     fn into_serializable(self) -> Vec<SerializableRunEntry> {
         let mut data: Vec<_> = self
-            .0
+            .entries
             .into_iter()
             .map(|(test_name, data)| SerializableRunEntry {
                 test_name,
                 pass_state: data.pass_state,
                 last_run: data.last_run,
+                run_count: data.run_count,
+                pass_count: data.pass_count,
+                fail_count: data.fail_count,
+                last_duration_ms: data.last_duration_ms,
+                pass_streak: data.pass_streak,
             })
             .collect();
 
@@ -43,11 +208,94 @@ impl TestRunEntries {
         data
     }
 
-    pub(crate) fn update_test_state(&mut self, t_key: &str, state: PassState) {
-        self.0
+    /// Records the result of a new run of a test, along with how long it took
+    /// (if it was actually run).
+    pub(crate) fn update_test_state(
+        &mut self,
+        t_key: &str,
+        state: PassState,
+        duration: Option<Duration>,
+    ) {
+        self.entries
+            .entry(t_key.to_string())
+            .or_default()
+            .record_run(state, duration);
+    }
+
+    /// Records the results of tests that were run without recording them as
+    /// they completed. Skipped tests are not recorded.
+    pub(crate) fn record_results(&mut self, res: &[TestGroupRunResults]) {
+        for t in res
+            .iter()
+            .flat_map(|g| g.sub_group_res.iter())
+            .flat_map(|sub_g| sub_g.test_res.iter())
+            .filter(|t| !matches!(t.status, TestStatus::Skipped(_)))
+        {
+            self.update_test_state(&t.name, t.status.clone().into(), t.duration);
+        }
+    }
+
+    /// Gets the tests whose last recorded duration is above the given
+    /// percentile (in `[0, 1]`) of the last recorded durations of all tests.
+    pub(crate) fn get_slow_tests(&self, percentile: f64) -> HashSet<String> {
+        let mut durations: Vec<_> = self
+            .entries
+            .values()
+            .filter_map(|entry| entry.last_duration_ms)
+            .collect();
+        if durations.is_empty() {
+            return HashSet::new();
+        }
+
+        durations.sort_unstable();
+        let threshold_idx =
+            ((durations.len() as f64 * percentile).ceil() as usize).clamp(1, durations.len()) - 1;
+        let threshold_ms = durations[threshold_idx];
+
+        self.entries
+            .iter()
+            .filter(|(_, entry)| entry.last_duration_ms.is_some_and(|d| d > threshold_ms))
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// Gets how long the last actual run of a test took, if it was recorded.
+    pub(crate) fn get_last_duration(&self, t_key: &str) -> Option<Duration> {
+        self.entries
+            .get(t_key)
+            .and_then(|entry| entry.last_duration_ms)
+            .map(Duration::from_millis)
+    }
+
+    /// Forgets the last recorded duration of every test, so that no test is
+    /// considered slow until they are run again.
+    pub(crate) fn clear_durations(&mut self) {
+        for entry in self.entries.values_mut() {
+            entry.last_duration_ms = None;
+        }
+    }
+
+    /// Overrides the pass state of the last run of a test, without counting it
+    /// as a new run.
+    pub(crate) fn override_last_pass_state(&mut self, t_key: &str, state: PassState) {
+        self.entries
             .entry(t_key.to_string())
-            .and_modify(|entry| *entry = RunEntry::new(state))
-            .or_insert_with(|| RunEntry::new(state));
+            .or_default()
+            .pass_state = state;
+    }
+
+    /// Whether a test has an entry with at least one recorded run.
+    pub(crate) fn has_previous_run(&self, t_key: &str) -> bool {
+        self.entries
+            .get(t_key)
+            .is_some_and(|entry| entry.run_count > 0)
+    }
+
+    /// Gets the pass state of a test from its last run.
+    pub(crate) fn get_pass_state(&self, t_key: &str) -> PassState {
+        self.entries
+            .get(t_key)
+            .map_or(PassState::NotRun, |entry| entry.pass_state)
     }
 
     pub(crate) fn add_remove_entries_from_upstream_tests<'a>(
@@ -59,15 +307,15 @@ impl TestRunEntries {
 
         // Add any new tests that we don't know about.
         for upstream_k in t_names_that_are_in_upstream.iter() {
-            if !self.0.contains_key(upstream_k) {
-                self.0.insert(upstream_k.clone(), Default::default());
+            if !self.entries.contains_key(upstream_k) {
+                self.entries.insert(upstream_k.clone(), Default::default());
             }
         }
 
         // Remove any entries that are not longer in upstream.
-        for local_k in self.0.keys().cloned().collect::<Vec<_>>() {
+        for local_k in self.entries.keys().cloned().collect::<Vec<_>>() {
             if !t_names_that_are_in_upstream.contains(&local_k) {
-                self.0.remove(local_k.as_str());
+                self.entries.remove(local_k.as_str());
             }
         }
     }
@@ -80,25 +328,65 @@ impl TestRunEntries {
         &self,
         witness_only: bool,
     ) -> impl Iterator<Item = &str> {
-        self.0.iter().filter_map(move |(name, info)| {
+        self.entries.iter().filter_map(move |(name, info)| {
             info.pass_state
                 .get_passed_status(witness_only)
                 .then_some(name.as_str())
         })
     }
+
+    /// Gets the tests that passed in each of their last `min_passes` runs.
+    /// Without `witness_only`, the last run must also have generated a proof.
+    pub(crate) fn get_consistently_passing_tests(
+        &self,
+        min_passes: u64,
+        witness_only: bool,
+    ) -> HashSet<String> {
+        self.entries
+            .iter()
+            .filter(|(_, info)| {
+                info.pass_streak >= min_passes
+                    && match info.pass_state {
+                        PassState::PassedProof => true,
+                        PassState::PassedWitness => witness_only,
+                        _ => false,
+                    }
+            })
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// Gets the tests that failed in their last run.
+    pub(crate) fn get_tests_that_have_failed(&self) -> HashSet<String> {
+        self.entries
+            .iter()
+            .filter(|(_, info)| matches!(info.pass_state, PassState::Failed))
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
 }
 
 impl From<Vec<SerializableRunEntry>> for TestRunEntries {
     fn from(v: Vec<SerializableRunEntry>) -> Self {
-        TestRunEntries(HashMap::from_iter(v.into_iter().map(|e| {
+        let entries = HashMap::from_iter(v.into_iter().map(|e| {
             (
                 e.test_name,
                 RunEntry {
                     pass_state: e.pass_state,
                     last_run: e.last_run,
+                    run_count: e.run_count,
+                    pass_count: e.pass_count,
+                    fail_count: e.fail_count,
+                    last_duration_ms: e.last_duration_ms,
+                    pass_streak: e.pass_streak,
                 },
             )
-        })))
+        }));
+
+        TestRunEntries {
+            entries,
+            ..Default::default()
+        }
     }
 }
 
@@ -132,7 +420,16 @@ impl From<TestStatus> for PassState {
             TestStatus::PassedWitness => PassState::PassedWitness,
             TestStatus::PassedProof => PassState::PassedProof,
             TestStatus::Ignored => PassState::Ignored,
-            TestStatus::EvmErr(_) | TestStatus::TimedOut => PassState::Failed,
+            TestStatus::Skipped(_) | TestStatus::NotPreviouslyRun | TestStatus::MockPassed => {
+                PassState::NotRun
+            }
+            TestStatus::EvmErr(_)
+            | TestStatus::ProofVerificationFailed(_)
+            | TestStatus::IncorrectGasUsage { .. }
+            | TestStatus::TimedOut
+            | TestStatus::Panicked(_)
+            | TestStatus::NonDeterministicProof
+            | TestStatus::Flaky { .. } => PassState::Failed,
         }
     }
 }
@@ -142,41 +439,214 @@ pub(crate) struct SerializableRunEntry {
     test_name: String,
     pass_state: PassState,
     last_run: Option<DateTime<Utc>>,
+    // Defaulted so that state files written before these were tracked can still
+    // be read.
+    #[serde(default)]
+    run_count: u64,
+    #[serde(default)]
+    pass_count: u64,
+    #[serde(default)]
+    fail_count: u64,
+    #[serde(default)]
+    last_duration_ms: Option<u64>,
+    #[serde(default)]
+    pass_streak: u64,
+}
+
+/// The columns of the CSV written by `export-state-csv`.
+#[derive(Debug, Serialize)]
+struct ExportedRunEntry {
+    test_name: String,
+    last_status: PassState,
+    last_run_timestamp: Option<DateTime<Utc>>,
+    run_count: u64,
+    pass_count: u64,
+    fail_count: u64,
 }
 
 #[derive(Debug, Deserialize, Default, Serialize)]
 struct RunEntry {
     pass_state: PassState,
     last_run: Option<DateTime<Utc>>,
+    run_count: u64,
+    pass_count: u64,
+    fail_count: u64,
+    last_duration_ms: Option<u64>,
+    /// The number of consecutive runs (up to the last one) that the test
+    /// passed in.
+    pass_streak: u64,
 }
 
 impl RunEntry {
-    fn new(pass_state: PassState) -> Self {
-        Self {
-            pass_state,
-            last_run: Some(chrono::Utc::now()),
+    fn record_run(&mut self, pass_state: PassState, duration: Option<Duration>) {
+        self.pass_state = pass_state;
+        self.last_run = Some(chrono::Utc::now());
+        self.run_count += 1;
+
+        // Tests that were skipped keep the duration of their last actual run.
+        if let Some(duration) = duration {
+            self.last_duration_ms = Some(duration.as_millis() as u64);
+        }
+
+        match pass_state {
+            PassState::PassedWitness | PassState::PassedProof => {
+                self.pass_count += 1;
+                self.pass_streak += 1;
+            }
+            PassState::Failed => {
+                self.fail_count += 1;
+                self.pass_streak = 0;
+            }
+            PassState::Ignored | PassState::NotRun => (),
         }
     }
 }
 
 pub(crate) fn load_existing_pass_state_from_disk_if_exists_or_create() -> TestRunEntries {
-    csv::Reader::from_path(PASS_STATE_PATH_STR)
-        .map(|mut reader| {
+    trace_io(IoOp::Open, Path::new(PASS_STATE_PATH_STR), || {
+        csv::Reader::from_path(PASS_STATE_PATH_STR)
+    })
+    .map(|mut reader| {
+        info!("Found existing test run state on disk.");
+
+        reader
+            .deserialize()
+            .map(|r| r.unwrap())
+            .collect::<Vec<_>>()
+            .into()
+    })
+    .unwrap_or_else(|_| {
+        info!("No existing test run state found.");
+        TestRunEntries::default()
+    })
+}
+
+/// Like `load_existing_pass_state_from_disk_if_exists_or_create`, but holds a
+/// shared lock on the state file while reading it, and remembers when it was
+/// last modified so that changes made by other processes in the meantime are
+/// merged in when writing it.
+pub(crate) fn load_watched_pass_state_from_disk_if_exists_or_create(
+) -> anyhow::Result<TestRunEntries> {
+    let path = Path::new(PASS_STATE_PATH_STR);
+    let mut state = match trace_io(IoOp::Open, path, || File::open(path)) {
+        Ok(file) => {
             info!("Found existing test run state on disk.");
 
-            reader
-                .deserialize()
-                .map(|r| r.unwrap())
-                .collect::<Vec<_>>()
-                .into()
-        })
-        .unwrap_or_else(|_| {
+            FileExt::lock_shared(&file)
+                .with_context(|| format!("Locking test pass state {:?}", path))?;
+            let mut state = read_entries(&file)?;
+            state.modified_when_read = file.metadata()?.modified().ok();
+            state
+        }
+        Err(err) if err.kind() == ErrorKind::NotFound => {
             info!("No existing test run state found.");
             TestRunEntries::default()
-        })
+        }
+        Err(err) => return Err(err).with_context(|| format!("Opening test pass state {:?}", path)),
+    };
+    state.watched = true;
+
+    Ok(state)
 }
 
-pub(crate) fn load_blacklist(blacklist_file: &PathBuf) -> IoResult<HashSet<String>> {
-    let file = File::open(blacklist_file)?;
-    Ok(BufReader::new(file).lines().map_while(Result::ok).collect())
+fn read_state_version() -> anyhow::Result<Option<StateVersion>> {
+    let path = Path::new(PASS_STATE_VERSION_PATH_STR);
+    let bytes = match trace_io(IoOp::Read, path, || fs::read(path)) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => {
+            return Err(err).with_context(|| format!("Reading test pass state version {:?}", path))
+        }
+    };
+
+    serde_json::from_slice(&bytes)
+        .map(Some)
+        .with_context(|| format!("Deserializing test pass state version {:?}", path))
+}
+
+fn write_state_version() -> anyhow::Result<()> {
+    let path = Path::new(PASS_STATE_VERSION_PATH_STR);
+    let json = serde_json::to_string_pretty(&StateVersion::current())?;
+
+    trace_io(IoOp::Write, path, || fs::write(path, json))
+        .with_context(|| format!("Writing test pass state version {:?}", path))
+}
+
+fn read_entries(reader: impl Read) -> anyhow::Result<TestRunEntries> {
+    let entries = csv::Reader::from_reader(reader)
+        .deserialize()
+        .collect::<Result<Vec<SerializableRunEntry>, _>>()
+        .with_context(|| "Deserializing test pass state")?;
+
+    Ok(entries.into())
+}
+
+/// Loads the test variants listed in a blacklist file, along with the line
+/// (starting at `1`) that each one is on.
+pub(crate) fn load_blacklist(blacklist_file: &PathBuf) -> IoResult<HashMap<String, usize>> {
+    let file = trace_io(IoOp::Open, blacklist_file, || File::open(blacklist_file))?;
+    Ok(BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .enumerate()
+        .map(|(idx, t_name)| (t_name, idx + 1))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, time::Duration};
+
+    use super::{PassState, TestRunEntries};
+
+    /// Tests `t_0` to `t_9`, where `t_i` took `(i + 1) * 100` ms, along with a
+    /// test that has never run.
+    fn entries_with_durations() -> TestRunEntries {
+        let mut entries = TestRunEntries::default();
+        for i in 0..10 {
+            entries.update_test_state(
+                &format!("t_{}", i),
+                PassState::PassedProof,
+                Some(Duration::from_millis((i + 1) * 100)),
+            );
+        }
+        entries.update_test_state("never_run", PassState::NotRun, None);
+
+        entries
+    }
+
+    fn t_names(names: &[&str]) -> HashSet<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn get_slow_tests_above_percentile() {
+        let entries = entries_with_durations();
+
+        assert_eq!(entries.get_slow_tests(0.8), t_names(&["t_8", "t_9"]));
+        assert_eq!(entries.get_slow_tests(0.9), t_names(&["t_9"]));
+    }
+
+    #[test]
+    fn get_slow_tests_at_percentile_bounds() {
+        let entries = entries_with_durations();
+
+        // Every test is at most as slow as the slowest one.
+        assert!(entries.get_slow_tests(1.0).is_empty());
+
+        // Only the fastest test is not slower than the fastest one.
+        let mut all_but_fastest: HashSet<_> = (1..10).map(|i| format!("t_{}", i)).collect();
+        assert_eq!(entries.get_slow_tests(0.0), all_but_fastest);
+
+        all_but_fastest.remove("t_1");
+        assert_eq!(entries.get_slow_tests(0.2), all_but_fastest);
+    }
+
+    #[test]
+    fn get_slow_tests_without_durations() {
+        let mut entries = TestRunEntries::default();
+        entries.update_test_state("never_run", PassState::NotRun, None);
+
+        assert!(entries.get_slow_tests(0.5).is_empty());
+    }
 }
@@ -2,33 +2,75 @@
 //! Essentially converts parsed tests into test results.
 
 use std::{
+    collections::{BTreeSet, HashMap, HashSet},
     fmt::{Debug, Display},
-    time::Duration,
+    mem,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
 };
 
 use common::types::TestVariantRunInfo;
-use ethereum_types::U256;
+use ethereum_types::{H256, U256};
 use evm_arithmetization::{
+    generation::GenerationInputs,
+    proof::AllProof,
     prover::{prove, testing::simulate_execution},
     verifier::verify_proof,
     AllStark, StarkConfig,
 };
 use futures::executor::block_on;
 use indicatif::{ProgressBar, ProgressStyle};
-use log::warn;
+use log::{debug, warn};
 use plonky2::{
     field::goldilocks_field::GoldilocksField, plonk::config::KeccakGoldilocksConfig,
     util::timing::TimingTree,
 };
+use rand::{
+    rngs::StdRng,
+    seq::{index, SliceRandom},
+    SeedableRng,
+};
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use tokio::{select, time::timeout};
 
 use crate::{
-    persistent_run_state::TestRunEntries,
-    test_dir_reading::{ParsedTestGroup, ParsedTestSubGroup, Test},
+    alloc_tracing::AllocStats,
+    checkpoint::Checkpoint,
+    merkle_proofs::write_trie_roots,
+    persistent_run_state::{PassState, TestRunEntries},
+    proof_json::write_proof_json,
+    proof_serialization::SerializableProof,
+    proof_structure::write_proof_structure,
+    public_values::{write_public_values, ExpectedPublicValues},
+    test_dir_reading::{ParsedTestGroup, Test},
+    test_isolation::{run_test_in_child_process, PreloadedCbor},
+    txn_details::format_txn_details,
+    work_stealing::WorkStealingPool,
     ProcessAbortedRecv,
 };
 
-pub(crate) type RunnerResult<T> = Result<T, ()>;
+/// Why a run stopped before all of its tests were run.
+#[derive(Debug)]
+pub(crate) enum RunAborted {
+    /// The process was interrupted (eg. with Ctrl-C).
+    Interrupted,
+    /// A test failed with an EVM error matching `--early-abort-pattern`.
+    EarlyAbortPattern { t_name: String, err_msg: String },
+}
+
+pub(crate) type RunnerResult<T> = Result<T, RunAborted>;
+
+/// How often the parallel runner checks whether the process was aborted while
+/// waiting for tests to complete.
+const ABORT_POLL_INTERVAL: Duration = Duration::from_millis(100);
 
 trait TestProgressIndicator: Debug {
     fn set_current_test_name(&self, t_name: String);
@@ -72,13 +114,38 @@ impl TestProgressIndicator for FancyProgressIndicator {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
 pub(crate) enum TestStatus {
     PassedWitness,
     PassedProof,
     Ignored,
     EvmErr(String),
+    ProofVerificationFailed(String),
+    /// The gas used reported by the proof does not match the gas used in the
+    /// expected block header. Only checked with `--strict-gas-accounting`.
+    IncorrectGasUsage {
+        #[schemars(with = "String")]
+        actual: U256,
+        #[schemars(with = "String")]
+        expected: U256,
+    },
     TimedOut,
+    Panicked(String),
+    /// The test was not run at all.
+    Skipped(SkipReason),
+    /// Passed in only one of two runs with `--track-flaky-tests`.
+    Flaky {
+        first_run: Box<TestStatus>,
+        second_run: Box<TestStatus>,
+    },
+    /// Proving the same inputs twice at the same time gave different proofs.
+    /// Only checked with `--check-determinism`.
+    NonDeterministicProof,
+    /// The test has never been run before, so it was failed without running
+    /// it. Only with `--fail-if-persistent-state-missing`.
+    NotPreviouslyRun,
+    /// Passed without running the prover, with `--mock-prove`.
+    MockPassed,
 }
 
 impl Display for TestStatus {
@@ -88,67 +155,629 @@ impl Display for TestStatus {
             TestStatus::PassedProof => write!(f, "Passed proof verification"),
             TestStatus::Ignored => write!(f, "Ignored"),
             TestStatus::EvmErr(err) => write!(f, "Evm error: {}", err),
+            TestStatus::ProofVerificationFailed(err) => {
+                write!(f, "Proof verification failed: {}", err)
+            }
+            TestStatus::IncorrectGasUsage { actual, expected } => write!(
+                f,
+                "Incorrect gas usage: used {} gas but expected {}",
+                actual, expected
+            ),
             TestStatus::TimedOut => write!(f, "Test timed out"),
+            TestStatus::Panicked(msg) => write!(f, "Prover panicked: {}", msg),
+            TestStatus::Skipped(reason) => write!(f, "Skipped ({})", reason),
+            TestStatus::Flaky {
+                first_run,
+                second_run,
+            } => write!(f, "Flaky ({} / {})", first_run, second_run),
+            TestStatus::NonDeterministicProof => write!(f, "Non-deterministic proof"),
+            TestStatus::NotPreviouslyRun => write!(f, "Not previously run"),
+            TestStatus::MockPassed => write!(f, "Passed (mock prover)"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+pub(crate) enum SkipReason {
+    /// The test's CBOR file is larger than `--max-cbor-size`.
+    InputTooLarge { size_bytes: u64 },
+    /// The test took longer than almost every other test in its last run (see
+    /// `--auto-skip-slow`).
+    AutoSkippedSlow,
+    /// Too many other tests in the same sub-group failed (see
+    /// `--max-subgroup-failures`).
+    SubgroupFailureLimit,
+    /// The test's CBOR file does not match its checksum (see
+    /// `--verify-hashes`).
+    CorruptedInput,
+    /// The test's contract code uses this opcode (see `--opcode-blacklist`).
+    BlacklistedOpcode(u8),
+    /// The test is listed on `line` of the blacklist file at `path` (see
+    /// `--blacklist-path`).
+    Blacklisted { path: PathBuf, line: usize },
+}
+
+impl Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SkipReason::InputTooLarge { size_bytes } => {
+                write!(f, "input too large ({} bytes)", size_bytes)
+            }
+            SkipReason::AutoSkippedSlow => write!(f, "too slow in the last run"),
+            SkipReason::SubgroupFailureLimit => write!(f, "too many failures in sub-group"),
+            SkipReason::CorruptedInput => write!(f, "corrupted input"),
+            SkipReason::BlacklistedOpcode(opcode) => write!(f, "uses opcode {:#04x}", opcode),
+            SkipReason::Blacklisted { path, line } => {
+                write!(f, "blacklisted ({}:{})", path.display(), line)
+            }
         }
     }
 }
 
 impl TestStatus {
     pub(crate) fn passed(&self) -> bool {
-        matches!(self, Self::PassedProof | Self::PassedWitness)
+        matches!(
+            self,
+            Self::PassedProof | Self::PassedWitness | Self::MockPassed
+        )
+    }
+
+    /// Whether the test ran and did not pass or get ignored.
+    pub(crate) fn failed(&self) -> bool {
+        !self.passed() && !matches!(self, Self::Ignored | Self::Skipped(_))
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
 pub(crate) struct TestGroupRunResults {
     pub(crate) name: String,
     pub(crate) sub_group_res: Vec<TestSubGroupRunResults>,
 }
 
-fn num_tests_in_groups<'a>(groups: impl Iterator<Item = &'a ParsedTestGroup> + 'a) -> u64 {
-    groups
-        .map(|g| {
-            g.sub_groups
-                .iter()
-                .flat_map(|sub_g| sub_g.tests.iter())
-                .count() as u64
-        })
-        .sum()
-}
-
-#[derive(Debug)]
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
 pub(crate) struct TestSubGroupRunResults {
     pub(crate) name: String,
     pub(crate) test_res: Vec<TestRunResult>,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
 pub(crate) struct TestRunResult {
     pub(crate) name: String,
     pub(crate) status: TestStatus,
+    /// Heap allocations made while running the test. Only recorded with
+    /// `--trace-malloc`.
+    #[serde(default)]
+    pub(crate) alloc_stats: Option<AllocStats>,
+    /// How long the test took to run. `None` if it was skipped without
+    /// running.
+    #[serde(default)]
+    pub(crate) duration: Option<Duration>,
+    /// The size of the serialized proof of the test. Only recorded with
+    /// `--output-proof-size`.
+    #[serde(default)]
+    pub(crate) proof_size_bytes: Option<u64>,
+}
+
+/// What running a test produced, before it is recorded in a `TestRunResult`.
+#[derive(Debug)]
+pub(crate) struct TestOutcome {
+    pub(crate) status: TestStatus,
+    pub(crate) proof_size_bytes: Option<u64>,
+}
+
+impl From<TestStatus> for TestOutcome {
+    fn from(status: TestStatus) -> Self {
+        Self {
+            status,
+            proof_size_bytes: None,
+        }
+    }
+}
+
+/// The number of tests of a run that passed, failed or were not run.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct TestStatusCounts {
+    pub(crate) passed: usize,
+    pub(crate) failed: usize,
+    /// Ignored tests and tests that were skipped without running.
+    pub(crate) skipped: usize,
+}
+
+impl TestStatusCounts {
+    pub(crate) fn new(res: &[TestGroupRunResults]) -> Self {
+        let mut counts = Self::default();
+
+        for test in res
+            .iter()
+            .flat_map(|g| g.sub_group_res.iter())
+            .flat_map(|sub_g| sub_g.test_res.iter())
+        {
+            match &test.status {
+                s if s.passed() => counts.passed += 1,
+                TestStatus::Ignored | TestStatus::Skipped(_) => counts.skipped += 1,
+                _ => counts.failed += 1,
+            }
+        }
+
+        counts
+    }
+
+    /// The fraction of tests that passed, not counting skipped tests. `None` if
+    /// no tests ran.
+    pub(crate) fn pass_rate(&self) -> Option<f64> {
+        match self.passed + self.failed {
+            0 => None,
+            tot_tests => Some(self.passed as f64 / tot_tests as f64),
+        }
+    }
+
+    /// The fraction of tests that failed, not counting skipped tests. `None` if
+    /// no tests ran.
+    pub(crate) fn fail_rate(&self) -> Option<f64> {
+        self.pass_rate().map(|rate| 1.0 - rate)
+    }
+
+    /// The number of tests that ran, not counting skipped tests.
+    pub(crate) fn num_run(&self) -> usize {
+        self.passed + self.failed
+    }
+}
+
+/// Gets the name of every test that was skipped without running, along with
+/// why it was skipped.
+pub(crate) fn get_skipped_tests(res: &[TestGroupRunResults]) -> Vec<(String, SkipReason)> {
+    res.iter()
+        .flat_map(|g| g.sub_group_res.iter())
+        .flat_map(|sub_g| sub_g.test_res.iter())
+        .filter_map(|t| match &t.status {
+            TestStatus::Skipped(reason) => Some((t.name.clone(), reason.clone())),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Gets the name of every test that failed.
+pub(crate) fn get_failed_tests(res: &[TestGroupRunResults]) -> Vec<String> {
+    res.iter()
+        .flat_map(|g| g.sub_group_res.iter())
+        .flat_map(|sub_g| sub_g.test_res.iter())
+        .filter(|t| t.status.failed())
+        .map(|t| t.name.clone())
+        .collect()
+}
+
+/// Gets the name of every test that timed out.
+pub(crate) fn get_timed_out_tests(res: &[TestGroupRunResults]) -> Vec<String> {
+    res.iter()
+        .flat_map(|g| g.sub_group_res.iter())
+        .flat_map(|sub_g| sub_g.test_res.iter())
+        .filter(|t| matches!(t.status, TestStatus::TimedOut))
+        .map(|t| t.name.clone())
+        .collect()
+}
+
+/// Gets the name of every test that passed in this run and is in
+/// `previously_failed`.
+pub(crate) fn get_new_passes(
+    res: &[TestGroupRunResults],
+    previously_failed: &HashSet<String>,
+) -> Vec<String> {
+    res.iter()
+        .flat_map(|g| g.sub_group_res.iter())
+        .flat_map(|sub_g| sub_g.test_res.iter())
+        .filter(|t| t.status.passed() && previously_failed.contains(&t.name))
+        .map(|t| t.name.clone())
+        .collect()
+}
+
+/// Gets every sub-group (as `<GROUP>/<SUB_GROUP>`) that ran fewer than
+/// `min_tests` tests, along with the number of tests it ran.
+pub(crate) fn get_sub_groups_below_coverage(
+    res: &[TestGroupRunResults],
+    min_tests: usize,
+) -> Vec<(String, usize)> {
+    res.iter()
+        .flat_map(|g| {
+            g.sub_group_res.iter().map(move |sub_g| {
+                let num_run = sub_g
+                    .test_res
+                    .iter()
+                    .filter(|t| !matches!(t.status, TestStatus::Skipped(_)))
+                    .count();
+
+                (format!("{}/{}", g.name, sub_g.name), num_run)
+            })
+        })
+        .filter(|(_, num_run)| *num_run < min_tests)
+        .collect()
+}
+
+/// Gets every unique `EvmErr` message of a run.
+pub(crate) fn get_evm_err_msgs(res: &[TestGroupRunResults]) -> BTreeSet<String> {
+    res.iter()
+        .flat_map(|g| g.sub_group_res.iter())
+        .flat_map(|sub_g| sub_g.test_res.iter())
+        .filter_map(|t| match &t.status {
+            TestStatus::EvmErr(msg) => Some(msg.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Options controlling how the tests of a run are executed.
+#[derive(Clone, Debug)]
+pub(crate) struct TestRunConfig {
+    pub(crate) simple_progress_indicator: bool,
+    /// Print the fields of the transaction of each test before running it.
+    pub(crate) output_transaction_details: bool,
+    /// Record the size of the proof of each test.
+    pub(crate) output_proof_size: bool,
+    /// Log the actual and expected receipts trie root of each test.
+    pub(crate) output_receipt_root_diff: bool,
+    pub(crate) witness_only: bool,
+    pub(crate) test_timeout: Option<Duration>,
+    /// If set, any test with a block gas limit above this value has its gas
+    /// limit lowered to it before running.
+    pub(crate) gas_limit_override: Option<u64>,
+    /// Record the heap allocations made by each test. Always `false` unless
+    /// compiled with the `jemallocator` feature.
+    #[cfg_attr(not(feature = "jemallocator"), allow(dead_code))]
+    pub(crate) trace_malloc: bool,
+    /// Fail tests whose proof reports a different amount of gas used than the
+    /// expected block header.
+    pub(crate) strict_gas_accounting: bool,
+    /// Let a panic in the prover terminate the process instead of marking the
+    /// test as failed and moving on.
+    pub(crate) abort_on_panic: bool,
+    /// Run each test in a separate child process, so that a test can not
+    /// affect the global state of the prover for the tests after it.
+    pub(crate) test_isolation: bool,
+    /// The parsed test files to hand to the child processes of
+    /// `test_isolation` instead of having them read from disk.
+    pub(crate) preloaded_cbor: Option<PreloadedCbor>,
+    /// Prove each test twice concurrently and fail it if the proofs differ.
+    pub(crate) check_determinism: bool,
+    /// If set, the trie roots of the proof of every passing test are written
+    /// to this directory.
+    pub(crate) merkle_proofs_dir: Option<PathBuf>,
+    /// The config that tests are proven and verified with.
+    pub(crate) stark_config: StarkConfig,
+    /// The file that `stark_config` was loaded from, if any. Only used to pass
+    /// it on to child processes.
+    pub(crate) prover_config_file: Option<PathBuf>,
+    /// Whether the schema version of each parsed test is checked when reading
+    /// it in. Only used to pass it on to child processes.
+    pub(crate) test_version_check: bool,
+    /// If set, the public values of the proof of every test are written to
+    /// this directory.
+    pub(crate) public_values_dir: Option<PathBuf>,
+    /// If set, the size of the tables of the proof of every test is written to
+    /// this directory.
+    pub(crate) proof_structure_dir: Option<PathBuf>,
+    /// If set, the proof of every passing test is written as JSON to this
+    /// directory.
+    pub(crate) proof_json_dir: Option<PathBuf>,
+    /// Also write the proofs of failing tests to `proof_json_dir`.
+    pub(crate) output_all_proofs: bool,
+    /// If set, the remaining tests of a sub-group are skipped once this many
+    /// of its tests have failed.
+    pub(crate) max_subgroup_failures: Option<usize>,
+    /// If set, the run is aborted once a test fails with an EVM error matching
+    /// this pattern.
+    pub(crate) early_abort_pattern: Option<Regex>,
+    /// Pass every test immediately without running the prover.
+    pub(crate) mock_prove: bool,
+    /// The number of tests to run at the same time. Tests are run one at a
+    /// time on the current thread if this is `1`.
+    pub(crate) worker_count: usize,
+    /// Give each worker whole sub-groups to run rather than single tests.
+    pub(crate) parallel_subgroups: bool,
+}
+
+/// A test along with the group and sub-group it belongs to.
+#[derive(Debug)]
+struct QueuedTest {
+    group_idx: usize,
+    sub_group_idx: usize,
+    test: Test,
+}
+
+/// The tests of a run in the order that they will be run, along with an entry
+/// for every group and sub-group to record their results in. Tests that are
+/// skipped without running already have their results recorded.
+#[derive(Debug)]
+pub(crate) struct TestQueue {
+    results: Vec<TestGroupRunResults>,
+    tests: Vec<QueuedTest>,
+}
+
+impl TestQueue {
+    /// Queues the tests in the order that they were read in. If `pass_state`
+    /// is provided, tests are prioritised based on how they did in their last
+    /// run: tests that failed are run first, followed by tests that have never
+    /// been run, and finally tests that passed or were ignored.
+    pub(crate) fn new(
+        parsed_tests: Vec<ParsedTestGroup>,
+        pass_state: Option<&TestRunEntries>,
+    ) -> Self {
+        let mut results = Vec::with_capacity(parsed_tests.len());
+        let mut tests = Vec::new();
+
+        for (group_idx, group) in parsed_tests.into_iter().enumerate() {
+            let mut sub_group_res = Vec::with_capacity(group.sub_groups.len());
+
+            for (sub_group_idx, sub_group) in group.sub_groups.into_iter().enumerate() {
+                tests.extend(sub_group.tests.into_iter().map(|test| QueuedTest {
+                    group_idx,
+                    sub_group_idx,
+                    test,
+                }));
+                // Skipped tests are never run, so their results are already known.
+                let test_res = sub_group
+                    .skipped_tests
+                    .into_iter()
+                    .map(|t| TestRunResult {
+                        name: t.name,
+                        status: TestStatus::Skipped(t.reason),
+                        alloc_stats: None,
+                        duration: None,
+                        proof_size_bytes: None,
+                    })
+                    .collect();
+
+                sub_group_res.push(TestSubGroupRunResults {
+                    name: sub_group.name,
+                    test_res,
+                });
+            }
+
+            results.push(TestGroupRunResults {
+                name: group.name,
+                sub_group_res,
+            });
+        }
+
+        if let Some(pass_state) = pass_state {
+            // Stable, so tests keep their read order within each priority.
+            tests.sort_by_key(|t| match pass_state.get_pass_state(&t.test.name) {
+                PassState::Failed => 0,
+                PassState::NotRun => 1,
+                PassState::PassedWitness | PassState::PassedProof | PassState::Ignored => 2,
+            });
+        }
+
+        Self { results, tests }
+    }
+
+    /// Removes the tests in `t_names` from the queue and records them as
+    /// skipped for `reason` instead.
+    pub(crate) fn skip_tests(&mut self, t_names: &HashSet<String>, reason: SkipReason) {
+        let (skipped, tests): (Vec<_>, Vec<_>) = mem::take(&mut self.tests)
+            .into_iter()
+            .partition(|t| t_names.contains(&t.test.name));
+        self.tests = tests;
+
+        for t in skipped {
+            self.results[t.group_idx].sub_group_res[t.sub_group_idx]
+                .test_res
+                .push(skipped_test_result(t.test.name, reason.clone()));
+        }
+    }
+
+    /// Removes the tests in `blacklist` (loaded from the blacklist file at
+    /// `path`) from the queue and records them as skipped, along with the line
+    /// of the file that they are on.
+    pub(crate) fn skip_blacklisted_tests(
+        &mut self,
+        path: &Path,
+        blacklist: &HashMap<String, usize>,
+    ) {
+        self.skip_tests_by(|t| {
+            blacklist.get(&t.name).map(|&line| SkipReason::Blacklisted {
+                path: path.to_path_buf(),
+                line,
+            })
+        });
+    }
+
+    /// Removes the tests that `skip_reason` returns a reason for from the
+    /// queue and records them as skipped.
+    pub(crate) fn skip_tests_by(&mut self, skip_reason: impl Fn(&Test) -> Option<SkipReason>) {
+        for t in mem::take(&mut self.tests) {
+            match skip_reason(&t.test) {
+                Some(reason) => self.results[t.group_idx].sub_group_res[t.sub_group_idx]
+                    .test_res
+                    .push(skipped_test_result(t.test.name, reason)),
+                None => self.tests.push(t),
+            }
+        }
+    }
+
+    /// Removes the tests in `t_names` from the queue and records them as
+    /// having passed without running them.
+    pub(crate) fn pass_tests_without_proving(
+        &mut self,
+        t_names: &HashSet<String>,
+        witness_only: bool,
+    ) {
+        let (passed, tests): (Vec<_>, Vec<_>) = mem::take(&mut self.tests)
+            .into_iter()
+            .partition(|t| t_names.contains(&t.test.name));
+        self.tests = tests;
+
+        let status = match witness_only {
+            true => TestStatus::PassedWitness,
+            false => TestStatus::PassedProof,
+        };
+        for t in passed {
+            self.results[t.group_idx].sub_group_res[t.sub_group_idx]
+                .test_res
+                .push(TestRunResult {
+                    name: t.test.name,
+                    status: status.clone(),
+                    alloc_stats: None,
+                    duration: None,
+                    proof_size_bytes: None,
+                });
+        }
+    }
+
+    /// Drops the tests that `keep` returns `false` for from the queue, without
+    /// recording any results for them.
+    pub(crate) fn retain_tests_by(&mut self, keep: impl Fn(&Test) -> bool) {
+        self.tests.retain(|t| keep(&t.test));
+    }
+
+    /// Drops all but `n` randomly selected tests from the queue. Results that
+    /// are already recorded (ie. for skipped tests) are kept. The same `seed`
+    /// always selects the same tests.
+    pub(crate) fn retain_random_sample(&mut self, n: usize, seed: u64) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let n = n.min(self.tests.len());
+
+        // A partial Fisher-Yates shuffle, which moves the `n` selected tests to the
+        // end of the queue.
+        self.tests.partial_shuffle(&mut rng, n);
+        self.tests.drain(..self.tests.len() - n);
+    }
+
+    /// Removes `n` randomly selected tests (or all of them if there are fewer
+    /// than `n`) from the queue and returns them in a queue of their own. The
+    /// same `seed` always selects the same tests.
+    pub(crate) fn split_off_random_sample(&mut self, n: usize, seed: u64) -> TestQueue {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut sampled_idxs =
+            index::sample(&mut rng, self.tests.len(), n.min(self.tests.len())).into_vec();
+
+        // Remove from the back so that the remaining indices stay valid.
+        sampled_idxs.sort_unstable_by(|a, b| b.cmp(a));
+        let mut tests: Vec<_> = sampled_idxs
+            .into_iter()
+            .map(|idx| self.tests.remove(idx))
+            .collect();
+        tests.reverse();
+
+        // The sampled tests still need somewhere to record their results, but the
+        // results of skipped tests stay with the original queue.
+        let results = self
+            .results
+            .iter()
+            .map(|g| TestGroupRunResults {
+                name: g.name.clone(),
+                sub_group_res: g
+                    .sub_group_res
+                    .iter()
+                    .map(|sub_g| TestSubGroupRunResults {
+                        name: sub_g.name.clone(),
+                        test_res: Vec::new(),
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        Self { results, tests }
+    }
+
+    /// Removes the tests in `t_names` from the queue and records them as
+    /// failed because they have never been run before.
+    pub(crate) fn fail_tests_not_previously_run(&mut self, t_names: &HashSet<String>) {
+        let (failed, tests): (Vec<_>, Vec<_>) = mem::take(&mut self.tests)
+            .into_iter()
+            .partition(|t| t_names.contains(&t.test.name));
+        self.tests = tests;
+
+        for t in failed {
+            self.results[t.group_idx].sub_group_res[t.sub_group_idx]
+                .test_res
+                .push(TestRunResult {
+                    name: t.test.name,
+                    status: TestStatus::NotPreviouslyRun,
+                    alloc_stats: None,
+                    duration: None,
+                    proof_size_bytes: None,
+                });
+        }
+    }
+
+    /// The names of all queued tests, in the order that they will be run.
+    pub(crate) fn test_names(&self) -> impl Iterator<Item = &str> {
+        self.tests.iter().map(|t| t.test.name.as_str())
+    }
+
+    /// The parsed test files of the queued tests. Tests that share a file
+    /// (variants of the same test) each yield it.
+    pub(crate) fn test_paths(&self) -> impl Iterator<Item = &Path> {
+        self.tests.iter().filter_map(|t| t.test.path.as_deref())
+    }
+}
+
+/// The number of failed tests in each sub-group (by group and sub-group
+/// index), shared with the workers of the parallel runner to enforce
+/// `--max-subgroup-failures`.
+#[derive(Clone, Debug, Default)]
+struct SubGroupFailureCounts(Arc<Mutex<HashMap<(usize, usize), usize>>>);
+
+impl SubGroupFailureCounts {
+    fn record(&self, group_idx: usize, sub_group_idx: usize, status: &TestStatus) {
+        if status.failed() {
+            *self
+                .0
+                .lock()
+                .unwrap()
+                .entry((group_idx, sub_group_idx))
+                .or_default() += 1;
+        }
+    }
+
+    fn limit_reached(&self, group_idx: usize, sub_group_idx: usize, limit: Option<usize>) -> bool {
+        limit.is_some_and(|limit| {
+            let num_failures = self
+                .0
+                .lock()
+                .unwrap()
+                .get(&(group_idx, sub_group_idx))
+                .copied()
+                .unwrap_or(0);
+
+            num_failures >= limit
+        })
+    }
+}
+
+fn skipped_test_result(name: String, reason: SkipReason) -> TestRunResult {
+    TestRunResult {
+        name,
+        status: TestStatus::Skipped(reason),
+        alloc_stats: None,
+        duration: None,
+        proof_size_bytes: None,
+    }
 }
 
 #[derive(Debug)]
 struct TestRunState<'a> {
     p_indicator: Box<dyn TestProgressIndicator>,
-    persistent_test_state: &'a mut TestRunEntries,
-    process_aborted_recv: ProcessAbortedRecv,
-    witness_only: bool,
+    /// `None` for runs that should not be recorded in the persistent state.
+    persistent_test_state: Option<&'a mut TestRunEntries>,
+    process_aborted_recv: &'a mut ProcessAbortedRecv,
+    config: TestRunConfig,
     test_timeout: Duration,
+    checkpoint: Option<Checkpoint>,
 }
 
+/// Runs the queued tests. Their results are recorded in
+/// `persistent_test_state`, if any.
 pub(crate) fn run_plonky2_tests(
-    parsed_tests: Vec<ParsedTestGroup>,
-    simple_progress_indicator: bool,
-    persistent_test_state: &mut TestRunEntries,
-    process_aborted: ProcessAbortedRecv,
-    witness_only: bool,
-    test_timeout: Option<Duration>,
+    test_queue: TestQueue,
+    config: TestRunConfig,
+    persistent_test_state: Option<&mut TestRunEntries>,
+    process_aborted: &mut ProcessAbortedRecv,
+    checkpoint: Option<Checkpoint>,
 ) -> RunnerResult<Vec<TestGroupRunResults>> {
-    let num_tests = num_tests_in_groups(parsed_tests.iter());
-    let p_indicator = create_progress_indicator(num_tests, simple_progress_indicator);
+    let num_tests = test_queue.tests.len() as u64;
+    let p_indicator = create_progress_indicator(num_tests, config.simple_progress_indicator);
 
-    let test_timeout = match test_timeout {
+    let test_timeout = match config.test_timeout {
         Some(t) => t,
         None => Duration::MAX,
     };
@@ -157,14 +786,25 @@ pub(crate) fn run_plonky2_tests(
         p_indicator,
         persistent_test_state,
         process_aborted_recv: process_aborted,
-        witness_only,
+        config,
         test_timeout,
+        checkpoint,
     };
 
-    parsed_tests
-        .into_iter()
-        .map(|g| run_test_group(g, &mut t_state))
-        .collect::<RunnerResult<_>>()
+    let res = match t_state.config.worker_count {
+        0 | 1 => run_tests_sequentially(test_queue, &mut t_state),
+        worker_count => run_tests_in_parallel(test_queue, worker_count, &mut t_state),
+    };
+
+    // Make sure we don't lose any progress made since the last checkpoint if the
+    // run was aborted.
+    if res.is_err()
+        && let Some(checkpoint) = t_state.checkpoint.as_mut()
+    {
+        checkpoint.write_to_disk();
+    }
+
+    res
 }
 
 fn create_progress_indicator(
@@ -189,57 +829,287 @@ fn create_progress_indicator(
     }
 }
 
-fn run_test_group(
-    group: ParsedTestGroup,
+fn run_tests_sequentially(
+    test_queue: TestQueue,
     t_state: &mut TestRunState,
-) -> RunnerResult<TestGroupRunResults> {
-    Ok(TestGroupRunResults {
-        name: group.name,
-        sub_group_res: group
-            .sub_groups
-            .into_iter()
-            .map(|sub_g| run_test_sub_group(sub_g, t_state))
-            .collect::<RunnerResult<_>>()?,
-    })
+) -> RunnerResult<Vec<TestGroupRunResults>> {
+    let TestQueue { mut results, tests } = test_queue;
+    let failure_counts = SubGroupFailureCounts::default();
+
+    for t in tests {
+        let group = &mut results[t.group_idx];
+        let sub_group = &mut group.sub_group_res[t.sub_group_idx];
+
+        let t_res = match failure_counts.limit_reached(
+            t.group_idx,
+            t.sub_group_idx,
+            t_state.config.max_subgroup_failures,
+        ) {
+            false => run_test(t.test, &group.name, &sub_group.name, t_state)?,
+            true => {
+                let t_res = skipped_test_result(t.test.name, SkipReason::SubgroupFailureLimit);
+                record_test_result(&t_res, &group.name, &sub_group.name, t_state);
+                t_res
+            }
+        };
+
+        failure_counts.record(t.group_idx, t.sub_group_idx, &t_res.status);
+        check_early_abort_pattern(&t_res, &t_state.config)?;
+        sub_group.test_res.push(t_res);
+    }
+
+    Ok(results)
 }
 
-fn run_test_sub_group(
-    sub_group: ParsedTestSubGroup,
+/// Aborts the run if the test failed with an EVM error matching
+/// `--early-abort-pattern`. The result of the test is already recorded by then.
+fn check_early_abort_pattern(t_res: &TestRunResult, config: &TestRunConfig) -> RunnerResult<()> {
+    if let Some(pattern) = &config.early_abort_pattern
+        && let TestStatus::EvmErr(err_msg) = &t_res.status
+        && pattern.is_match(err_msg)
+    {
+        return Err(RunAborted::EarlyAbortPattern {
+            t_name: t_res.name.clone(),
+            err_msg: err_msg.clone(),
+        });
+    }
+
+    Ok(())
+}
+
+fn run_test(
+    test: Test,
+    group_name: &str,
+    sub_group_name: &str,
     t_state: &mut TestRunState,
-) -> RunnerResult<TestSubGroupRunResults> {
-    Ok(TestSubGroupRunResults {
-        name: sub_group.name,
-        test_res: sub_group
-            .tests
-            .into_iter()
-            .map(|sub_g| run_test(sub_g, t_state))
-            .collect::<RunnerResult<_>>()?,
-    })
+) -> RunnerResult<TestRunResult> {
+    let name = test.name.clone();
+    t_state.p_indicator.set_current_test_name(name.clone());
+
+    #[cfg(feature = "jemallocator")]
+    let alloc_before = t_state
+        .config
+        .trace_malloc
+        .then(crate::alloc_tracing::current_totals);
+
+    let start = Instant::now();
+    let outcome = run_test_or_fail_on_timeout(test, t_state)?;
+    let duration = start.elapsed();
+
+    #[cfg(feature = "jemallocator")]
+    let alloc_stats = alloc_before.map(|before| crate::alloc_tracing::alloc_stats_since(&before));
+    #[cfg(not(feature = "jemallocator"))]
+    let alloc_stats = None;
+
+    let t_res = TestRunResult {
+        name,
+        status: outcome.status,
+        alloc_stats,
+        duration: Some(duration),
+        proof_size_bytes: outcome.proof_size_bytes,
+    };
+    record_test_result(&t_res, group_name, sub_group_name, t_state);
+
+    Ok(t_res)
 }
 
-fn run_test(test: Test, t_state: &mut TestRunState) -> RunnerResult<TestRunResult> {
-    t_state
-        .p_indicator
-        .set_current_test_name(test.name.to_string());
-    let res = run_test_or_fail_on_timeout(test.info, t_state)?;
+/// Updates the run state with the result of a test that just completed. Tests
+/// that were skipped without running only count towards the progress, so that
+/// they are run again next time.
+fn record_test_result(
+    t_res: &TestRunResult,
+    group_name: &str,
+    sub_group_name: &str,
+    t_state: &mut TestRunState,
+) {
+    if let TestStatus::Skipped(_) = t_res.status {
+        t_state.p_indicator.notify_test_completed();
+        return;
+    }
 
-    t_state
-        .persistent_test_state
-        .update_test_state(&test.name, res.clone().into());
+    if let Some(persistent_test_state) = t_state.persistent_test_state.as_deref_mut() {
+        persistent_test_state.update_test_state(
+            &t_res.name,
+            t_res.status.clone().into(),
+            t_res.duration,
+        );
+    }
     t_state.p_indicator.notify_test_completed();
 
-    Ok(TestRunResult {
-        name: test.name,
-        status: res,
+    if let Some(checkpoint) = t_state.checkpoint.as_mut() {
+        checkpoint.record_test_result(group_name, sub_group_name, t_res);
+    }
+}
+
+/// Runs the tests on `worker_count` threads using a work-stealing pool.
+///
+/// Results are recorded on the current thread as the tests complete, so
+/// within a sub-group they are in completion order rather than in the order
+/// that the tests were queued in. Allocations are not tracked per test, as
+/// concurrently running tests would be counted together.
+///
+/// With `--parallel-subgroups`, each worker runs all the tests of a sub-group
+/// one after the other instead, and their results are only recorded once the
+/// whole sub-group completes.
+fn run_tests_in_parallel(
+    test_queue: TestQueue,
+    worker_count: usize,
+    t_state: &mut TestRunState,
+) -> RunnerResult<Vec<TestGroupRunResults>> {
+    let TestQueue { mut results, tests } = test_queue;
+
+    let tasks = match t_state.config.parallel_subgroups {
+        false => tests.into_iter().map(|t| vec![t]).collect(),
+        true => group_tests_by_sub_group(tests),
+    };
+
+    let config = t_state.config.clone();
+    let test_timeout = t_state.test_timeout;
+    let failure_counts = SubGroupFailureCounts::default();
+    // Workers report each test as they pick it up, so that the progress
+    // indicator shows the tests that are running rather than those that just
+    // completed.
+    let (started_send, started_recv) = mpsc::channel();
+    let pool = WorkStealingPool::spawn(tasks, worker_count, move |task: Vec<QueuedTest>| {
+        task.into_iter()
+            .map(|t| {
+                let t_res = run_queued_test_on_worker(
+                    t.test,
+                    t.group_idx,
+                    t.sub_group_idx,
+                    &config,
+                    test_timeout,
+                    &failure_counts,
+                    &started_send,
+                );
+                // Recorded here rather than once the result is received, so
+                // that the next test of a sub-group run by this worker sees it.
+                failure_counts.record(t.group_idx, t.sub_group_idx, &t_res.status);
+
+                (t.group_idx, t.sub_group_idx, t_res)
+            })
+            .collect::<Vec<_>>()
+    });
+
+    loop {
+        let next_result = pool.next_result(ABORT_POLL_INTERVAL);
+        for t_name in started_recv.try_iter() {
+            t_state.p_indicator.set_current_test_name(t_name);
+        }
+
+        match next_result {
+            Ok(task_results) => {
+                for (group_idx, sub_group_idx, t_res) in task_results {
+                    let group = &mut results[group_idx];
+                    let sub_group = &mut group.sub_group_res[sub_group_idx];
+
+                    record_test_result(&t_res, &group.name, &sub_group.name, t_state);
+                    check_early_abort_pattern(&t_res, &t_state.config)?;
+                    sub_group.test_res.push(t_res);
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        // Process was aborted.
+        if t_state.process_aborted_recv.try_recv().is_ok() {
+            return Err(RunAborted::Interrupted);
+        }
+    }
+
+    Ok(results)
+}
+
+/// Runs a test on a worker of the parallel runner, unless its sub-group already
+/// reached `--max-subgroup-failures`. The name of the test is sent to `started`
+/// before it runs.
+fn run_queued_test_on_worker(
+    test: Test,
+    group_idx: usize,
+    sub_group_idx: usize,
+    config: &TestRunConfig,
+    test_timeout: Duration,
+    failure_counts: &SubGroupFailureCounts,
+    started: &Sender<String>,
+) -> TestRunResult {
+    if failure_counts.limit_reached(group_idx, sub_group_idx, config.max_subgroup_failures) {
+        return skipped_test_result(test.name, SkipReason::SubgroupFailureLimit);
+    }
+
+    let name = test.name.clone();
+    // The runner only stops listening once every worker is done.
+    let _ = started.send(name.clone());
+    let start = Instant::now();
+    let outcome = run_test_with_timeout(test, config, test_timeout);
+
+    TestRunResult {
+        name,
+        status: outcome.status,
+        alloc_stats: None,
+        duration: Some(start.elapsed()),
+        proof_size_bytes: outcome.proof_size_bytes,
+    }
+}
+
+/// Groups the queued tests by their sub-group. The sub-groups are ordered by
+/// their first test in the queue, and keep the order of their tests.
+fn group_tests_by_sub_group(tests: Vec<QueuedTest>) -> Vec<Vec<QueuedTest>> {
+    let mut sub_group_idxs = HashMap::new();
+    let mut sub_groups: Vec<Vec<QueuedTest>> = Vec::new();
+
+    for t in tests {
+        let idx = *sub_group_idxs
+            .entry((t.group_idx, t.sub_group_idx))
+            .or_insert_with(|| {
+                sub_groups.push(Vec::new());
+                sub_groups.len() - 1
+            });
+        sub_groups[idx].push(t);
+    }
+
+    sub_groups
+}
+
+/// Runs a single test outside of a full run, without updating any run state.
+pub(crate) fn run_single_test(test: Test, config: &TestRunConfig) -> TestRunResult {
+    let test_timeout = config.test_timeout.unwrap_or(Duration::MAX);
+    let name = test.name.clone();
+    let start = Instant::now();
+    let outcome = run_test_with_timeout(test, config, test_timeout);
+
+    TestRunResult {
+        name,
+        status: outcome.status,
+        alloc_stats: None,
+        duration: Some(start.elapsed()),
+        proof_size_bytes: outcome.proof_size_bytes,
+    }
+}
+
+/// Runs a single test with a timeout, without being able to be aborted.
+/// Used on the worker threads of the parallel runner.
+fn run_test_with_timeout(
+    test: Test,
+    config: &TestRunConfig,
+    test_timeout: Duration,
+) -> TestOutcome {
+    block_on(async {
+        let proof_gen_fut = async { run_test_and_catch_panics(test, config) };
+
+        match timeout(test_timeout, proof_gen_fut).await {
+            Ok(t_res) => t_res,
+            Err(_) => TestStatus::TimedOut.into(),
+        }
     })
 }
 
 fn run_test_or_fail_on_timeout(
-    test: TestVariantRunInfo,
+    test: Test,
     t_state: &mut TestRunState,
-) -> RunnerResult<TestStatus> {
+) -> RunnerResult<TestOutcome> {
     block_on(async {
-        let proof_gen_fut = async { run_test_and_get_test_result(test, t_state.witness_only) };
+        let proof_gen_fut = async { run_test_and_catch_panics(test, &t_state.config) };
         let proof_gen_with_timeout_fut = timeout(t_state.test_timeout, proof_gen_fut);
         let process_aborted_fut = t_state.process_aborted_recv.recv();
 
@@ -247,20 +1117,89 @@ fn run_test_or_fail_on_timeout(
             res = proof_gen_with_timeout_fut => {
                 match res {
                     Ok(t_res) => Ok(t_res),
-                    Err(_) => Ok(TestStatus::TimedOut),
+                    Err(_) => Ok(TestStatus::TimedOut.into()),
                 }
             },
             // Process was aborted.
-            _ = process_aborted_fut => Err(()),
+            _ = process_aborted_fut => Err(RunAborted::Interrupted),
         }
     })
 }
 
+/// Run a test, turning any panic in the prover into a failed test so that the
+/// remaining tests can still run. Panics are left to propagate with
+/// `--abort-on-panic`.
+///
+/// With `--test-isolation`, the test is run in a child process instead, which
+/// catches any panics itself.
+fn run_test_and_catch_panics(test: Test, config: &TestRunConfig) -> TestOutcome {
+    if config.output_transaction_details {
+        let details = format_txn_details(&test.name, test.info.gen_inputs.signed_txn.as_deref());
+
+        // Printing would break up the progress bar.
+        match config.simple_progress_indicator {
+            true => println!("{}", details),
+            false => debug!("{}", details),
+        }
+    }
+
+    if config.test_isolation
+        && let Some(path) = &test.path
+    {
+        return run_test_in_child_process(path, test.info.variant_idx, config);
+    }
+
+    if config.abort_on_panic {
+        return run_test_and_get_outcome(test.info, config);
+    }
+
+    match panic::catch_unwind(AssertUnwindSafe(|| {
+        run_test_and_get_outcome(test.info, config)
+    })) {
+        Ok(outcome) => outcome,
+        Err(payload) => {
+            let msg = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "Unknown panic payload".to_string());
+
+            warn!("Prover panicked: {}", msg);
+            TestStatus::Panicked(msg).into()
+        }
+    }
+}
+
+fn run_test_and_get_outcome(test: TestVariantRunInfo, config: &TestRunConfig) -> TestOutcome {
+    let mut proof_size_bytes = None;
+    let status = run_test_and_get_test_result(test, config, &mut proof_size_bytes);
+
+    TestOutcome {
+        status,
+        proof_size_bytes,
+    }
+}
+
 /// Run a test against `plonky2` and output a result based on what happens.
-fn run_test_and_get_test_result(test: TestVariantRunInfo, witness_only: bool) -> TestStatus {
+/// With `--output-proof-size`, the size of the proof is written to
+/// `proof_size_bytes` once it is generated.
+fn run_test_and_get_test_result(
+    mut test: TestVariantRunInfo,
+    config: &TestRunConfig,
+    proof_size_bytes: &mut Option<u64>,
+) -> TestStatus {
+    // Only exercises the runner itself, so the inputs are never looked at.
+    if config.mock_prove {
+        return TestStatus::MockPassed;
+    }
+
     let timing = TimingTree::new("prove", log::Level::Debug);
 
-    match witness_only {
+    if let Some(gas_limit) = config.gas_limit_override {
+        apply_gas_limit_override(&mut test.gen_inputs, gas_limit);
+    }
+
+    match config.witness_only {
         true => {
             let res = simulate_execution::<GoldilocksField>(test.gen_inputs);
 
@@ -277,6 +1216,7 @@ fn run_test_and_get_test_result(test: TestVariantRunInfo, witness_only: bool) ->
             // will ignore it if proving the altered inputs failed so as to not
             // have false positives.
             let mut inputs = test.gen_inputs;
+            let expected_gas_used = inputs.block_metadata.block_gas_used;
             let is_gaslimit_changed =
                 TryInto::<u32>::try_into(inputs.block_metadata.block_gaslimit).is_err();
 
@@ -284,13 +1224,25 @@ fn run_test_and_get_test_result(test: TestVariantRunInfo, witness_only: bool) ->
                 inputs.block_metadata.block_gaslimit = U256::from(u32::MAX);
             }
 
-            let proof_run_res = prove::<GoldilocksField, KeccakGoldilocksConfig, 2>(
-                &AllStark::default(),
-                &StarkConfig::standard_fast_config(),
-                inputs,
-                &mut TimingTree::default(),
-                None,
-            );
+            let expected_public_values = ExpectedPublicValues {
+                trie_roots_after: inputs.trie_roots_after.clone(),
+                gas_used: expected_gas_used,
+            };
+
+            let proof_run_res = match config.check_determinism {
+                false => prove_inputs(inputs, &config.stark_config),
+                true => {
+                    let (proof_run_res, other_proof_run_res) =
+                        prove_inputs_twice_concurrently(inputs, &config.stark_config);
+
+                    if proof_runs_differ(&proof_run_res, &other_proof_run_res) {
+                        warn!("Proving the same inputs twice gave different results");
+                        return TestStatus::NonDeterministicProof;
+                    }
+
+                    proof_run_res
+                }
+            };
 
             timing.filter(Duration::from_millis(100)).print();
 
@@ -299,14 +1251,102 @@ fn run_test_and_get_test_result(test: TestVariantRunInfo, witness_only: bool) ->
                 Err(evm_err) => return handle_evm_err(evm_err, is_gaslimit_changed, "Proving"),
             };
 
-            let verif_output = verify_proof(
-                &AllStark::default(),
-                proof_run_output,
-                &StarkConfig::standard_fast_config(),
-            );
-            if verif_output.is_err() {
-                warn!("Verification failed with error: {:?}", verif_output);
-                return TestStatus::EvmErr("Proof verification failed.".to_string());
+            if config.output_proof_size {
+                *proof_size_bytes = Some(
+                    serde_cbor::to_vec(&SerializableProof::from(&proof_run_output))
+                        .expect("Serializing proof")
+                        .len() as u64,
+                );
+            }
+
+            if config.output_receipt_root_diff {
+                log_receipt_root_diff(
+                    &test.variant_name,
+                    proof_run_output
+                        .public_values
+                        .trie_roots_after
+                        .receipts_root,
+                    expected_public_values.trie_roots_after.receipts_root,
+                );
+            }
+
+            if let Some(dir) = &config.public_values_dir
+                && let Err(err) = write_public_values(
+                    dir,
+                    &test.variant_name,
+                    &proof_run_output.public_values,
+                    &expected_public_values,
+                )
+            {
+                warn!("Could not write the public values of the proof: {:#}", err);
+            }
+
+            if let Some(dir) = &config.proof_structure_dir
+                && let Err(err) = write_proof_structure(
+                    dir,
+                    &test.variant_name,
+                    &proof_run_output,
+                    &config.stark_config,
+                )
+            {
+                warn!("Could not write the structure of the proof: {:#}", err);
+            }
+
+            // Written before the proof is checked, so that it is kept whether or
+            // not the test passes.
+            if let Some(dir) = &config.proof_json_dir
+                && config.output_all_proofs
+                && let Err(err) = write_proof_json(dir, &test.variant_name, &proof_run_output)
+            {
+                warn!("Could not write the proof as JSON: {:#}", err);
+            }
+
+            // Verifying consumes the proof, so keep the public values around to
+            // write them out afterwards.
+            let actual_gas_used = proof_run_output
+                .public_values
+                .extra_block_data
+                .gas_used_after;
+            let public_values = config
+                .merkle_proofs_dir
+                .is_some()
+                .then(|| proof_run_output.public_values.clone());
+            let proof = (config.proof_json_dir.is_some() && !config.output_all_proofs)
+                .then(|| proof_run_output.clone());
+
+            let verif_output =
+                verify_proof(&AllStark::default(), proof_run_output, &config.stark_config);
+            if let Err(verif_err) = verif_output {
+                warn!("Verification failed with error: {:?}", verif_err);
+                return TestStatus::ProofVerificationFailed(verif_err.to_string());
+            }
+
+            // Only checked once the proof is known to be valid, as the gas used
+            // of an invalid proof means nothing.
+            if config.strict_gas_accounting && actual_gas_used != expected_gas_used {
+                // Like for errors, don't report failures on altered inputs.
+                if is_gaslimit_changed {
+                    return TestStatus::Ignored;
+                }
+
+                return TestStatus::IncorrectGasUsage {
+                    actual: actual_gas_used,
+                    expected: expected_gas_used,
+                };
+            }
+
+            if let Some(dir) = &config.merkle_proofs_dir
+                && let Some(public_values) = public_values
+                && let Err(err) = write_trie_roots(dir, &test.variant_name, &public_values)
+            {
+                warn!("Could not write the trie roots of the proof: {:#}", err);
+            }
+
+            if let Some(dir) = &config.proof_json_dir
+                && let Some(proof) = proof
+                && let Err(err) = write_proof_json(dir, &test.variant_name, &proof)
+            {
+                warn!("Could not write the proof as JSON: {:#}", err);
             }
         }
     }
@@ -314,6 +1354,79 @@ fn run_test_and_get_test_result(test: TestVariantRunInfo, witness_only: bool) ->
     TestStatus::PassedProof
 }
 
+/// Tests that were not parsed from a block header have a zero receipts root,
+/// which is not worth comparing against.
+fn log_receipt_root_diff(t_name: &str, actual: H256, expected: H256) {
+    match expected.is_zero() {
+        true => debug!(
+            "Receipts root of {}: {:?} (no expected root)",
+            t_name, actual
+        ),
+        false if actual == expected => {
+            debug!("Receipts root of {}: {:?} (matches)", t_name, actual)
+        }
+        false => debug!(
+            "Receipts root of {} differs: {:?} (expected {:?})",
+            t_name, actual, expected
+        ),
+    }
+}
+
+pub(crate) type Proof = AllProof<GoldilocksField, KeccakGoldilocksConfig, 2>;
+
+fn prove_inputs(inputs: GenerationInputs, stark_config: &StarkConfig) -> anyhow::Result<Proof> {
+    prove::<GoldilocksField, KeccakGoldilocksConfig, 2>(
+        &AllStark::default(),
+        stark_config,
+        inputs,
+        &mut TimingTree::default(),
+        None,
+    )
+}
+
+/// Proves the same inputs on two threads at once, so that any state shared
+/// between the two provers is raced on.
+fn prove_inputs_twice_concurrently(
+    inputs: GenerationInputs,
+    stark_config: &StarkConfig,
+) -> (anyhow::Result<Proof>, anyhow::Result<Proof>) {
+    let other_inputs = inputs.clone();
+
+    thread::scope(|s| {
+        let other_proof_run = s.spawn(|| prove_inputs(other_inputs, stark_config));
+        let proof_run_res = prove_inputs(inputs, stark_config);
+
+        // Let a panic on the other thread propagate like a panic on this one.
+        let other_proof_run_res = other_proof_run
+            .join()
+            .unwrap_or_else(|payload| panic::resume_unwind(payload));
+
+        (proof_run_res, other_proof_run_res)
+    })
+}
+
+/// Two runs differ if only one of them failed, or if both succeeded but
+/// produced different proofs. Proofs have no `PartialEq` or `Serialize` impl,
+/// so they are compared through their `Debug` output, which includes every
+/// field element of the proof.
+fn proof_runs_differ(res: &anyhow::Result<Proof>, other_res: &anyhow::Result<Proof>) -> bool {
+    match (res, other_res) {
+        (Ok(proof), Ok(other_proof)) => format!("{:?}", proof) != format!("{:?}", other_proof),
+        (Err(_), Err(_)) => false,
+        _ => true,
+    }
+}
+
+/// Lowers the block gas limit of the inputs to `gas_limit`. Inputs that
+/// already have a lower gas limit are left untouched.
+fn apply_gas_limit_override(inputs: &mut GenerationInputs, gas_limit: u64) {
+    let gas_limit = U256::from(gas_limit);
+
+    if inputs.block_metadata.block_gaslimit > gas_limit {
+        inputs.block_metadata.block_gaslimit = gas_limit;
+    }
+}
+
 fn handle_evm_err(
     evm_err: anyhow::Error,
     is_gaslimit_changed: bool,
@@ -328,3 +1441,84 @@ fn handle_evm_err(
     warn!("{} failed with error: {:?}", gen_type, evm_err);
     TestStatus::EvmErr(evm_err.to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use common::types::{ExpectedFinalRoots, TestVariantRunInfo};
+    use ethereum_types::H256;
+    use evm_arithmetization::generation::GenerationInputs;
+
+    use super::TestQueue;
+    use crate::test_dir_reading::{ParsedTestGroup, ParsedTestSubGroup, Test};
+
+    const NUM_TESTS: usize = 20;
+
+    fn test_queue() -> TestQueue {
+        let tests = (0..NUM_TESTS)
+            .map(|i| Test {
+                name: format!("test_{}", i),
+                path: None,
+                info: TestVariantRunInfo {
+                    variant_name: format!("test_{}", i),
+                    gen_inputs: GenerationInputs::default(),
+                    final_roots: ExpectedFinalRoots {
+                        state_root_hash: H256::zero(),
+                        txn_trie_root_hash: H256::zero(),
+                        receipts_trie_root_hash: H256::zero(),
+                    },
+                    variant_idx: 0,
+                },
+            })
+            .collect();
+
+        let parsed_tests = vec![ParsedTestGroup {
+            name: "group".to_string(),
+            sub_groups: vec![ParsedTestSubGroup {
+                name: "sub_group".to_string(),
+                tests,
+                skipped_tests: Vec::new(),
+            }],
+        }];
+
+        TestQueue::new(parsed_tests, None)
+    }
+
+    fn sampled_t_names(n: usize, seed: u64) -> Vec<String> {
+        let mut queue = test_queue();
+        queue.retain_random_sample(n, seed);
+
+        queue
+            .test_names()
+            .map(|t_name| t_name.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn retain_random_sample_is_reproducible_with_the_same_seed() {
+        let sample = sampled_t_names(5, 42);
+
+        assert_eq!(sample.len(), 5);
+        assert_eq!(sample, sampled_t_names(5, 42));
+    }
+
+    #[test]
+    fn retain_random_sample_depends_on_the_seed() {
+        assert_ne!(sampled_t_names(5, 1), sampled_t_names(5, 2));
+    }
+
+    #[test]
+    fn retain_random_sample_of_zero_tests() {
+        assert!(sampled_t_names(0, 42).is_empty());
+    }
+
+    #[test]
+    fn retain_random_sample_larger_than_the_queue_keeps_every_test() {
+        let mut sample = sampled_t_names(NUM_TESTS + 10, 42);
+        sample.sort();
+
+        let mut t_names: Vec<_> = (0..NUM_TESTS).map(|i| format!("test_{}", i)).collect();
+        t_names.sort();
+
+        assert_eq!(sample, t_names);
+    }
+}
@@ -0,0 +1,22 @@
+//! Writes the proof of each test as JSON with `--output-proof-json`, so that it
+//! can be checked by external verifiers and auditing tools.
+
+use std::{fs, path::Path};
+
+use anyhow::Context;
+
+use crate::{
+    io_trace::{trace_io, IoOp},
+    plonky2_runner::Proof,
+    proof_serialization::SerializableProof,
+};
+
+/// Writes `proof` to `<dir>/<test_name>.proof.json`.
+pub(crate) fn write_proof_json(dir: &Path, test_name: &str, proof: &Proof) -> anyhow::Result<()> {
+    fs::create_dir_all(dir).with_context(|| format!("Creating directory {:?}", dir))?;
+
+    let path = dir.join(format!("{}.proof.json", test_name));
+    let json = serde_json::to_string(&SerializableProof::from(proof))?;
+    trace_io(IoOp::Write, &path, || fs::write(&path, json))
+        .with_context(|| format!("Writing proof to {:?}", path))
+}
@@ -0,0 +1,83 @@
+//! A serializable view of the proof of a test. `AllProof` does not implement
+//! `Serialize`, so its fields are borrowed into structs that do. Every hash and
+//! field element of the proof is included, so the size of the serialized view
+//! follows the size of the proof.
+
+use evm_arithmetization::proof::PublicValues;
+use plonky2::{
+    field::{extension::quadratic::QuadraticExtension, goldilocks_field::GoldilocksField},
+    fri::proof::FriProof,
+    hash::{keccak::KeccakHash, merkle_tree::MerkleCap},
+};
+use serde::Serialize;
+
+use crate::plonky2_runner::Proof;
+
+type F = GoldilocksField;
+type FE = QuadraticExtension<F>;
+type H = KeccakHash<25>;
+
+#[derive(Debug, Serialize)]
+pub(crate) struct SerializableProof<'a> {
+    /// The STARK proof of each table, in the order of `AllStark`.
+    stark_proofs: Vec<SerializableStarkProof<'a>>,
+    /// The `(beta, gamma)` pair of each cross-table lookup challenge.
+    ctl_challenges: Vec<(F, F)>,
+    public_values: &'a PublicValues,
+}
+
+#[derive(Debug, Serialize)]
+struct SerializableStarkProof<'a> {
+    init_challenger_state: &'a [F],
+    trace_cap: &'a MerkleCap<F, H>,
+    auxiliary_polys_cap: Option<&'a MerkleCap<F, H>>,
+    quotient_polys_cap: Option<&'a MerkleCap<F, H>>,
+    local_values: &'a [FE],
+    next_values: &'a [FE],
+    auxiliary_polys: Option<&'a [FE]>,
+    auxiliary_polys_next: Option<&'a [FE]>,
+    ctl_zs_first: Option<&'a [F]>,
+    quotient_polys: Option<&'a [FE]>,
+    opening_proof: &'a FriProof<F, H, 2>,
+}
+
+impl<'a> From<&'a Proof> for SerializableProof<'a> {
+    fn from(proof: &'a Proof) -> Self {
+        let stark_proofs = proof
+            .multi_proof
+            .stark_proofs
+            .iter()
+            .map(|p| {
+                let openings = &p.proof.openings;
+
+                SerializableStarkProof {
+                    init_challenger_state: p.init_challenger_state.as_ref(),
+                    trace_cap: &p.proof.trace_cap,
+                    auxiliary_polys_cap: p.proof.auxiliary_polys_cap.as_ref(),
+                    quotient_polys_cap: p.proof.quotient_polys_cap.as_ref(),
+                    local_values: &openings.local_values,
+                    next_values: &openings.next_values,
+                    auxiliary_polys: openings.auxiliary_polys.as_deref(),
+                    auxiliary_polys_next: openings.auxiliary_polys_next.as_deref(),
+                    ctl_zs_first: openings.ctl_zs_first.as_deref(),
+                    quotient_polys: openings.quotient_polys.as_deref(),
+                    opening_proof: &p.proof.opening_proof,
+                }
+            })
+            .collect();
+
+        let ctl_challenges = proof
+            .multi_proof
+            .ctl_challenges
+            .challenges
+            .iter()
+            .map(|c| (c.beta, c.gamma))
+            .collect();
+
+        Self {
+            stark_proofs,
+            ctl_challenges,
+            public_values: &proof.public_values,
+        }
+    }
+}
@@ -0,0 +1,81 @@
+//! Writes the size of the STARK tables of the proof of each test with
+//! `--log-proof-structure`, so that proving times can be correlated with the
+//! size of the trace.
+
+use std::{fs, path::Path};
+
+use anyhow::Context;
+use evm_arithmetization::{all_stark::Table, StarkConfig};
+use serde::Serialize;
+
+use crate::{
+    io_trace::{trace_io, IoOp},
+    plonky2_runner::Proof,
+};
+
+/// The number of STARK tables. `evm_arithmetization` does not export its own
+/// constant for it.
+const NUM_TABLES: usize = Table::Memory as usize + 1;
+
+/// The tables of `AllStark`, in the order of the proofs in `AllProof`.
+const TABLE_NAMES: [&str; NUM_TABLES] = [
+    "arithmetic",
+    "byte_packing",
+    "cpu",
+    "keccak",
+    "keccak_sponge",
+    "logic",
+    "memory",
+];
+
+/// The degree of the constraints of every EVM STARK.
+const CONSTRAINT_DEGREE: usize = 3;
+
+#[derive(Debug, Serialize)]
+struct TableStructure {
+    name: &'static str,
+    degree_bits: usize,
+    rows: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct ProofStructure<'a> {
+    test_name: &'a str,
+    tables: Vec<TableStructure>,
+    constraint_degree: usize,
+    rate_bits: usize,
+    num_query_rounds: usize,
+}
+
+/// Writes the structure of `proof` to `<dir>/<test_name>.structure.json`.
+pub(crate) fn write_proof_structure(
+    dir: &Path,
+    test_name: &str,
+    proof: &Proof,
+    stark_config: &StarkConfig,
+) -> anyhow::Result<()> {
+    let tables = TABLE_NAMES
+        .iter()
+        .zip(proof.degree_bits(stark_config))
+        .map(|(&name, degree_bits)| TableStructure {
+            name,
+            degree_bits,
+            rows: 1 << degree_bits,
+        })
+        .collect();
+
+    let structure = ProofStructure {
+        test_name,
+        tables,
+        constraint_degree: CONSTRAINT_DEGREE,
+        rate_bits: stark_config.fri_config.rate_bits,
+        num_query_rounds: stark_config.fri_config.num_query_rounds,
+    };
+
+    fs::create_dir_all(dir).with_context(|| format!("Creating directory {:?}", dir))?;
+
+    let path = dir.join(format!("{}.structure.json", test_name));
+    let json = serde_json::to_string_pretty(&structure)?;
+    trace_io(IoOp::Write, &path, || fs::write(&path, json))
+        .with_context(|| format!("Writing the proof structure to {:?}", path))
+}
@@ -0,0 +1,159 @@
+//! Loads the `StarkConfig` that tests are proven and verified with from a JSON
+//! file with `--prover-config-file`, instead of always using
+//! `StarkConfig::standard_fast_config()`.
+//!
+//! `StarkConfig` can not be deserialized, so the file is read into a
+//! `StarkConfigSpec` that mirrors it. Any field left out of the file keeps its
+//! value from `standard_fast_config()`, eg.:
+//!
+//! ```json
+//! { "rate_bits": 2, "num_query_rounds": 42 }
+//! ```
+//!
+//! The parameters and what changing them does:
+//! - `rate_bits`: log2 of the FRI blowup factor. Each query round adds about
+//!   this many bits of security, so raising it allows fewer query rounds (a
+//!   smaller proof), at the cost of proving time and memory. Must be at least
+//!   `1`, as the constraints of the EVM STARKs have degree `3`.
+//! - `num_query_rounds`: the number of FRI queries. Security is roughly
+//!   `rate_bits * num_query_rounds + proof_of_work_bits` bits, so lowering it
+//!   weakens the proof. Proof size and verification time grow with it.
+//! - `proof_of_work_bits`: bits of security added by grinding, at the cost of
+//!   proving time (which doubles with each bit).
+//! - `cap_height`: the height of the Merkle caps. Only trades off proof size
+//!   against verification time, so it is always safe to change.
+//! - `reduction_strategy`: how FRI folds the polynomials in each round. Only
+//!   affects performance and proof size, so it is always safe to change.
+//! - `num_challenges`: the number of challenges of the lookup and permutation
+//!   arguments, each giving up to ~64 bits of soundness. Lowering it weakens
+//!   the proof.
+//! - `security_bits`: the security that the other parameters are meant to
+//!   reach. A warning is printed if they fall short of it.
+
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, Context};
+use evm_arithmetization::StarkConfig;
+use log::warn;
+use plonky2::fri::{reduction_strategies::FriReductionStrategy, FriConfig};
+use serde::Deserialize;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct StarkConfigSpec {
+    security_bits: usize,
+    num_challenges: usize,
+    rate_bits: usize,
+    cap_height: usize,
+    proof_of_work_bits: u32,
+    reduction_strategy: FriReductionStrategySpec,
+    num_query_rounds: usize,
+}
+
+/// Mirrors `FriReductionStrategy`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum FriReductionStrategySpec {
+    /// The arity bits of each reduction.
+    Fixed(Vec<usize>),
+    /// Reduce by `arity_bits` until the final polynomial has at most
+    /// `final_poly_bits` bits.
+    ConstantArityBits {
+        arity_bits: usize,
+        final_poly_bits: usize,
+    },
+    /// Pick the reductions that minimize the proof size, with at most
+    /// `max_arity_bits` bits per reduction.
+    MinSize { max_arity_bits: Option<usize> },
+}
+
+impl Default for StarkConfigSpec {
+    fn default() -> Self {
+        let StarkConfig {
+            security_bits,
+            num_challenges,
+            fri_config,
+        } = StarkConfig::standard_fast_config();
+
+        Self {
+            security_bits,
+            num_challenges,
+            rate_bits: fri_config.rate_bits,
+            cap_height: fri_config.cap_height,
+            proof_of_work_bits: fri_config.proof_of_work_bits,
+            reduction_strategy: fri_config.reduction_strategy.into(),
+            num_query_rounds: fri_config.num_query_rounds,
+        }
+    }
+}
+
+impl From<FriReductionStrategy> for FriReductionStrategySpec {
+    fn from(v: FriReductionStrategy) -> Self {
+        match v {
+            FriReductionStrategy::Fixed(arities) => Self::Fixed(arities),
+            FriReductionStrategy::ConstantArityBits(arity_bits, final_poly_bits) => {
+                Self::ConstantArityBits {
+                    arity_bits,
+                    final_poly_bits,
+                }
+            }
+            FriReductionStrategy::MinSize(max_arity_bits) => Self::MinSize { max_arity_bits },
+        }
+    }
+}
+
+impl From<FriReductionStrategySpec> for FriReductionStrategy {
+    fn from(v: FriReductionStrategySpec) -> Self {
+        match v {
+            FriReductionStrategySpec::Fixed(arities) => Self::Fixed(arities),
+            FriReductionStrategySpec::ConstantArityBits {
+                arity_bits,
+                final_poly_bits,
+            } => Self::ConstantArityBits(arity_bits, final_poly_bits),
+            FriReductionStrategySpec::MinSize { max_arity_bits } => Self::MinSize(max_arity_bits),
+        }
+    }
+}
+
+impl StarkConfigSpec {
+    /// The conjectured security (in bits) of FRI with these parameters.
+    fn fri_security_bits(&self) -> usize {
+        self.rate_bits * self.num_query_rounds + self.proof_of_work_bits as usize
+    }
+}
+
+/// Reads the `StarkConfig` in the JSON file at `path`.
+pub(crate) fn load_stark_config(path: &Path) -> anyhow::Result<StarkConfig> {
+    let bytes = fs::read(path).with_context(|| format!("Reading prover config {:?}", path))?;
+    let spec: StarkConfigSpec = serde_json::from_slice(&bytes)
+        .with_context(|| format!("Deserializing prover config {:?}", path))?;
+
+    if spec.rate_bits == 0 {
+        return Err(anyhow!(
+            "The prover config {:?} has a `rate_bits` of 0, but the EVM STARKs need at least 1",
+            path
+        ));
+    }
+
+    if spec.fri_security_bits() < spec.security_bits {
+        warn!(
+            "The prover config {:?} only reaches about {} bits of security, below its \
+             `security_bits` of {}",
+            path,
+            spec.fri_security_bits(),
+            spec.security_bits
+        );
+    }
+
+    Ok(StarkConfig {
+        security_bits: spec.security_bits,
+        num_challenges: spec.num_challenges,
+        fri_config: FriConfig {
+            rate_bits: spec.rate_bits,
+            cap_height: spec.cap_height,
+            proof_of_work_bits: spec.proof_of_work_bits,
+            reduction_strategy: spec.reduction_strategy.into(),
+            num_query_rounds: spec.num_query_rounds,
+        },
+    })
+}
@@ -0,0 +1,48 @@
+//! Writes the public values of the proof of each test with
+//! `--output-public-values`, so that they can be audited without the full
+//! proof.
+
+use std::{fs, path::Path};
+
+use anyhow::Context;
+use ethereum_types::U256;
+use evm_arithmetization::proof::{PublicValues, TrieRoots};
+use serde::Serialize;
+
+use crate::io_trace::{trace_io, IoOp};
+
+/// The values that the test expects the proof to commit to.
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct ExpectedPublicValues {
+    pub(crate) trie_roots_after: TrieRoots,
+    pub(crate) gas_used: U256,
+}
+
+#[derive(Debug, Serialize)]
+struct TestPublicValues<'a> {
+    test_name: &'a str,
+    actual: &'a PublicValues,
+    expected: &'a ExpectedPublicValues,
+}
+
+/// Writes the public values of a proof, along with the values expected by the
+/// test, to `<dir>/<test_name>.public_values.json`.
+pub(crate) fn write_public_values(
+    dir: &Path,
+    test_name: &str,
+    actual: &PublicValues,
+    expected: &ExpectedPublicValues,
+) -> anyhow::Result<()> {
+    let values = TestPublicValues {
+        test_name,
+        actual,
+        expected,
+    };
+
+    fs::create_dir_all(dir).with_context(|| format!("Creating directory {:?}", dir))?;
+
+    let path = dir.join(format!("{}.public_values.json", test_name));
+    let json = serde_json::to_string_pretty(&values)?;
+    trace_io(IoOp::Write, &path, || fs::write(&path, json))
+        .with_context(|| format!("Writing public values to {:?}", path))
+}
@@ -5,18 +5,31 @@
 //! - Generates markdown for all tests that match a string filter output to
 //!   `stdout`. Tests are not displayed in groups and instead are shown in a
 //!   single table with information of failures if any.
+//!
+//! Both reports can optionally include a section that groups failed tests by
+//! their `EvmErr` message, making the most prevalent errors stand out. The
+//! terminal report can also print the full error of each failed test in a box,
+//! or a table that sorts the `EvmErr`s into broad categories.
 
-use std::{fs, path::Path};
+use std::{collections::HashMap, fs, path::Path};
 
 use anyhow::Context;
 use askama::Template;
+use comfy_table::{presets::UTF8_FULL, Table};
+use console::style;
 
-use crate::plonky2_runner::{
-    TestGroupRunResults, TestRunResult, TestStatus, TestSubGroupRunResults,
+use crate::{
+    io_trace::{trace_io, IoOp},
+    plonky2_runner::{
+        SkipReason, TestGroupRunResults, TestRunResult, TestStatus, TestSubGroupRunResults,
+    },
 };
 
 const REPORT_OUTPUT: &str = "reports";
 
+/// The number of example tests to list per error category.
+const NUM_EXAMPLES_PER_ERROR_CATEGORY: usize = 3;
+
 /// Template for writing a summary markdown report to file.
 #[derive(Debug, Template)]
 #[template(path = "filtered_test_results.md")]
@@ -24,6 +37,70 @@ struct FilteredTestResultsTemplate {
     filter_str_template: String,
     passed_info: PassedInfo,
     tests: Vec<TestRunResult>,
+    error_summary: Option<ErrorSummary>,
+}
+
+/// Options controlling how `EvmErr`s are displayed in reports.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ErrorReportOptions {
+    /// Group `EvmErr`s by message and show each unique message once with a
+    /// count.
+    pub(crate) aggregate_errors: bool,
+
+    /// When aggregating, also list the tests that failed with each message.
+    pub(crate) verbose_errors: bool,
+
+    /// Print the error of each failed test in a box below the terminal report.
+    pub(crate) pretty_print_errors: bool,
+}
+
+/// All unique `EvmErr` messages of a run, sorted by the number of tests that
+/// failed with them.
+#[derive(Debug)]
+struct ErrorSummary {
+    errors: Vec<AggregatedError>,
+    verbose: bool,
+}
+
+#[derive(Debug)]
+struct AggregatedError {
+    msg: String,
+    test_names: Vec<String>,
+}
+
+impl AggregatedError {
+    fn count(&self) -> usize {
+        self.test_names.len()
+    }
+}
+
+impl ErrorSummary {
+    fn new(res: &[TestGroupRunResults], opts: ErrorReportOptions) -> Option<Self> {
+        if !opts.aggregate_errors {
+            return None;
+        }
+
+        let mut tests_per_err: HashMap<String, Vec<String>> = HashMap::new();
+        for test in res.iter().flat_map(|g| g.flatten_tests()) {
+            if let TestStatus::EvmErr(msg) = test.status {
+                tests_per_err.entry(msg).or_default().push(test.name);
+            }
+        }
+
+        let mut errors: Vec<_> = tests_per_err
+            .into_iter()
+            .map(|(msg, test_names)| AggregatedError { msg, test_names })
+            .collect();
+
+        // Most prevalent errors first. Ties are broken by message to keep the
+        // output stable between runs.
+        errors.sort_by(|a, b| b.count().cmp(&a.count()).then_with(|| a.msg.cmp(&b.msg)));
+
+        Some(Self {
+            errors,
+            verbose: opts.verbose_errors,
+        })
+    }
 }
 
 impl TestGroupRunResults {
@@ -37,6 +114,9 @@ impl TestGroupRunResults {
                 TestRunResult {
                     name: full_path.to_str().unwrap().to_string(),
                     status: test.status.clone(),
+                    alloc_stats: test.alloc_stats,
+                    duration: test.duration,
+                    proof_size_bytes: test.proof_size_bytes,
                 }
             })
         })
@@ -45,7 +125,11 @@ impl TestGroupRunResults {
 
 impl FilteredTestResultsTemplate {
     // Note: Tests are already filtered from a previous step.
-    fn new(res: &[TestGroupRunResults], filter_str_template: &Option<String>) -> Self {
+    fn new(
+        res: &[TestGroupRunResults],
+        filter_str_template: &Option<String>,
+        err_opts: ErrorReportOptions,
+    ) -> Self {
         let tests: Vec<_> = res.iter().flat_map(|g| g.flatten_tests()).collect();
         let num_passed = tests.iter().filter(|t| t.status.passed()).count();
 
@@ -58,6 +142,7 @@ impl FilteredTestResultsTemplate {
             filter_str_template,
             passed_info: PassedInfo::new(tests.len(), num_passed),
             tests,
+            error_summary: ErrorSummary::new(res, err_opts),
         }
     }
 }
@@ -67,12 +152,16 @@ impl FilteredTestResultsTemplate {
 #[template(path = "test_results_summary.md")]
 struct TestResultsSummaryTemplate {
     groups: Vec<TemplateGroupResultsData>,
+    error_summary: Option<ErrorSummary>,
 }
 
-impl From<Vec<TestGroupRunResults>> for TestResultsSummaryTemplate {
-    fn from(v: Vec<TestGroupRunResults>) -> Self {
+impl TestResultsSummaryTemplate {
+    fn new(v: Vec<TestGroupRunResults>, err_opts: ErrorReportOptions) -> Self {
+        let error_summary = ErrorSummary::new(&v, err_opts);
+
         Self {
             groups: v.into_iter().map(|g| g.into()).collect(),
+            error_summary,
         }
     }
 }
@@ -116,15 +205,7 @@ struct TemplateSubGroupResultsData {
 impl From<TestSubGroupRunResults> for TemplateSubGroupResultsData {
     fn from(v: TestSubGroupRunResults) -> Self {
         let tests: Vec<TestRunResult> = v.test_res.into_iter().collect();
-        let num_passed = tests
-            .iter()
-            .filter(|t| {
-                matches!(
-                    t.status,
-                    TestStatus::PassedProof | TestStatus::PassedWitness
-                )
-            })
-            .count();
+        let num_passed = tests.iter().filter(|t| t.status.passed()).count();
 
         Self {
             name: v.name,
@@ -157,13 +238,255 @@ impl PassedInfo {
 pub(crate) fn output_test_report_for_terminal(
     res: &[TestGroupRunResults],
     test_filter_str: Option<String>,
+    err_opts: ErrorReportOptions,
 ) {
-    let filtered_tests_output_template = FilteredTestResultsTemplate::new(res, &test_filter_str);
+    let filtered_tests_output_template =
+        FilteredTestResultsTemplate::new(res, &test_filter_str, err_opts);
     let report = filtered_tests_output_template
         .render()
         .expect("Error rendering filtered test output markdown");
 
     termimad::print_text(&report);
+
+    if err_opts.pretty_print_errors {
+        output_pretty_errors_for_terminal(res);
+    }
+}
+
+/// Print the error of every failed test in a box, with the test name as the
+/// header. Long multi-line errors are much easier to read this way than in the
+/// report table.
+fn output_pretty_errors_for_terminal(res: &[TestGroupRunResults]) {
+    let failed_tests = res.iter().flat_map(|g| g.flatten_tests()).filter(|t| {
+        !t.status.passed() && !matches!(t.status, TestStatus::Ignored | TestStatus::Skipped(_))
+    });
+
+    for test in failed_tests {
+        let body = test
+            .status
+            .to_string()
+            .lines()
+            .map(|l| format!("    {}", highlight_numbers(l)))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let mut table = Table::new();
+        table
+            .load_preset(UTF8_FULL)
+            .set_header(vec![test.name])
+            .add_row(vec![body]);
+
+        println!("{}", table);
+    }
+}
+
+/// Highlights every word that starts with a digit (decimal numbers, as well as
+/// hex values such as hashes) in cyan.
+fn highlight_numbers(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+
+    while let Some(c) = rest.chars().next() {
+        let word_len = rest
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(rest.len());
+
+        if word_len == 0 {
+            out.push(c);
+            rest = &rest[c.len_utf8()..];
+            continue;
+        }
+
+        let (word, tail) = rest.split_at(word_len);
+        match c.is_ascii_digit() {
+            true => out.push_str(&style(word).cyan().to_string()),
+            false => out.push_str(word),
+        }
+        rest = tail;
+    }
+
+    out
+}
+
+/// A broad category of `EvmErr`, guessed from the error message.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+enum ErrorCategory {
+    StackError,
+    ArithmeticError,
+    OpcodeError,
+    MemoryError,
+    Other,
+}
+
+impl ErrorCategory {
+    /// The keywords (lowercase) that put a message in each category. Checked in
+    /// order, so that eg. a stack overflow is not counted as an arithmetic
+    /// error.
+    const KEYWORDS: [(ErrorCategory, &'static [&'static str]); 4] = [
+        (ErrorCategory::StackError, &["stack"]),
+        (
+            ErrorCategory::ArithmeticError,
+            &[
+                "overflow",
+                "underflow",
+                "integertoolarge",
+                "division",
+                "arithmetic",
+            ],
+        ),
+        (
+            ErrorCategory::OpcodeError,
+            &["opcode", "jumpdestination", "jumpidestination"],
+        ),
+        (ErrorCategory::MemoryError, &["memory", "segment"]),
+    ];
+
+    fn from_err_msg(msg: &str) -> Self {
+        let msg = msg.to_lowercase();
+
+        Self::KEYWORDS
+            .iter()
+            .find(|(_, keywords)| keywords.iter().any(|k| msg.contains(k)))
+            .map_or(ErrorCategory::Other, |(category, _)| *category)
+    }
+}
+
+/// Print a table with the number of tests that failed with an `EvmErr` of each
+/// category, along with a few example tests, to help decide which kind of
+/// error to fix first.
+pub(crate) fn output_error_categories_for_terminal(res: &[TestGroupRunResults]) {
+    let mut tests_per_category: HashMap<ErrorCategory, Vec<String>> = HashMap::new();
+    for test in res.iter().flat_map(|g| g.flatten_tests()) {
+        if let TestStatus::EvmErr(msg) = &test.status {
+            tests_per_category
+                .entry(ErrorCategory::from_err_msg(msg))
+                .or_default()
+                .push(test.name);
+        }
+    }
+
+    let mut categories: Vec<_> = tests_per_category.into_iter().collect();
+    // Ties are broken by category to keep the output stable between runs.
+    categories.sort_by(|(c_a, a), (c_b, b)| b.len().cmp(&a.len()).then_with(|| c_a.cmp(c_b)));
+
+    let mut table = Table::new();
+    table
+        .load_preset(UTF8_FULL)
+        .set_header(vec!["Error category", "Tests", "Examples"]);
+    for (category, test_names) in categories {
+        let examples = test_names
+            .iter()
+            .take(NUM_EXAMPLES_PER_ERROR_CATEGORY)
+            .cloned()
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        table.add_row(vec![
+            format!("{:?}", category),
+            test_names.len().to_string(),
+            examples,
+        ]);
+    }
+
+    println!("{}", table);
+}
+
+/// The options that can cause tests to be skipped, to explain where the skip
+/// reason of each skipped test came from.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct SkipOptions {
+    pub(crate) max_cbor_size: Option<u64>,
+    pub(crate) max_subgroup_failures: Option<usize>,
+}
+
+impl SkipOptions {
+    fn explain(&self, reason: &SkipReason) -> String {
+        match reason {
+            SkipReason::InputTooLarge { size_bytes } => match self.max_cbor_size {
+                Some(max_size) => format!(
+                    "parsed CBOR file is {} bytes, larger than `--max-cbor-size {}`",
+                    size_bytes, max_size
+                ),
+                None => format!("parsed CBOR file is too large ({} bytes)", size_bytes),
+            },
+            SkipReason::AutoSkippedSlow => {
+                "among the slowest tests in its last run (`--auto-skip-slow`)".to_string()
+            }
+            SkipReason::SubgroupFailureLimit => match self.max_subgroup_failures {
+                Some(max_failures) => format!(
+                    "{} tests of its sub-group already failed (`--max-subgroup-failures {}`)",
+                    max_failures, max_failures
+                ),
+                None => "too many tests of its sub-group failed".to_string(),
+            },
+            SkipReason::CorruptedInput => {
+                "parsed CBOR file does not match its checksum (`--verify-hashes`)".to_string()
+            }
+            SkipReason::BlacklistedOpcode(opcode) => format!(
+                "contract code uses opcode {:#04x} (`--opcode-blacklist`)",
+                opcode
+            ),
+            SkipReason::Blacklisted { path, line } => format!(
+                "listed on line {} of the blacklist {:?} (`--blacklist-path`)",
+                line, path
+            ),
+        }
+    }
+}
+
+/// Print every test that was skipped without running, along with why it was
+/// skipped and which option caused it.
+pub(crate) fn output_skipped_tests_for_terminal(
+    skipped_tests: &[(String, SkipReason)],
+    opts: SkipOptions,
+) {
+    println!("\nSkipped tests ({}):", skipped_tests.len());
+    for (t_name, reason) in skipped_tests {
+        println!("  {}: {}", t_name, opts.explain(reason));
+    }
+}
+
+/// Print the `num_tests` tests that allocated the most heap memory to the
+/// terminal. Tests without any recorded allocation stats are skipped.
+pub(crate) fn output_alloc_report_for_terminal(res: &[TestGroupRunResults], num_tests: usize) {
+    let mut tests: Vec<_> = res
+        .iter()
+        .flat_map(|g| g.flatten_tests())
+        .filter_map(|t| t.alloc_stats.map(|stats| (t.name, stats)))
+        .collect();
+    tests.sort_by(|(_, a), (_, b)| b.bytes_allocated.cmp(&a.bytes_allocated));
+
+    println!("Top {} tests by bytes allocated:", num_tests);
+    for (name, stats) in tests.into_iter().take(num_tests) {
+        println!(
+            "{:>16} allocated, {:>16} deallocated: {}",
+            stats.bytes_allocated, stats.bytes_deallocated, name
+        );
+    }
+}
+
+/// Print statistics on the size of the proofs of the tests that recorded one.
+pub(crate) fn output_proof_size_report_for_terminal(res: &[TestGroupRunResults]) {
+    let mut sizes: Vec<_> = res
+        .iter()
+        .flat_map(|g| g.sub_group_res.iter())
+        .flat_map(|sub_g| sub_g.test_res.iter())
+        .filter_map(|t| t.proof_size_bytes)
+        .collect();
+    if sizes.is_empty() {
+        println!("No proof sizes were recorded");
+        return;
+    }
+    sizes.sort_unstable();
+
+    let mean = sizes.iter().sum::<u64>() as f64 / sizes.len() as f64;
+    let p99_idx = ((sizes.len() as f64 * 0.99).ceil() as usize).clamp(1, sizes.len()) - 1;
+
+    println!("Proof sizes of {} tests (bytes):", sizes.len());
+    println!("{:>6}: {:.0}", "mean", mean);
+    println!("{:>6}: {}", "min", sizes[0]);
+    println!("{:>6}: {}", "max", sizes[sizes.len() - 1]);
+    println!("{:>6}: {}", "p99", sizes[p99_idx]);
 }
 
 /// Write a generalized markdown report to file showing the number of passing
@@ -171,8 +494,9 @@ pub(crate) fn output_test_report_for_terminal(
 /// specific test failures.
 pub(crate) fn write_overall_status_report_summary_to_file(
     res: Vec<TestGroupRunResults>,
+    err_opts: ErrorReportOptions,
 ) -> anyhow::Result<()> {
-    let overall_summary_template: TestResultsSummaryTemplate = res.into();
+    let overall_summary_template = TestResultsSummaryTemplate::new(res, err_opts);
     let report = overall_summary_template
         .render()
         .expect("Error rendering summary report markdown");
@@ -181,7 +505,115 @@ pub(crate) fn write_overall_status_report_summary_to_file(
     fs::create_dir_all(summary_path.parent().unwrap())
         .with_context(|| format!("Creating report subdirectory {}", REPORT_OUTPUT))?;
 
-    fs::write(&summary_path, report)
-        .with_context(|| format!("Writing report to {:?}", summary_path))?;
+    trace_io(IoOp::Write, &summary_path, || {
+        fs::write(&summary_path, report)
+    })
+    .with_context(|| format!("Writing report to {:?}", summary_path))?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use console::style;
+
+    use super::{highlight_numbers, ErrorCategory, SkipOptions};
+    use crate::plonky2_runner::SkipReason;
+
+    fn cyan(s: &str) -> String {
+        style(s).cyan().to_string()
+    }
+
+    #[test]
+    fn highlight_numbers_highlights_numbers_and_hex_values() {
+        assert_eq!(
+            highlight_numbers("used 21000 gas at 0xdeadbeef."),
+            format!("used {} gas at {}.", cyan("21000"), cyan("0xdeadbeef"))
+        );
+    }
+
+    #[test]
+    fn highlight_numbers_ignores_words_containing_digits() {
+        assert_eq!(highlight_numbers("PUSH32 keccak256"), "PUSH32 keccak256");
+    }
+
+    #[test]
+    fn highlight_numbers_keeps_non_ascii_characters() {
+        assert_eq!(highlight_numbers("é→7 ü"), format!("é→{} ü", cyan("7")));
+    }
+
+    #[test]
+    fn highlight_numbers_of_empty_line() {
+        assert_eq!(highlight_numbers(""), "");
+    }
+
+    #[test]
+    fn error_category_prefers_stack_over_arithmetic() {
+        assert_eq!(
+            ErrorCategory::from_err_msg("Stack overflow"),
+            ErrorCategory::StackError
+        );
+        assert_eq!(
+            ErrorCategory::from_err_msg("StackUnderflow"),
+            ErrorCategory::StackError
+        );
+    }
+
+    #[test]
+    fn error_category_from_keywords() {
+        assert_eq!(
+            ErrorCategory::from_err_msg("IntegerTooLarge"),
+            ErrorCategory::ArithmeticError
+        );
+        assert_eq!(
+            ErrorCategory::from_err_msg("InvalidOpcode"),
+            ErrorCategory::OpcodeError
+        );
+        assert_eq!(
+            ErrorCategory::from_err_msg("InvalidJumpDestination"),
+            ErrorCategory::OpcodeError
+        );
+        assert_eq!(
+            ErrorCategory::from_err_msg("Out of bounds access to memory segment"),
+            ErrorCategory::MemoryError
+        );
+    }
+
+    #[test]
+    fn error_category_of_unknown_message_is_other() {
+        assert_eq!(
+            ErrorCategory::from_err_msg("Kernel panic"),
+            ErrorCategory::Other
+        );
+    }
+
+    #[test]
+    fn explain_skipped_shows_cbor_size_and_limit() {
+        let opts = SkipOptions {
+            max_cbor_size: Some(1000),
+            max_subgroup_failures: None,
+        };
+
+        assert_eq!(
+            opts.explain(&SkipReason::InputTooLarge { size_bytes: 1234 }),
+            "parsed CBOR file is 1234 bytes, larger than `--max-cbor-size 1000`"
+        );
+    }
+
+    #[test]
+    fn explain_skipped_shows_blacklist_file_and_line() {
+        let opts = SkipOptions {
+            max_cbor_size: None,
+            max_subgroup_failures: None,
+        };
+
+        assert_eq!(
+            opts.explain(&SkipReason::Blacklisted {
+                path: PathBuf::from("blacklist.txt"),
+                line: 3,
+            }),
+            "listed on line 3 of the blacklist \"blacklist.txt\" (`--blacklist-path`)"
+        );
+    }
+}
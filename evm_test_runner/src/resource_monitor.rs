@@ -0,0 +1,113 @@
+//! Periodically samples the resource usage of the runner with
+//! `--monitor-state-drift`, to spot memory leaks or gradual slowdowns over long
+//! runs. The samples can be lined up with the test timings by their timestamps.
+//!
+//! Samples are appended to an NDJSON file as they are taken, so that the
+//! timeline survives the run being killed. Only the runner process itself is
+//! sampled, which excludes the child processes of `--test-isolation`.
+
+use std::{
+    fs::{self, OpenOptions},
+    io::{LineWriter, Write},
+    sync::mpsc::{self, RecvTimeoutError},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context};
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde::Serialize;
+use sysinfo::{Pid, System};
+
+const RESOURCE_TIMELINE_PATH_STR: &str = "resource_timeline.jsonl";
+
+#[derive(Debug, Serialize)]
+struct ResourceSample {
+    timestamp: DateTime<Utc>,
+    memory_bytes: u64,
+    virtual_memory_bytes: u64,
+    cpu_usage_percent: f32,
+    /// Only available on Linux.
+    open_fds: Option<usize>,
+}
+
+/// Samples the resource usage of the runner on a background thread until
+/// dropped.
+#[derive(Debug)]
+pub(crate) struct ResourceMonitor {
+    stop_send: Option<mpsc::Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl ResourceMonitor {
+    pub(crate) fn start(interval: Duration) -> anyhow::Result<Self> {
+        let pid = sysinfo::get_current_pid().map_err(|err| anyhow!(err))?;
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(RESOURCE_TIMELINE_PATH_STR)
+            .with_context(|| format!("Opening resource timeline {}", RESOURCE_TIMELINE_PATH_STR))?;
+
+        println!(
+            "Writing resource usage every {:?} to {}...",
+            interval, RESOURCE_TIMELINE_PATH_STR
+        );
+
+        let (stop_send, stop_recv) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            let mut writer = LineWriter::new(file);
+            let mut sys = System::new();
+
+            // Stops once the monitor is dropped.
+            while let Err(RecvTimeoutError::Timeout) = stop_recv.recv_timeout(interval) {
+                let Some(sample) = take_sample(&mut sys, pid) else {
+                    continue;
+                };
+
+                if let Err(err) = write_sample(&mut writer, &sample) {
+                    warn!("Unable to write a resource usage sample: {:#}", err);
+                }
+            }
+        });
+
+        Ok(Self {
+            stop_send: Some(stop_send),
+            handle: Some(handle),
+        })
+    }
+}
+
+impl Drop for ResourceMonitor {
+    fn drop(&mut self) {
+        // Dropping the sender wakes up the sampling thread.
+        self.stop_send.take();
+
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn write_sample(writer: &mut impl Write, sample: &ResourceSample) -> anyhow::Result<()> {
+    serde_json::to_writer(&mut *writer, sample)
+        .with_context(|| "Serializing resource usage sample")?;
+    writeln!(writer)?;
+
+    Ok(())
+}
+
+fn take_sample(sys: &mut System, pid: Pid) -> Option<ResourceSample> {
+    sys.refresh_process(pid);
+    let process = sys.process(pid)?;
+
+    Some(ResourceSample {
+        timestamp: Utc::now(),
+        memory_bytes: process.memory(),
+        virtual_memory_bytes: process.virtual_memory(),
+        cpu_usage_percent: process.cpu_usage(),
+        open_fds: fs::read_dir("/proc/self/fd")
+            .ok()
+            .map(|entries| entries.count()),
+    })
+}
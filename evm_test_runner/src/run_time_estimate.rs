@@ -0,0 +1,58 @@
+//! Predicts how long a run will take with `--estimate-total-time`, from how
+//! long each test took in its last run according to the persistent state.
+
+use std::time::Duration;
+
+use crate::persistent_run_state::TestRunEntries;
+
+/// Prints how long running `t_names` on `worker_count` workers should take.
+/// Tests without a recorded duration are assumed to take as long as the mean
+/// of those with one.
+pub(crate) fn print_total_time_estimate<'a>(
+    t_names: impl Iterator<Item = &'a str>,
+    pass_state: &TestRunEntries,
+    worker_count: usize,
+) {
+    let mut known_total = Duration::ZERO;
+    let mut num_known = 0;
+    let mut num_unknown = 0;
+
+    for t_name in t_names {
+        match pass_state.get_last_duration(t_name) {
+            Some(duration) => {
+                known_total += duration;
+                num_known += 1;
+            }
+            None => num_unknown += 1,
+        }
+    }
+
+    if num_known == 0 {
+        println!(
+            "None of the {} tests have a recorded duration, so the run time can not be \
+             estimated.",
+            num_unknown
+        );
+        return;
+    }
+
+    let mean = known_total / num_known;
+    let total = (known_total + mean * num_unknown) / worker_count.max(1) as u32;
+    // Anything below a minute is noise for a whole run.
+    let total = Duration::from_secs(total.as_secs() / 60 * 60);
+
+    println!(
+        "Estimated run time: ~{} ({} tests, mean of {:.1}s per test, {} workers)",
+        humantime::format_duration(total),
+        num_known + num_unknown,
+        mean.as_secs_f64(),
+        worker_count.max(1)
+    );
+
+    if num_unknown > 0 {
+        println!(
+            "{} tests have no recorded duration and were assumed to take the mean time.",
+            num_unknown
+        );
+    }
+}
@@ -0,0 +1,105 @@
+//! A minimal "hello world" for the prover. Proves a block containing no
+//! transactions to check that `plonky2` is correctly set up before starting a
+//! full (and much longer) test run.
+
+use std::{collections::HashMap, time::Instant};
+
+use anyhow::Context;
+use ethereum_types::{BigEndianHash, H256};
+use evm_arithmetization::{
+    generation::{GenerationInputs, TrieInputs},
+    proof::{BlockHashes, BlockMetadata, TrieRoots},
+    prover::prove,
+    verifier::verify_proof,
+    AllStark, Node, StarkConfig,
+};
+use keccak_hash::keccak;
+use mpt_trie::partial_trie::{HashedPartialTrie, PartialTrie};
+use plonky2::{
+    field::goldilocks_field::GoldilocksField, plonk::config::KeccakGoldilocksConfig,
+    util::timing::TimingTree,
+};
+
+/// Runs the sanity check and prints the outcome to `stdout`.
+pub(crate) fn run_sanity_check() -> anyhow::Result<()> {
+    println!("Proving an empty block to check that the prover is working...");
+    let start = Instant::now();
+
+    match prove_and_verify_empty_block() {
+        Ok(()) => {
+            println!(
+                "Sanity check passed in {}.",
+                humantime::format_duration(start.elapsed())
+            );
+            Ok(())
+        }
+        Err(err) => {
+            println!("Sanity check failed with error: {:#}", err);
+            println!(
+                "Proving a block without any transactions should always succeed. Check that the \
+                 `evm_arithmetization` and `plonky2` versions in `Cargo.toml` are compatible \
+                 with each other, that the runner was built with a nightly toolchain (see \
+                 `rust-toolchain.toml`), and that the tests were parsed with the same version of \
+                 `evm_arithmetization`."
+            );
+            Err(err)
+        }
+    }
+}
+
+fn prove_and_verify_empty_block() -> anyhow::Result<()> {
+    let all_stark = AllStark::default();
+    let config = StarkConfig::standard_fast_config();
+
+    let proof = prove::<GoldilocksField, KeccakGoldilocksConfig, 2>(
+        &all_stark,
+        &config,
+        empty_block_inputs(),
+        &mut TimingTree::default(),
+        None,
+    )
+    .with_context(|| "Proving the empty block")?;
+
+    verify_proof(&all_stark, proof, &config).with_context(|| "Verifying the empty block proof")
+}
+
+/// Generation inputs with no transactions, so none of the tries are modified.
+fn empty_block_inputs() -> GenerationInputs {
+    let state_trie = HashedPartialTrie::from(Node::Empty);
+    let transactions_trie = HashedPartialTrie::from(Node::Empty);
+    let receipts_trie = HashedPartialTrie::from(Node::Empty);
+
+    let trie_roots_after = TrieRoots {
+        state_root: state_trie.hash(),
+        transactions_root: transactions_trie.hash(),
+        receipts_root: receipts_trie.hash(),
+    };
+
+    let mut prev_hashes = vec![H256::default(); 256];
+    prev_hashes[255] = H256::from_uint(&0x200.into());
+
+    GenerationInputs {
+        signed_txn: None,
+        withdrawals: vec![],
+        checkpoint_state_trie_root: state_trie.hash(),
+        tries: TrieInputs {
+            state_trie,
+            transactions_trie,
+            receipts_trie,
+            storage_tries: vec![],
+        },
+        trie_roots_after,
+        contract_code: HashMap::from([(keccak([]), vec![])]),
+        block_metadata: BlockMetadata {
+            block_number: 1.into(),
+            ..Default::default()
+        },
+        txn_number_before: 0.into(),
+        gas_used_before: 0.into(),
+        gas_used_after: 0.into(),
+        block_hashes: BlockHashes {
+            prev_hashes,
+            cur_hash: H256::default(),
+        },
+    }
+}
@@ -0,0 +1,41 @@
+//! Runs a small random sample of the tests before the full suite, to catch a
+//! broken prover setup in minutes instead of hours into a run.
+
+use crate::plonky2_runner::{TestGroupRunResults, TestStatus};
+
+/// Checks the results of the smoke tests and prints the outcome to `stdout`.
+/// Returns `false` if the prover panicked or generated a proof that does not
+/// verify for any test, which usually means that the prover itself is
+/// misconfigured rather than that the test found a bug. Tests that fail
+/// gracefully with an EVM error (eg. a state root mismatch) do not abort the
+/// run.
+pub(crate) fn smoke_tests_passed(res: &[TestGroupRunResults]) -> bool {
+    let setup_failures: Vec<_> = res
+        .iter()
+        .flat_map(|g| g.sub_group_res.iter())
+        .flat_map(|sub_g| sub_g.test_res.iter())
+        .filter(|t| {
+            matches!(
+                t.status,
+                TestStatus::Panicked(_) | TestStatus::ProofVerificationFailed(_)
+            )
+        })
+        .collect();
+
+    if setup_failures.is_empty() {
+        println!("Smoke tests completed without any prover failures. Starting the full run...");
+        return true;
+    }
+
+    println!("{} smoke tests failed in the prover:", setup_failures.len());
+    for t in setup_failures {
+        println!("{}: {}", t.name, t.status);
+    }
+    println!(
+        "Aborting before the full run. Run `evm_test_runner sanity-check` to check that the \
+         prover is set up correctly, and make sure that the tests were parsed with the same \
+         version of `evm_arithmetization` that the runner was built with."
+    );
+
+    false
+}
@@ -0,0 +1,159 @@
+//! Handlers for the subcommands of the runner (and `--test-stdin`), which do
+//! something other than a full test run.
+
+use std::path::{Path, PathBuf};
+
+use common::types::VariantFilterType;
+use regex::Regex;
+
+use crate::{
+    arg_parsing::Command,
+    checkpoint::{merge_results_files, print_checkpoint_schema, write_results_file},
+    persistent_run_state::load_existing_pass_state_from_disk_if_exists_or_create,
+    plonky2_runner::{run_single_test, TestRunConfig},
+    sanity_check::run_sanity_check,
+    test_dir_reading::{
+        get_default_parsed_tests_path, read_in_all_parsed_tests,
+        read_in_all_parsed_tests_from_ndjson, read_parsed_test_from_stdin,
+        read_parsed_test_variant, read_parsed_test_variant_from_stdin,
+    },
+    test_fetching::fetch_test_archive,
+    test_metadata_report::output_test_metadata_report,
+    timing_history::show_timing_trends,
+};
+
+/// Where the tests of a run are read from, for subcommands that read the same
+/// tests as a full run would.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct TestSource<'a> {
+    pub(crate) test_ndjson: Option<&'a Path>,
+    pub(crate) network_fetch_tests: Option<&'a str>,
+    pub(crate) variant_filter: Option<&'a VariantFilterType>,
+    pub(crate) test_version_check: bool,
+}
+
+pub(crate) async fn run_command(
+    command: Command,
+    test_source: TestSource<'_>,
+    run_config: &TestRunConfig,
+) -> anyhow::Result<()> {
+    match command {
+        Command::SanityCheck => run_sanity_check(),
+        Command::OutputSchema => print_checkpoint_schema(),
+        Command::MergeResults { files, output } => merge_results(&files, &output),
+        Command::ExportStateCsv { output } => export_state_csv(&output),
+        Command::ShowTimingTrends { test_key } => show_timing_trends(&test_key),
+        Command::TestMetadataReport { json, filter } => {
+            test_metadata_report(test_source, filter.as_ref(), json).await
+        }
+        Command::RunSingle {
+            test_file,
+            variant,
+            stdin,
+        } => {
+            run_single(
+                test_file,
+                variant,
+                stdin,
+                test_source.test_version_check,
+                run_config,
+            )
+            .await
+        }
+    }
+}
+
+fn merge_results(files: &[PathBuf], output: &Path) -> anyhow::Result<()> {
+    let (merged_res, duplicate_t_names) = merge_results_files(files)?;
+    if !duplicate_t_names.is_empty() {
+        println!("Tests in more than one results file (only the first result was kept):");
+        for t_name in duplicate_t_names.iter() {
+            println!("  {}", t_name);
+        }
+    }
+
+    write_results_file(output, &merged_res)?;
+    println!(
+        "Wrote the merged results of {} files to {:?}",
+        files.len(),
+        output
+    );
+
+    Ok(())
+}
+
+fn export_state_csv(output: &Path) -> anyhow::Result<()> {
+    load_existing_pass_state_from_disk_if_exists_or_create().export_csv(output)?;
+    println!("Wrote the persistent test state to {:?}", output);
+
+    Ok(())
+}
+
+async fn test_metadata_report(
+    test_source: TestSource<'_>,
+    filter: Option<&Regex>,
+    json: bool,
+) -> anyhow::Result<()> {
+    // Read the same tests that a run with these arguments would.
+    let variant_filter = test_source.variant_filter.cloned();
+    let parsed_tests = match test_source.test_ndjson {
+        Some(ndjson_path) => read_in_all_parsed_tests_from_ndjson(
+            ndjson_path,
+            None,
+            variant_filter,
+            None,
+            test_source.test_version_check,
+        )?,
+        None => {
+            let parsed_tests_path = match test_source.network_fetch_tests {
+                Some(url) => fetch_test_archive(url).await?,
+                None => get_default_parsed_tests_path()?,
+            };
+
+            read_in_all_parsed_tests(
+                &parsed_tests_path,
+                None,
+                variant_filter,
+                None,
+                test_source.test_version_check,
+                None,
+                false,
+            )
+            .await?
+        }
+    };
+
+    output_test_metadata_report(&parsed_tests, filter, json)
+}
+
+async fn run_single(
+    test_file: PathBuf,
+    variant: usize,
+    stdin: bool,
+    test_version_check: bool,
+    run_config: &TestRunConfig,
+) -> anyhow::Result<()> {
+    let test = match stdin {
+        false => read_parsed_test_variant(test_file, variant, test_version_check).await?,
+        true => read_parsed_test_variant_from_stdin(test_file, variant, test_version_check)?,
+    };
+    let t_res = run_single_test(test, run_config);
+    println!("{}", serde_json::to_string(&t_res)?);
+
+    Ok(())
+}
+
+/// Runs every variant of the parsed test read from `stdin` with
+/// `--test-stdin`, printing the result of each one.
+pub(crate) fn run_tests_from_stdin(
+    variant_filter: Option<VariantFilterType>,
+    test_version_check: bool,
+    run_config: &TestRunConfig,
+) -> anyhow::Result<()> {
+    for test in read_parsed_test_from_stdin(variant_filter, test_version_check)? {
+        let t_res = run_single_test(test, run_config);
+        println!("{}", serde_json::to_string(&t_res)?);
+    }
+
+    Ok(())
+}
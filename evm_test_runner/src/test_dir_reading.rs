@@ -9,23 +9,37 @@
 // High code duplication. Difficult to reduce, but may want to tackle later.
 
 use std::{
-    collections::HashSet,
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::File,
+    io::{self, BufRead, BufReader, Read},
     path::{Path, PathBuf},
     sync::Arc,
 };
 
 use anyhow::{anyhow, Context};
 use common::{
-    config::{GENERATION_INPUTS_DEFAULT_OUTPUT_DIR, MAIN_TEST_DIR},
-    types::{ParsedTestManifest, TestVariantRunInfo, VariantFilterType},
+    config::{
+        CHECKSUMS_FILE_NAME, GENERATION_INPUTS_DEFAULT_OUTPUT_DIR, MAIN_TEST_DIR,
+        PARSED_TEST_SCHEMA_VERSION,
+    },
+    types::{
+        ParsedTestManifest, ParsedTestManifestVersion, ParsedTestNdjsonEntry, TestVariantRunInfo,
+        VariantFilterType,
+    },
+    utils::sha256_hex,
 };
-use log::{info, trace};
+use log::{info, trace, warn};
 use tokio::{
     fs::{self, read_dir},
     task::JoinSet,
 };
 use tokio_stream::{wrappers::ReadDirStream, StreamExt};
 
+use crate::{
+    io_trace::{trace_io, trace_io_async, IoOp},
+    plonky2_runner::SkipReason,
+};
+
 #[derive(Debug)]
 pub(crate) struct ParsedTestGroup {
     pub(crate) name: String,
@@ -36,11 +50,23 @@ pub(crate) struct ParsedTestGroup {
 pub(crate) struct ParsedTestSubGroup {
     pub(crate) name: String,
     pub(crate) tests: Vec<Test>,
+    /// Test files that were not read in. These are named after the file, as
+    /// we never learn the names of the variants inside.
+    pub(crate) skipped_tests: Vec<SkippedTest>,
+}
+
+#[derive(Debug)]
+pub(crate) struct SkippedTest {
+    pub(crate) name: String,
+    pub(crate) reason: SkipReason,
 }
 
 #[derive(Debug)]
 pub(crate) struct Test {
     pub(crate) name: String,
+    /// The parsed test file that the test was read from. `None` if it was read
+    /// from `stdin`.
+    pub(crate) path: Option<PathBuf>,
     pub(crate) info: TestVariantRunInfo,
 }
 
@@ -62,13 +88,26 @@ pub(crate) fn get_default_parsed_tests_path() -> anyhow::Result<PathBuf> {
         })
 }
 
+/// Checksums of parsed test files, keyed by the path of the file.
+type Checksums = HashMap<PathBuf, String>;
+
 /// Reads in all parsed tests from the given parsed test directory.
+///
+/// If `verify_hashes` is set, test files that do not match their checksum in
+/// `CHECKSUMS_FILE_NAME` are skipped instead of being read in.
 pub(crate) async fn read_in_all_parsed_tests(
     parsed_tests_path: &Path,
     filter_str: Option<String>,
     variant_filter: Option<VariantFilterType>,
     blacklist: Option<Arc<HashSet<String>>>,
+    version_check: bool,
+    max_cbor_size: Option<u64>,
+    verify_hashes: bool,
 ) -> anyhow::Result<Vec<ParsedTestGroup>> {
+    let checksums = match verify_hashes {
+        true => Some(Arc::new(read_checksums(parsed_tests_path).await?)),
+        false => None,
+    };
     let (mut groups, mut join_set, mut read_dirs) =
         parse_dir_init(Path::new(parsed_tests_path)).await?;
 
@@ -84,6 +123,9 @@ pub(crate) async fn read_in_all_parsed_tests(
             filter_str.clone(),
             variant_filter.clone(),
             blacklist.clone(),
+            version_check,
+            max_cbor_size,
+            checksums.clone(),
         ));
     }
 
@@ -92,11 +134,41 @@ pub(crate) async fn read_in_all_parsed_tests(
     Ok(groups)
 }
 
+/// Reads the checksums written by `eth_test_parser` to `CHECKSUMS_FILE_NAME`
+/// in the parsed test directory.
+async fn read_checksums(parsed_tests_path: &Path) -> anyhow::Result<Checksums> {
+    let path = parsed_tests_path.join(CHECKSUMS_FILE_NAME);
+    let contents = trace_io_async(IoOp::Read, &path, fs::read_to_string(&path))
+        .await
+        .with_context(|| {
+            format!(
+                "Reading the checksums of the parsed tests from {:?}. Re-run the parser to \
+             generate it.",
+                path
+            )
+        })?;
+
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let (checksum, rel_path) = line
+                .split_once("  ")
+                .ok_or_else(|| anyhow!("Malformed line {:?} in {:?}", line, path))?;
+
+            Ok((parsed_tests_path.join(rel_path), checksum.to_string()))
+        })
+        .collect()
+}
+
 async fn parse_test_group(
     path: PathBuf,
     filter_str: Option<String>,
     variant_filter: Option<VariantFilterType>,
     blacklist: Option<Arc<HashSet<String>>>,
+    version_check: bool,
+    max_cbor_size: Option<u64>,
+    checksums: Option<Arc<Checksums>>,
 ) -> anyhow::Result<ParsedTestGroup> {
     info!("Reading in test group {:?}...", path);
     let (mut sub_groups, mut join_set, mut read_dirs) = parse_dir_init(&path).await?;
@@ -113,6 +185,9 @@ async fn parse_test_group(
             filter_str.clone(),
             variant_filter.clone(),
             blacklist.clone(),
+            version_check,
+            max_cbor_size,
+            checksums.clone(),
         ));
     }
 
@@ -129,9 +204,13 @@ async fn parse_test_sub_group(
     filter_str: Option<String>,
     variant_filter: Option<VariantFilterType>,
     blacklist: Option<Arc<HashSet<String>>>,
+    version_check: bool,
+    max_cbor_size: Option<u64>,
+    checksums: Option<Arc<Checksums>>,
 ) -> anyhow::Result<ParsedTestSubGroup> {
     trace!("Reading in test subgroup {:?}...", path);
-    let (mut tests, mut join_set, mut read_dirs) = parse_dir_init(&path).await?;
+    let (mut parsed_tests, mut join_set, mut read_dirs) = parse_dir_init(&path).await?;
+    let mut skipped_tests = Vec::new();
 
     while let Some(entry) = read_dirs.next().await {
         let entry = entry?;
@@ -141,18 +220,58 @@ async fn parse_test_sub_group(
             continue;
         }
 
-        join_set.spawn(parse_test(
+        let size_bytes = entry.metadata().await?.len();
+        if max_cbor_size.is_some_and(|max_size| size_bytes > max_size) {
+            // Tests skipped for their size are named after their file, so a
+            // resumed checkpoint that already has the skip leaves it out
+            // instead of skipping it again.
+            let name = get_file_stem(&file_path)?;
+            if !blacklisted(blacklist.as_deref(), &name) {
+                skipped_tests.push(SkippedTest {
+                    name,
+                    reason: SkipReason::InputTooLarge { size_bytes },
+                });
+            }
+            continue;
+        }
+
+        let expected_checksum = match &checksums {
+            Some(checksums) => {
+                let checksum = checksums.get(&file_path).cloned();
+                if checksum.is_none() {
+                    warn!(
+                        "No checksum found for {:?}, reading it unverified",
+                        file_path
+                    );
+                }
+                checksum
+            }
+            None => None,
+        };
+
+        join_set.spawn(parse_test_if_checksum_matches(
             file_path,
+            expected_checksum,
             variant_filter.clone(),
             blacklist.clone(),
+            version_check,
         ));
     }
 
-    wait_for_task_to_finish_and_extend_vec(&mut join_set, &mut tests).await?;
+    wait_for_task_to_finish_and_push_to_vec(&mut join_set, &mut parsed_tests).await?;
+
+    let mut tests = Vec::new();
+    for parsed_test in parsed_tests {
+        match parsed_test {
+            Ok(parsed_test) => tests.extend(parsed_test),
+            Err(skipped_test) => skipped_tests.push(skipped_test),
+        }
+    }
 
     Ok(ParsedTestSubGroup {
         name: get_file_stem(&path)?,
         tests,
+        skipped_tests,
     })
 }
 
@@ -172,24 +291,299 @@ async fn parse_test(
     path: PathBuf,
     variant_filter: Option<VariantFilterType>,
     blacklist: Option<Arc<HashSet<String>>>,
+    version_check: bool,
 ) -> anyhow::Result<Vec<Test>> {
     trace!("Reading in {:?}...", path);
 
-    let parsed_test_bytes = fs::read(&path).await?;
-    let parsed_test: ParsedTestManifest = serde_cbor::from_slice(&parsed_test_bytes)
+    let parsed_test_bytes = trace_io_async(IoOp::Read, &path, fs::read(&path)).await?;
+
+    parse_test_bytes(
+        &path,
+        &parsed_test_bytes,
+        variant_filter,
+        blacklist,
+        version_check,
+    )
+}
+
+/// Like `parse_test`, but skips the test instead if its file does not match
+/// `expected_checksum`.
+async fn parse_test_if_checksum_matches(
+    path: PathBuf,
+    expected_checksum: Option<String>,
+    variant_filter: Option<VariantFilterType>,
+    blacklist: Option<Arc<HashSet<String>>>,
+    version_check: bool,
+) -> anyhow::Result<Result<Vec<Test>, SkippedTest>> {
+    trace!("Reading in {:?}...", path);
+
+    let parsed_test_bytes = trace_io_async(IoOp::Read, &path, fs::read(&path)).await?;
+
+    if let Some(expected_checksum) = expected_checksum
+        && sha256_hex(&parsed_test_bytes) != expected_checksum
+    {
+        warn!("The test {:?} does not match its checksum. Skipping!", path);
+        return Ok(Err(SkippedTest {
+            name: get_file_stem(&path)?,
+            reason: SkipReason::CorruptedInput,
+        }));
+    }
+
+    parse_test_bytes(
+        &path,
+        &parsed_test_bytes,
+        variant_filter,
+        blacklist,
+        version_check,
+    )
+    .map(Ok)
+}
+
+fn parse_test_bytes(
+    path: &Path,
+    parsed_test_bytes: &[u8],
+    variant_filter: Option<VariantFilterType>,
+    blacklist: Option<Arc<HashSet<String>>>,
+    version_check: bool,
+) -> anyhow::Result<Vec<Test>> {
+    if version_check {
+        check_schema_version(path, parsed_test_bytes)?;
+    }
+
+    let parsed_test: ParsedTestManifest = serde_cbor::from_slice(parsed_test_bytes)
         .unwrap_or_else(|_| panic!("Unable to parse the test {:?} (bad format)", path));
 
+    Ok(into_tests(
+        parsed_test,
+        Some(path),
+        variant_filter,
+        blacklist.as_deref(),
+    ))
+}
+
+/// Reads in all parsed tests from an NDJSON file written by `eth_test_parser
+/// --output-generation-inputs-jsonl`. The group and sub-group of each test
+/// are taken from the last directories of its path, like when reading from
+/// the parsed test directory.
+pub(crate) fn read_in_all_parsed_tests_from_ndjson(
+    ndjson_path: &Path,
+    filter_str: Option<String>,
+    variant_filter: Option<VariantFilterType>,
+    blacklist: Option<Arc<HashSet<String>>>,
+    version_check: bool,
+) -> anyhow::Result<Vec<ParsedTestGroup>> {
+    info!("Reading in tests from {:?}...", ndjson_path);
+    let file = trace_io(IoOp::Open, ndjson_path, || File::open(ndjson_path))
+        .with_context(|| format!("Opening test NDJSON file {:?}", ndjson_path))?;
+
+    let mut groups: BTreeMap<String, BTreeMap<String, Vec<Test>>> = BTreeMap::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let ParsedTestNdjsonEntry { path, test } = serde_json::from_str(&line)
+            .with_context(|| format!("Parsing a test from {:?} (bad format)", ndjson_path))?;
+
+        if test_is_not_in_filter_str(&filter_str, &path) {
+            continue;
+        }
+
+        if version_check {
+            check_schema_version_matches(&path, test.schema_version)?;
+        }
+
+        let sub_group_path = path
+            .parent()
+            .with_context(|| format!("The test path {:?} has no sub-group", path))?;
+        let group_path = sub_group_path
+            .parent()
+            .with_context(|| format!("The test path {:?} has no group", path))?;
+
+        groups
+            .entry(get_file_stem(group_path)?)
+            .or_default()
+            .entry(get_file_stem(sub_group_path)?)
+            .or_default()
+            .extend(into_tests(
+                test,
+                None,
+                variant_filter.clone(),
+                blacklist.as_deref(),
+            ));
+    }
+
+    Ok(groups
+        .into_iter()
+        .map(|(name, sub_groups)| ParsedTestGroup {
+            name,
+            sub_groups: sub_groups
+                .into_iter()
+                .map(|(name, tests)| ParsedTestSubGroup {
+                    name,
+                    tests,
+                    skipped_tests: Vec::new(),
+                })
+                .collect(),
+        })
+        .collect())
+}
+
+/// Reads in a single variant of the parsed test at `path`.
+pub(crate) async fn read_parsed_test_variant(
+    path: PathBuf,
+    variant_idx: usize,
+    version_check: bool,
+) -> anyhow::Result<Test> {
+    parse_test(
+        path.clone(),
+        Some(VariantFilterType::Single(variant_idx)),
+        None,
+        version_check,
+    )
+    .await?
+    .pop()
+    .ok_or_else(|| anyhow!("The test {:?} has no variant {}", path, variant_idx))
+}
+
+/// Like `read_parsed_test_variant`, but reads the contents of the file at
+/// `path` from `stdin` (see `--preload-cbor`).
+pub(crate) fn read_parsed_test_variant_from_stdin(
+    path: PathBuf,
+    variant_idx: usize,
+    version_check: bool,
+) -> anyhow::Result<Test> {
+    let mut parsed_test_bytes = Vec::new();
+    io::stdin()
+        .read_to_end(&mut parsed_test_bytes)
+        .with_context(|| format!("Reading the test {:?} from stdin", path))?;
+
+    parse_test_bytes(
+        &path,
+        &parsed_test_bytes,
+        Some(VariantFilterType::Single(variant_idx)),
+        None,
+        version_check,
+    )?
+    .pop()
+    .ok_or_else(|| anyhow!("The test {:?} has no variant {}", path, variant_idx))
+}
+
+/// Reads in a single parsed test (in the same CBOR format as the files in the
+/// parsed test directory) from `stdin`.
+pub(crate) fn read_parsed_test_from_stdin(
+    variant_filter: Option<VariantFilterType>,
+    version_check: bool,
+) -> anyhow::Result<Vec<Test>> {
+    let mut parsed_test_bytes = Vec::new();
+    io::stdin()
+        .read_to_end(&mut parsed_test_bytes)
+        .with_context(|| "Reading test from stdin")?;
+
+    if version_check {
+        check_schema_version(Path::new("<stdin>"), &parsed_test_bytes)?;
+    }
+
+    let parsed_test: ParsedTestManifest = serde_cbor::from_slice(&parsed_test_bytes)
+        .with_context(|| "Unable to parse the test from stdin (bad format)")?;
+
+    Ok(into_tests(parsed_test, None, variant_filter, None))
+}
+
+fn into_tests(
+    parsed_test: ParsedTestManifest,
+    path: Option<&Path>,
+    variant_filter: Option<VariantFilterType>,
+    blacklist: Option<&HashSet<String>>,
+) -> Vec<Test> {
     let v_out = parsed_test.into_filtered_variants(variant_filter);
 
-    let blacklist_ref = blacklist.as_deref();
-    Ok(v_out
+    v_out
         .variants
         .into_iter()
         .filter_map(|info| {
             let name = info.variant_name.clone();
-            (!blacklisted(blacklist_ref, &name)).then_some(Test { name, info })
+            (!blacklisted(blacklist, &name)).then_some(Test {
+                name,
+                path: path.map(Path::to_path_buf),
+                info,
+            })
         })
-        .collect())
+        .collect()
+}
+
+/// Reads the schema version of every parsed test file under
+/// `parsed_tests_path`, and returns the files whose version does not match
+/// `PARSED_TEST_SCHEMA_VERSION` along with their version (`None` if it could
+/// not be read).
+pub(crate) fn find_schema_version_mismatches(
+    parsed_tests_path: &Path,
+) -> anyhow::Result<Vec<(PathBuf, Option<u32>)>> {
+    let mut test_files = Vec::new();
+    collect_test_files(parsed_tests_path, &mut test_files)?;
+    test_files.sort();
+
+    let mut mismatches = Vec::new();
+    for path in test_files {
+        let parsed_test_bytes = trace_io(IoOp::Read, &path, || std::fs::read(&path))
+            .with_context(|| format!("Reading {:?}", path))?;
+        let schema_version =
+            serde_cbor::from_slice::<ParsedTestManifestVersion>(&parsed_test_bytes)
+                .ok()
+                .map(|v| v.schema_version);
+
+        if schema_version != Some(PARSED_TEST_SCHEMA_VERSION) {
+            mismatches.push((path, schema_version));
+        }
+    }
+
+    Ok(mismatches)
+}
+
+fn collect_test_files(dir: &Path, test_files: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    let entries = trace_io(IoOp::ReadDir, dir, || std::fs::read_dir(dir))
+        .with_context(|| format!("Reading directory {:?}", dir))?;
+
+    for entry in entries {
+        let path = entry?.path();
+
+        if path.is_dir() {
+            collect_test_files(&path, test_files)?;
+        } else if path.extension().is_some_and(|ext| ext == "cbor") {
+            test_files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Errors if the test at `path` was written by a parser using a different
+/// `ParsedTestManifest` format than the one this runner expects.
+fn check_schema_version(path: &Path, parsed_test_bytes: &[u8]) -> anyhow::Result<()> {
+    let ParsedTestManifestVersion { schema_version } = serde_cbor::from_slice(parsed_test_bytes)
+        .with_context(|| format!("Reading the schema version of the test {:?}", path))?;
+
+    check_schema_version_matches(path, schema_version)
+}
+
+fn check_schema_version_matches(path: &Path, schema_version: u32) -> anyhow::Result<()> {
+    if schema_version != PARSED_TEST_SCHEMA_VERSION {
+        return Err(anyhow!(
+            "The test {:?} was parsed with schema version {}, but this runner expects version \
+             {}. The parsed tests were likely generated by a different version of \
+             `eth_test_parser`.\n\n\
+             To migrate, re-run the parser from the same checkout as the runner (the already \
+             fetched tests can be reused with `--no_fetch`):\n\n\
+             cd eth_test_parser\n\
+             cargo run -- --no_fetch",
+            path,
+            schema_version,
+            PARSED_TEST_SCHEMA_VERSION
+        ));
+    }
+
+    Ok(())
 }
 
 async fn wait_for_task_to_finish_and_push_to_vec<T: 'static>(
@@ -199,18 +593,6 @@ async fn wait_for_task_to_finish_and_push_to_vec<T: 'static>(
     wait_for_task_to_finish_and_apply_elem_to_vec(join_set, out_vec, |v, elem| v.push(elem)).await
 }
 
-async fn wait_for_task_to_finish_and_extend_vec<T: 'static>(
-    join_set: &mut JoinSet<anyhow::Result<Vec<T>>>,
-    out_vec: &mut Vec<T>,
-) -> anyhow::Result<()> {
-    wait_for_task_to_finish_and_apply_elem_to_vec(
-        join_set,
-        out_vec,
-        |v: &mut Vec<T>, elems: Vec<T>| v.extend(elems.into_iter()),
-    )
-    .await
-}
-
 async fn wait_for_task_to_finish_and_apply_elem_to_vec<
     T: 'static,
     U: 'static,
@@ -237,7 +619,7 @@ async fn parse_dir_init<T, U>(path: &Path) -> anyhow::Result<(Vec<T>, JoinSet<U>
     let output = Vec::new();
     let join_set = JoinSet::new();
     let read_dirs = ReadDirStream::new(
-        read_dir(path)
+        trace_io_async(IoOp::ReadDir, path, read_dir(path))
             .await
             .with_context(|| format!("Creating a directory stream for path {:?}", path))?,
     );
@@ -0,0 +1,75 @@
+//! Downloads an archive of parsed tests with `--network-fetch-tests`, for
+//! machines that can not clone the upstream tests and run the parser (eg. CI
+//! without git access).
+//!
+//! The archive is a `.tar.gz` of the parsed test directory (either of its
+//! `MAIN_TEST_DIR` or of the directory containing it). It must be served
+//! along with a `<URL>.sha256` file containing its SHA-256 (only the first
+//! word of the file is read, so the output of `sha256sum` works as is).
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use common::{config::MAIN_TEST_DIR, utils::sha256_hex};
+use flate2::read::GzDecoder;
+use tar::Archive;
+
+/// The directory (relative to the working directory) that fetched tests are
+/// extracted to. Replaced on every fetch.
+const FETCHED_TESTS_DIR: &str = "fetched_tests";
+
+/// Downloads and extracts the test archive at `url`, returning the path to
+/// read the parsed tests from.
+pub(crate) async fn fetch_test_archive(url: &str) -> anyhow::Result<PathBuf> {
+    println!("Downloading tests from {}...", url);
+
+    let expected_checksum = download(&format!("{}.sha256", url)).await?;
+    let expected_checksum = String::from_utf8_lossy(&expected_checksum)
+        .split_whitespace()
+        .next()
+        .ok_or_else(|| anyhow!("The checksum file of {} is empty", url))?
+        .to_lowercase();
+
+    let archive = download(url).await?;
+    let checksum = sha256_hex(&archive);
+    if checksum != expected_checksum {
+        return Err(anyhow!(
+            "The test archive at {} has a SHA-256 of {}, but {} was expected",
+            url,
+            checksum,
+            expected_checksum
+        ));
+    }
+
+    let out_dir = Path::new(FETCHED_TESTS_DIR);
+    if out_dir.exists() {
+        fs::remove_dir_all(out_dir)
+            .with_context(|| format!("Removing previously fetched tests in {:?}", out_dir))?;
+    }
+
+    println!("Extracting tests to {:?}...", out_dir);
+    Archive::new(GzDecoder::new(archive.as_slice()))
+        .unpack(out_dir)
+        .with_context(|| format!("Extracting the test archive from {}", url))?;
+
+    let main_test_dir = out_dir.join(MAIN_TEST_DIR);
+    Ok(match main_test_dir.exists() {
+        true => main_test_dir,
+        false => out_dir.to_path_buf(),
+    })
+}
+
+async fn download(url: &str) -> anyhow::Result<Vec<u8>> {
+    let bytes = reqwest::get(url)
+        .await
+        .and_then(|resp| resp.error_for_status())
+        .with_context(|| format!("Requesting {}", url))?
+        .bytes()
+        .await
+        .with_context(|| format!("Downloading {}", url))?;
+
+    Ok(bytes.to_vec())
+}
@@ -0,0 +1,235 @@
+//! Runs tests in child processes with `--test-isolation`. Each child proves a
+//! single test with the `run-single` subcommand and writes the result to
+//! `stdout` as JSON, so a test can not leave the prover in a bad state for the
+//! tests after it. This comes at the cost of starting a new process per test.
+
+use std::{
+    collections::HashMap,
+    env,
+    ffi::OsString,
+    fmt, fs,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context};
+
+use crate::{
+    io_trace::{trace_io, IoOp},
+    plonky2_runner::{TestOutcome, TestRunConfig, TestRunResult, TestStatus},
+};
+
+/// How often to check whether the child process has exited.
+const CHILD_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The contents of the parsed test files read in up front with
+/// `--preload-cbor`, which are handed to the child processes over `stdin`
+/// instead of each child reading its file while other tests are proving.
+#[derive(Clone, Default)]
+pub(crate) struct PreloadedCbor(Arc<HashMap<PathBuf, Vec<u8>>>);
+
+impl PreloadedCbor {
+    /// Reads in every file in `paths`.
+    pub(crate) fn read<'a>(paths: impl Iterator<Item = &'a Path>) -> anyhow::Result<Self> {
+        let mut files = HashMap::new();
+
+        for path in paths {
+            if files.contains_key(path) {
+                continue;
+            }
+
+            let bytes = trace_io(IoOp::Read, path, || fs::read(path))
+                .with_context(|| format!("Preloading the parsed test {:?}", path))?;
+            files.insert(path.to_path_buf(), bytes);
+        }
+
+        Ok(Self(Arc::new(files)))
+    }
+
+    pub(crate) fn num_bytes(&self) -> usize {
+        self.0.values().map(|bytes| bytes.len()).sum()
+    }
+
+    pub(crate) fn num_files(&self) -> usize {
+        self.0.len()
+    }
+}
+
+// Printing every preloaded file is not useful.
+impl fmt::Debug for PreloadedCbor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "PreloadedCbor({} files, {} bytes)",
+            self.num_files(),
+            self.num_bytes()
+        )
+    }
+}
+
+/// The arguments to the runner that prove the variant `variant_idx` of the
+/// test at `path` with `run-single`, the same way as `config` would.
+pub(crate) fn run_single_args(
+    path: &Path,
+    variant_idx: usize,
+    config: &TestRunConfig,
+) -> Vec<OsString> {
+    let mut args: Vec<OsString> = Vec::new();
+
+    if config.witness_only {
+        args.push("--witness-only".into());
+    }
+    if let Some(gas_limit) = config.gas_limit_override {
+        args.push("--gas-limit-override".into());
+        args.push(gas_limit.to_string().into());
+    }
+    if config.strict_gas_accounting {
+        args.push("--strict-gas-accounting".into());
+    }
+    if config.check_determinism {
+        args.push("--check-determinism".into());
+    }
+    if let Some(dir) = &config.merkle_proofs_dir {
+        args.push("--output-merkle-proofs".into());
+        args.push(dir.into());
+    }
+    if let Some(dir) = &config.public_values_dir {
+        args.push("--output-public-values".into());
+        args.push(dir.into());
+    }
+    if let Some(dir) = &config.proof_structure_dir {
+        args.push("--log-proof-structure".into());
+        args.push(dir.into());
+    }
+    if let Some(dir) = &config.proof_json_dir {
+        args.push("--output-proof-json".into());
+        args.push(dir.into());
+    }
+    if config.output_all_proofs {
+        args.push("--output-all-proofs".into());
+    }
+    if let Some(path) = &config.prover_config_file {
+        args.push("--prover-config-file".into());
+        args.push(path.into());
+    }
+    if config.test_version_check {
+        args.push("--test-version-check".into());
+    }
+    if config.output_proof_size {
+        args.push("--output-proof-size".into());
+    }
+    if config.output_receipt_root_diff {
+        args.push("--output-receipt-root-diff".into());
+    }
+    if config.mock_prove {
+        args.push("--mock-prove".into());
+    }
+    args.push("run-single".into());
+    args.push(path.into());
+    args.push("--variant".into());
+    args.push(variant_idx.to_string().into());
+
+    args
+}
+
+/// Runs the variant `variant_idx` of the test at `path` in a child process.
+/// The child is killed if it exceeds the test timeout. A child that crashes
+/// or does not report a result is treated like a panic in the prover.
+pub(crate) fn run_test_in_child_process(
+    path: &Path,
+    variant_idx: usize,
+    config: &TestRunConfig,
+) -> TestOutcome {
+    run_test_in_child_process_intern(path, variant_idx, config)
+        .unwrap_or_else(|err| TestStatus::Panicked(format!("{:#}", err)).into())
+}
+
+fn run_test_in_child_process_intern(
+    path: &Path,
+    variant_idx: usize,
+    config: &TestRunConfig,
+) -> anyhow::Result<TestOutcome> {
+    let preloaded_bytes = config
+        .preloaded_cbor
+        .as_ref()
+        .and_then(|preloaded| preloaded.0.get(path));
+
+    let mut cmd = Command::new(env::current_exe().with_context(|| "Getting the runner path")?);
+    cmd.args(run_single_args(path, variant_idx, config));
+    match preloaded_bytes {
+        Some(_) => cmd.arg("--stdin").stdin(Stdio::piped()),
+        None => cmd.stdin(Stdio::null()),
+    };
+
+    let mut child = cmd
+        .stdout(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Spawning a child process for the test {:?}", path))?;
+
+    // The child reads all of `stdin` before doing anything else, and dropping
+    // the pipe lets it know that the test is complete.
+    if let Some(bytes) = preloaded_bytes {
+        child
+            .stdin
+            .take()
+            .expect("Child stdin is piped")
+            .write_all(bytes)
+            .with_context(|| format!("Writing the test {:?} to the child process", path))?;
+    }
+
+    // Drained while polling, as a child that fills up the pipe would otherwise
+    // block until it is killed by the timeout.
+    let mut stdout = child.stdout.take().expect("Child stdout is piped");
+    let stdout_reader = thread::spawn(move || {
+        let mut output = String::new();
+        stdout.read_to_string(&mut output).map(|_| output)
+    });
+
+    let start = Instant::now();
+    let exit_status = loop {
+        if let Some(status) = child.try_wait()? {
+            break Some(status);
+        }
+
+        if let Some(test_timeout) = config.test_timeout
+            && start.elapsed() > test_timeout
+        {
+            child.kill()?;
+            child.wait()?;
+            break None;
+        }
+
+        thread::sleep(CHILD_POLL_INTERVAL);
+    };
+
+    // The child has exited, so the reader reaches the end of its output.
+    let output = stdout_reader
+        .join()
+        .map_err(|_| anyhow!("The thread reading the output of the child process panicked"))??;
+
+    let Some(exit_status) = exit_status else {
+        return Ok(TestStatus::TimedOut.into());
+    };
+
+    if !exit_status.success() {
+        return Ok(
+            TestStatus::Panicked(format!("Test process exited with {}", exit_status)).into(),
+        );
+    }
+
+    let t_res: TestRunResult = serde_json::from_str(output.trim()).with_context(|| {
+        format!(
+            "Reading the test result from the child process {:?}",
+            output
+        )
+    })?;
+
+    Ok(TestOutcome {
+        status: t_res.status,
+        proof_size_bytes: t_res.proof_size_bytes,
+    })
+}
@@ -0,0 +1,155 @@
+//! Summarises the characteristics of the parsed tests (transaction types, gas
+//! used and pre-state sizes) without running them, to understand what the
+//! test corpus covers.
+
+use std::collections::BTreeMap;
+
+use mpt_trie::{partial_trie::PartialTrie, trie_ops::ValOrHash};
+use regex::Regex;
+use serde::Serialize;
+
+use crate::{
+    arg_parsing::TxnType,
+    test_dir_reading::{ParsedTestGroup, Test},
+};
+
+/// The number of most common pre-state account counts to list.
+const NUM_TOP_ACCOUNT_COUNTS_TO_REPORT: usize = 10;
+
+/// Upper bounds (exclusive) of the gas used ranges, along with their labels.
+const GAS_USED_RANGES: [(u64, &str); 4] = [
+    (25_000, "< 25k"),
+    (100_000, "25k - 100k"),
+    (1_000_000, "100k - 1M"),
+    (10_000_000, "1M - 10M"),
+];
+const GAS_USED_ABOVE_RANGES_LABEL: &str = ">= 10M";
+
+#[derive(Debug, Default, Serialize)]
+struct TestMetadataReport {
+    num_tests: usize,
+    txn_types: BTreeMap<&'static str, usize>,
+    gas_used_ranges: BTreeMap<&'static str, usize>,
+    /// The number of tests per number of accounts in the pre-state.
+    pre_state_account_counts: BTreeMap<usize, usize>,
+}
+
+impl TestMetadataReport {
+    fn new<'a>(tests: impl Iterator<Item = &'a Test>) -> Self {
+        let mut report = Self::default();
+
+        for test in tests {
+            let inputs = &test.info.gen_inputs;
+
+            report.num_tests += 1;
+            *report
+                .txn_types
+                .entry(txn_type(inputs.signed_txn.as_deref()))
+                .or_default() += 1;
+            *report
+                .gas_used_ranges
+                .entry(gas_used_range(inputs.gas_used_after.low_u64()))
+                .or_default() += 1;
+
+            let num_accounts = inputs
+                .tries
+                .state_trie
+                .items()
+                .filter(|(_, v)| matches!(v, ValOrHash::Val(_)))
+                .count();
+            *report
+                .pre_state_account_counts
+                .entry(num_accounts)
+                .or_default() += 1;
+        }
+
+        report
+    }
+
+    fn print(&self) {
+        println!("Tests: {}", self.num_tests);
+
+        println!("\nTransaction types:");
+        for (txn_type, count) in self.txn_types.iter() {
+            println!("{:>12}: {}", txn_type, count);
+        }
+
+        println!("\nGas used:");
+        for (_, label) in GAS_USED_RANGES {
+            print_gas_used_range(label, self.gas_used_ranges.get(label));
+        }
+        print_gas_used_range(
+            GAS_USED_ABOVE_RANGES_LABEL,
+            self.gas_used_ranges.get(GAS_USED_ABOVE_RANGES_LABEL),
+        );
+
+        let mut account_counts: Vec<_> = self.pre_state_account_counts.iter().collect();
+        account_counts.sort_by(|(_, a), (_, b)| b.cmp(a));
+
+        println!("\nMost common pre-state account counts:");
+        for (num_accounts, count) in account_counts
+            .into_iter()
+            .take(NUM_TOP_ACCOUNT_COUNTS_TO_REPORT)
+        {
+            let label = format!("{} accounts", num_accounts);
+            println!("{:>12}: {} tests", label, count);
+        }
+    }
+}
+
+fn print_gas_used_range(label: &str, count: Option<&usize>) {
+    println!("{:>12}: {}", label, count.copied().unwrap_or(0));
+}
+
+/// Typed transactions (EIP-2718) start with their type byte, while legacy
+/// transactions are an RLP list (which always starts with a byte >= `0xc0`).
+pub(crate) fn txn_type(signed_txn: Option<&[u8]>) -> &'static str {
+    match signed_txn.and_then(|txn| txn.first()) {
+        None => "none",
+        Some(0x01) => "access list",
+        Some(0x02) => "EIP-1559",
+        Some(0x03) => "blob",
+        Some(b) if *b >= 0xc0 => "legacy",
+        Some(_) => "unknown",
+    }
+}
+
+/// Whether `signed_txn` is a transaction of type `ty`.
+pub(crate) fn is_txn_type(signed_txn: Option<&[u8]>, ty: TxnType) -> bool {
+    match (signed_txn.and_then(|txn| txn.first()), ty) {
+        (Some(b), TxnType::Legacy) => *b >= 0xc0,
+        (Some(0x01), TxnType::Eip2930)
+        | (Some(0x02), TxnType::Eip1559)
+        | (Some(0x03), TxnType::Eip4844) => true,
+        _ => false,
+    }
+}
+
+fn gas_used_range(gas_used: u64) -> &'static str {
+    GAS_USED_RANGES
+        .iter()
+        .find(|(upper_bound, _)| gas_used < *upper_bound)
+        .map_or(GAS_USED_ABOVE_RANGES_LABEL, |(_, label)| label)
+}
+
+/// Prints statistics on the parsed tests whose name matches `filter` (if any),
+/// either as text or as JSON.
+pub(crate) fn output_test_metadata_report(
+    parsed_tests: &[ParsedTestGroup],
+    filter: Option<&Regex>,
+    json: bool,
+) -> anyhow::Result<()> {
+    let tests = parsed_tests
+        .iter()
+        .flat_map(|g| g.sub_groups.iter())
+        .flat_map(|sub_g| sub_g.tests.iter())
+        .filter(|t| filter.map_or(true, |f| f.is_match(&t.name)));
+    let report = TestMetadataReport::new(tests);
+
+    match json {
+        true => println!("{}", serde_json::to_string_pretty(&report)?),
+        false => report.print(),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,29 @@
+//! Writes out the tests that a run is about to execute, in the order that they
+//! will be run. Comparing this against the results of a run shows any tests
+//! that were planned but never ran (eg. because the run crashed).
+
+use std::{fs, path::Path};
+
+use anyhow::Context;
+
+use crate::io_trace::{trace_io, IoOp};
+
+/// Writes the names of all tests that are going to run to `path` as a JSON
+/// array, in execution order.
+pub(crate) fn write_test_plan<'a>(
+    path: &Path,
+    t_names: impl Iterator<Item = &'a str>,
+) -> anyhow::Result<()> {
+    let t_names: Vec<&str> = t_names.collect();
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Creating test plan directory {:?}", parent))?;
+    }
+
+    let file = trace_io(IoOp::Create, path, || fs::File::create(path))
+        .with_context(|| format!("Creating test plan file {:?}", path))?;
+    serde_json::to_writer_pretty(file, &t_names).with_context(|| "Serializing test plan")?;
+
+    Ok(())
+}
@@ -0,0 +1,129 @@
+//! Keeps a history of how long each test took to run across runs (and prover
+//! versions), so that performance regressions in the prover can be spotted
+//! before they become critical.
+//!
+//! The history is stored as NDJSON, with one line per test per run, so that a
+//! run only ever has to append to it.
+
+use std::{
+    fs::{self, OpenOptions},
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    io_trace::{trace_io, IoOp},
+    plonky2_runner::TestGroupRunResults,
+    version_info::PLONKY2_VERSION,
+};
+
+const TIMING_HISTORY_PATH_STR: &str = "timing_history.jsonl";
+
+/// The width (in characters) of the longest bar in the timing trend chart.
+const MAX_BAR_WIDTH: usize = 50;
+
+#[derive(Debug, Deserialize, Serialize)]
+struct TimingEntry {
+    /// `<group>/<sub-group>/<test name>`, as variants in different groups can
+    /// share a name. Entries written before this only have the test name.
+    #[serde(alias = "test_name")]
+    test_key: String,
+    timestamp: DateTime<Utc>,
+    plonky2_version: String,
+    elapsed_ms: u64,
+}
+
+/// Appends the duration of every test that ran to the timing history.
+pub(crate) fn append_to_timing_history(res: &[TestGroupRunResults]) -> anyhow::Result<()> {
+    println!("Appending test timings to {}...", TIMING_HISTORY_PATH_STR);
+
+    let file = trace_io(IoOp::Open, Path::new(TIMING_HISTORY_PATH_STR), || {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(TIMING_HISTORY_PATH_STR)
+    })
+    .with_context(|| format!("Opening timing history {}", TIMING_HISTORY_PATH_STR))?;
+    let mut writer = BufWriter::new(file);
+
+    let timestamp = Utc::now();
+    for (g, sub_g, test) in res.iter().flat_map(|g| {
+        g.sub_group_res
+            .iter()
+            .flat_map(move |sub_g| sub_g.test_res.iter().map(move |t| (g, sub_g, t)))
+    }) {
+        let Some(duration) = test.duration else {
+            continue;
+        };
+
+        let entry = TimingEntry {
+            test_key: timing_key(&g.name, &sub_g.name, &test.name),
+            timestamp,
+            plonky2_version: PLONKY2_VERSION.to_string(),
+            elapsed_ms: duration.as_millis() as u64,
+        };
+        serde_json::to_writer(&mut writer, &entry).with_context(|| "Serializing timing entry")?;
+        writeln!(writer)?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+fn timing_key(group_name: &str, sub_group_name: &str, test_name: &str) -> String {
+    format!("{}/{}/{}", group_name, sub_group_name, test_name)
+}
+
+/// Prints a bar chart of how long the test at `test_key` (ie.
+/// `<group>/<sub-group>/<test name>`) took in each recorded run, oldest first.
+pub(crate) fn show_timing_trends(test_key: &str) -> anyhow::Result<()> {
+    let path = Path::new(TIMING_HISTORY_PATH_STR);
+    if !path.exists() {
+        println!("No timing history found. Run the tests with `--persist-timing-history` first.");
+        return Ok(());
+    }
+
+    let history = trace_io(IoOp::Read, path, || fs::read_to_string(path))
+        .with_context(|| format!("Reading timing history {:?}", path))?;
+
+    let mut entries = Vec::new();
+    for line in history.lines().filter(|l| !l.trim().is_empty()) {
+        let entry: TimingEntry = serde_json::from_str(line)
+            .with_context(|| format!("Parsing timing history entry {:?}", line))?;
+
+        if entry.test_key == test_key {
+            entries.push(entry);
+        }
+    }
+
+    if entries.is_empty() {
+        println!("No timings recorded for {}.", test_key);
+        return Ok(());
+    }
+
+    entries.sort_by_key(|e| e.timestamp);
+    let max_elapsed_ms = entries.iter().map(|e| e.elapsed_ms).max().unwrap_or(0);
+
+    println!("Timing history for {}:", test_key);
+    for entry in entries {
+        let bar_width = match max_elapsed_ms {
+            0 => 0,
+            max => (entry.elapsed_ms as usize * MAX_BAR_WIDTH) / max as usize,
+        };
+
+        println!(
+            "{}  plonky2 {:<8} {:<width$} {} ms",
+            entry.timestamp.format("%Y-%m-%d %H:%M"),
+            entry.plonky2_version,
+            "#".repeat(bar_width),
+            entry.elapsed_ms,
+            width = MAX_BAR_WIDTH
+        );
+    }
+
+    Ok(())
+}
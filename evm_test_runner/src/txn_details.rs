@@ -0,0 +1,177 @@
+//! Decodes the signed transaction of a test to print its fields with
+//! `--output-transaction-details`, before the test is run.
+
+use std::fmt::Write;
+
+use anyhow::{anyhow, Context};
+use ethereum_types::{Address, U256};
+use keccak_hash::keccak;
+use rlp::{Rlp, RlpStream};
+use secp256k1::{
+    ecdsa::{RecoverableSignature, RecoveryId},
+    Message, Secp256k1,
+};
+
+use crate::test_metadata_report::txn_type;
+
+/// The positions of the fields of a transaction in its RLP list, which depend
+/// on its type.
+struct TxnLayout {
+    nonce: usize,
+    gas_limit: usize,
+    to: usize,
+    value: usize,
+    data: usize,
+}
+
+const LEGACY_TXN_LAYOUT: TxnLayout = TxnLayout {
+    nonce: 0,
+    gas_limit: 2,
+    to: 3,
+    value: 4,
+    data: 5,
+};
+
+const ACCESS_LIST_TXN_LAYOUT: TxnLayout = TxnLayout {
+    nonce: 1,
+    gas_limit: 3,
+    to: 4,
+    value: 5,
+    data: 6,
+};
+
+/// Shared by EIP-1559 and blob transactions, which only add fields after
+/// `data`.
+const FEE_MARKET_TXN_LAYOUT: TxnLayout = TxnLayout {
+    nonce: 1,
+    gas_limit: 4,
+    to: 5,
+    value: 6,
+    data: 7,
+};
+
+#[derive(Debug)]
+struct TxnDetails {
+    from: Address,
+    /// `None` for contract creations.
+    to: Option<Address>,
+    nonce: U256,
+    gas_limit: U256,
+    value: U256,
+    data: Vec<u8>,
+}
+
+/// Formats the fields of `signed_txn` under a header with the test name.
+pub(crate) fn format_txn_details(test_name: &str, signed_txn: Option<&[u8]>) -> String {
+    let mut out = format!("=== {} ===\n", test_name);
+
+    let Some(signed_txn) = signed_txn else {
+        out.push_str("  (no transaction)");
+        return out;
+    };
+
+    let details = match decode_txn(signed_txn) {
+        Ok(details) => details,
+        Err(err) => {
+            let _ = write!(out, "  Unable to decode the transaction: {:#}", err);
+            return out;
+        }
+    };
+
+    let to = details.to.map_or_else(
+        || "(contract creation)".to_string(),
+        |to| format!("{:?}", to),
+    );
+
+    let _ = writeln!(out, "  type:  {}", txn_type(Some(signed_txn)));
+    let _ = writeln!(out, "  from:  {:?}", details.from);
+    let _ = writeln!(out, "  to:    {}", to);
+    let _ = writeln!(out, "  nonce: {}", details.nonce);
+    let _ = writeln!(out, "  gas:   {}", details.gas_limit);
+    let _ = writeln!(out, "  value: {}", details.value);
+    let _ = write!(out, "  data:  0x{}", hex::encode(&details.data));
+
+    out
+}
+
+fn decode_txn(signed_txn: &[u8]) -> anyhow::Result<TxnDetails> {
+    let (txn_type, payload, layout) = match signed_txn.first() {
+        Some(&b) if b >= 0xc0 => (None, signed_txn, LEGACY_TXN_LAYOUT),
+        Some(0x01) => (Some(0x01), &signed_txn[1..], ACCESS_LIST_TXN_LAYOUT),
+        Some(&b @ (0x02 | 0x03)) => (Some(b), &signed_txn[1..], FEE_MARKET_TXN_LAYOUT),
+        Some(b) => return Err(anyhow!("Unknown transaction type {:#04x}", b)),
+        None => return Err(anyhow!("Empty transaction")),
+    };
+
+    let rlp = Rlp::new(payload);
+    let to = match rlp.at(layout.to)?.data()? {
+        [] => None,
+        to if to.len() == Address::len_bytes() => Some(Address::from_slice(to)),
+        to => return Err(anyhow!("Invalid recipient 0x{}", hex::encode(to))),
+    };
+
+    Ok(TxnDetails {
+        from: recover_sender(&rlp, txn_type).with_context(|| "Recovering the sender")?,
+        to,
+        nonce: rlp.val_at(layout.nonce)?,
+        gas_limit: rlp.val_at(layout.gas_limit)?,
+        value: rlp.val_at(layout.value)?,
+        data: rlp.at(layout.data)?.data()?.to_vec(),
+    })
+}
+
+/// Recovers the sender from the signature, which is always made of the last
+/// three fields of the transaction.
+fn recover_sender(rlp: &Rlp, txn_type: Option<u8>) -> anyhow::Result<Address> {
+    let num_fields = rlp.item_count()?;
+    if num_fields < 3 {
+        return Err(anyhow!(
+            "Expected a signature, but only got {} fields",
+            num_fields
+        ));
+    }
+
+    let num_unsigned_fields = num_fields - 3;
+    let v: u64 = rlp.val_at(num_fields - 3)?;
+    let r: U256 = rlp.val_at(num_fields - 2)?;
+    let s: U256 = rlp.val_at(num_fields - 1)?;
+
+    // The unsigned transaction is the same list without the signature, with
+    // the chain ID (and two empty fields) appended for EIP-155 legacy
+    // transactions.
+    let (chain_id, rec_id) = match txn_type {
+        Some(_) => (None, v),
+        None if v >= 35 => (Some((v - 35) / 2), (v - 35) % 2),
+        None => (
+            None,
+            v.checked_sub(27)
+                .ok_or_else(|| anyhow!("Invalid signature v {}", v))?,
+        ),
+    };
+
+    let mut stream = RlpStream::new_list(num_unsigned_fields + 3 * chain_id.is_some() as usize);
+    for i in 0..num_unsigned_fields {
+        stream.append_raw(rlp.at(i)?.as_raw(), 1);
+    }
+    if let Some(chain_id) = chain_id {
+        stream
+            .append(&chain_id)
+            .append_empty_data()
+            .append_empty_data();
+    }
+
+    let mut unsigned_txn = txn_type.map(|t| vec![t]).unwrap_or_default();
+    unsigned_txn.extend_from_slice(&stream.out());
+
+    let mut sig = [0; 64];
+    r.to_big_endian(&mut sig[..32]);
+    s.to_big_endian(&mut sig[32..]);
+
+    let sig = RecoverableSignature::from_compact(&sig, RecoveryId::from_i32(rec_id as i32)?)?;
+    let msg = Message::from_digest(keccak(&unsigned_txn).0);
+    let pub_key = Secp256k1::verification_only()
+        .recover_ecdsa(&msg, &sig)?
+        .serialize_uncompressed();
+
+    Ok(Address::from_slice(&keccak(&pub_key[1..]).0[12..]))
+}
@@ -0,0 +1,43 @@
+//! Version information about the runner and the prover it was built against,
+//! for including in bug reports.
+
+use serde::Serialize;
+
+/// The version of `plonky2` that the runner is built against. Keep in sync
+/// with the workspace `Cargo.toml`.
+pub(crate) const PLONKY2_VERSION: &str = "0.2.2";
+
+/// The version of `evm_arithmetization` that the runner is built against.
+/// Keep in sync with the workspace `Cargo.toml`.
+const EVM_ARITHMETIZATION_VERSION: &str = "0.2.0";
+
+#[derive(Debug, Serialize)]
+pub(crate) struct VersionInfo {
+    evm_test_runner: &'static str,
+    plonky2: &'static str,
+    evm_arithmetization: &'static str,
+    rustc: &'static str,
+    git_commit: &'static str,
+}
+
+impl VersionInfo {
+    pub(crate) fn get() -> Self {
+        Self {
+            evm_test_runner: env!("CARGO_PKG_VERSION"),
+            plonky2: PLONKY2_VERSION,
+            evm_arithmetization: EVM_ARITHMETIZATION_VERSION,
+            rustc: env!("RUSTC_VERSION"),
+            git_commit: env!("GIT_COMMIT_HASH"),
+        }
+    }
+}
+
+pub(crate) fn print_version_info() {
+    let info = VersionInfo::get();
+
+    println!("evm_test_runner: {}", info.evm_test_runner);
+    println!("plonky2: {}", info.plonky2);
+    println!("evm_arithmetization: {}", info.evm_arithmetization);
+    println!("rustc: {}", info.rustc);
+    println!("git commit: {}", info.git_commit);
+}
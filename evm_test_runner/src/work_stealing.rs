@@ -0,0 +1,109 @@
+//! A small work-stealing thread pool used to run tests in parallel.
+//!
+//! All tasks start in a global queue. Each worker takes batches of tasks from
+//! it into its own local queue, and once the global queue is empty, idle
+//! workers steal from the local queues of the others. This keeps every worker
+//! busy even when task durations vary a lot, which is very much the case for
+//! proof generation.
+
+use std::{
+    iter,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crossbeam_deque::{Injector, Stealer, Worker};
+
+#[derive(Debug)]
+pub(crate) struct WorkStealingPool<R> {
+    results: Receiver<R>,
+    stop: Arc<AtomicBool>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl<R: Send + 'static> WorkStealingPool<R> {
+    /// Spawns `worker_count` threads that run `run_task` on every task and
+    /// immediately starts processing `tasks`.
+    pub(crate) fn spawn<T, F>(tasks: Vec<T>, worker_count: usize, run_task: F) -> Self
+    where
+        T: Send + 'static,
+        F: Fn(T) -> R + Send + Sync + 'static,
+    {
+        let global = Arc::new(Injector::new());
+        for task in tasks {
+            global.push(task);
+        }
+
+        let locals: Vec<_> = (0..worker_count).map(|_| Worker::new_fifo()).collect();
+        let stealers: Arc<Vec<_>> = Arc::new(locals.iter().map(|w| w.stealer()).collect());
+        let stop = Arc::new(AtomicBool::new(false));
+        let run_task = Arc::new(run_task);
+        let (send, results) = mpsc::channel();
+
+        let mut workers = Vec::with_capacity(worker_count);
+        for local in locals {
+            let global = global.clone();
+            let stealers = stealers.clone();
+            let stop = stop.clone();
+            let run_task = run_task.clone();
+            let send = send.clone();
+
+            workers.push(thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let Some(task) = find_task(&local, &global, &stealers) else {
+                        break;
+                    };
+
+                    // The pool was dropped, so no one cares about the result anymore.
+                    if send.send(run_task(task)).is_err() {
+                        break;
+                    }
+                }
+            }));
+        }
+
+        Self {
+            results,
+            stop,
+            workers,
+        }
+    }
+
+    /// Waits up to `timeout` for the next result. Fails with
+    /// `RecvTimeoutError::Disconnected` once every task has completed.
+    pub(crate) fn next_result(&self, timeout: Duration) -> Result<R, RecvTimeoutError> {
+        self.results.recv_timeout(timeout)
+    }
+}
+
+impl<R> Drop for WorkStealingPool<R> {
+    fn drop(&mut self) {
+        // Workers finish their current task, but do not pick up any new ones.
+        // Waiting for them makes sure that no test is still running (and
+        // writing its outputs) once the pool is gone.
+        self.stop.store(true, Ordering::Relaxed);
+
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Gets the next task for a worker, first from its own queue, then from the
+/// global queue and finally by stealing from the other workers.
+fn find_task<T>(local: &Worker<T>, global: &Injector<T>, stealers: &[Stealer<T>]) -> Option<T> {
+    local.pop().or_else(|| {
+        iter::repeat_with(|| {
+            global
+                .steal_batch_and_pop(local)
+                .or_else(|| stealers.iter().map(|s| s.steal()).collect())
+        })
+        .find(|s| !s.is_retry())
+        .and_then(|s| s.success())
+    })
+}